@@ -149,24 +149,31 @@ const BRAINFUCK_CODE: &str =
     +[-[->>>>>>>>>+<<<<<<<<<]>>>>>>>>>]>>>>>->>>>>>>>>>>>>>>>>>>>>>>>>>>-<<<<<<[<<<<
     <<<<<]]>>>]";
 
-fn interpret() {
+#[cfg_attr(not(feature = "jit"), allow(unused_variables))]
+fn interpret(jit: bool) {
     let memory_config = MemoryConfig {
         len: 32768,
         addr: Addr::Unsigned,
         cell: Cell::I8,
         overflow: Overflow::Wrap,
         eof: Eof::Ignore,
+        storage: Storage::Dense,
+        init: None,
     };
     let stream_config = StreamConfig {
         input: Input::Null,
         output: Output::Null,
     };
     let mut interpreter = Interpreter::new(memory_config, stream_config);
+    #[cfg(feature = "jit")]
+    interpreter.set_jit(jit);
     interpreter.run(BRAINFUCK_CODE).unwrap();
 }
 
 fn benchmark(c: &mut Criterion) {
-    c.bench_function("mandelbrot", |b| b.iter(interpret));
+    c.bench_function("mandelbrot", |b| b.iter(|| interpret(false)));
+    #[cfg(feature = "jit")]
+    c.bench_function("mandelbrot (jit)", |b| b.iter(|| interpret(true)));
 }
 
 criterion_group! {
@@ -1,6 +1,11 @@
-use common::compiler::{Compiler, ParseError};
+use common::bytecode::{BytecodeError, Program};
+use common::codegen::{self, CodegenError};
+use common::compiler::{Compiler, Disassembler, InstructionList, ParseError, Report, Span};
 use common::execution::context::Context;
+#[cfg(feature = "jit")]
+use common::execution::jit::Jit;
 use common::execution::memory::config::Config as MemoryConfig;
+use common::execution::memory::{Memory, MemoryError};
 use common::execution::processor::{Processor, ProcessorError};
 use common::execution::stream::config::Config as StreamConfig;
 
@@ -10,22 +15,145 @@ type Result<T> = std::result::Result<T, InterpreterError>;
 
 pub struct Interpreter {
     context: Context,
+    #[cfg(feature = "jit")]
+    jit: bool,
 }
 
 impl Interpreter {
     pub fn new(memory_config: MemoryConfig, stream_config: StreamConfig) -> Self {
         Self {
             context: Context::new(memory_config, stream_config),
+            #[cfg(feature = "jit")]
+            jit: true,
         }
     }
 
+    /// Enable or disable the JIT compiler, which is enabled by default.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, jit: bool) {
+        self.jit = jit;
+    }
+
     pub fn run(&mut self, code: &str) -> Result<()> {
-        let compiler = Compiler::new();
-        let instructions = compiler.compile(code)?;
-        let mut processor = Processor::new(instructions);
+        let compiler = self.compiler();
+        let (instructions, spans) = compiler.compile_with_spans(code)?;
+        self.execute(instructions)
+            .map_err(|e| locate(e, code, &spans))
+    }
+
+    /// Run a program which has been compiled already.
+    pub fn execute(&mut self, instructions: InstructionList) -> Result<()> {
+        #[cfg(feature = "jit")]
+        if self.jit {
+            Jit::new(instructions)?.run(&mut self.context)?;
+            return Ok(());
+        }
+
+        let mut processor = Processor::new(instructions)?;
         processor.run(&mut self.context)?;
         Ok(())
     }
+
+    /// Get the memory, which keeps the cells and the pointer after running
+    /// until the next run, even if the program failed.
+    pub fn memory(&self) -> &Memory {
+        &self.context.memory
+    }
+
+    fn compiler(&self) -> Compiler {
+        compiler(self.context.memory.config().unwrap())
+    }
+
+    /// Compile the code without running it and report what the optimizer did,
+    /// so the instructions can be run by `execute` afterwards.
+    pub fn compile_with_report(&self, code: &str) -> Result<(InstructionList, Report)> {
+        let compiler = self.compiler();
+        Ok(compiler.compile_with_report(code)?)
+    }
+}
+
+/// The languages the program can be translated into instead of running it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Target {
+    C,
+    /// A Rust program with a `main` function.
+    Rust,
+    /// A Rust module exposing a `run` function.
+    RustModule,
+    /// A WebAssembly text format module.
+    Wat,
+    /// x86-64 assembly for the GNU assembler on Linux.
+    Asm,
+    /// The compiled instructions in a human-readable form.
+    Ir,
+    /// Brainfuck with the comments removed, which compiles into the same
+    /// instructions.
+    Brainfuck,
+}
+
+/// Create a compiler for the programs running with the memory config, which
+/// only assumes the memory is zero when the program starts if it isn't
+/// preloaded.
+fn compiler(memory_config: &MemoryConfig) -> Compiler {
+    match memory_config.init {
+        Some(_) => Compiler::new(),
+        None => Compiler::pristine(),
+    }
+}
+
+/// Point the input reading EOF at its `,` in the source code, given the span
+/// each instruction comes from.
+fn locate(e: InterpreterError, code: &str, spans: &[Option<Span>]) -> InterpreterError {
+    match e {
+        InterpreterError::Runtime {
+            source: ProcessorError::UnexpectedEof { addr },
+        } => match spans.get(addr).copied().flatten() {
+            Some(span) => {
+                let (line, column) = span.location(code);
+                InterpreterError::UnexpectedEof { line, column }
+            }
+            None => ProcessorError::UnexpectedEof { addr }.into(),
+        },
+        e => e,
+    }
+}
+
+/// Compile the code into bytecode which runs with the memory config.
+pub fn compile(code: &str, memory_config: MemoryConfig) -> Result<Vec<u8>> {
+    memory_config.check()?;
+    let instructions = compiler(&memory_config).compile(code)?;
+    Ok(Program::new(memory_config, instructions).encode())
+}
+
+/// Decode the bytecode into the memory config and the instructions of the
+/// program.
+pub fn load(bytes: &[u8]) -> Result<(MemoryConfig, InstructionList)> {
+    let program = Program::decode(bytes)?;
+    Ok((program.config, program.instructions))
+}
+
+/// Compile the code and translate it into the target language.
+pub fn emit(code: &str, target: Target, memory_config: MemoryConfig) -> Result<String> {
+    let compiler = compiler(&memory_config);
+    let output = match target {
+        Target::C => codegen::c::Emitter::new(memory_config).emit(&compiler.compile(code)?)?,
+        Target::Rust => {
+            let emitter = codegen::rust::Emitter::new(memory_config, codegen::rust::Form::Program);
+            emitter.emit(&compiler.parse(code)?)?
+        }
+        Target::RustModule => {
+            let emitter = codegen::rust::Emitter::new(memory_config, codegen::rust::Form::Module);
+            emitter.emit(&compiler.parse(code)?)?
+        }
+        Target::Wat => codegen::wat::Emitter::new(memory_config).emit(&compiler.compile(code)?)?,
+        Target::Asm => codegen::asm::Emitter::new(memory_config).emit(&compiler.compile(code)?)?,
+        Target::Ir => {
+            let (instructions, spans) = compiler.compile_with_spans(code)?;
+            Disassembler::with_source(code, &spans).disassemble(&instructions)
+        }
+        Target::Brainfuck => codegen::brainfuck::Emitter::new().emit(&compiler.parse(code)?)?,
+    };
+    Ok(output)
 }
 
 #[derive(Snafu, Debug, PartialEq, Eq)]
@@ -34,8 +162,16 @@ pub enum InterpreterError {
     Parse { source: ParseError },
     #[snafu(display("an error occurred when running the code"))]
     Runtime { source: ProcessorError },
+    #[snafu(display("couldn't generate the code"))]
+    Codegen { source: CodegenError },
+    #[snafu(display("couldn't load the bytecode"))]
+    Bytecode { source: BytecodeError },
+    #[snafu(display("couldn't build the memory from the config"))]
+    Init { source: MemoryError },
     #[snafu(display("the program hasn't been loaded yet"))]
     Uninitialized,
+    #[snafu(display("the input at {line}:{column} reads EOF"))]
+    UnexpectedEof { line: usize, column: usize },
 }
 
 impl From<ParseError> for InterpreterError {
@@ -49,3 +185,47 @@ impl From<ProcessorError> for InterpreterError {
         Self::Runtime { source: e }
     }
 }
+
+impl From<CodegenError> for InterpreterError {
+    fn from(e: CodegenError) -> Self {
+        Self::Codegen { source: e }
+    }
+}
+
+impl From<MemoryError> for InterpreterError {
+    fn from(e: MemoryError) -> Self {
+        Self::Init { source: e }
+    }
+}
+
+impl From<BytecodeError> for InterpreterError {
+    fn from(e: BytecodeError) -> Self {
+        Self::Bytecode { source: e }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use common::execution::memory::config::Eof;
+    use common::execution::stream::config::{Input, Output};
+
+    use super::*;
+
+    #[test]
+    fn unexpected_eof() {
+        let memory_config = MemoryConfig {
+            eof: Eof::Error,
+            ..Default::default()
+        };
+        let stream_config = StreamConfig {
+            input: Input::Vec(Rc::new(RefCell::new(VecDeque::from([1])))),
+            output: Output::Vec(Rc::new(RefCell::new(VecDeque::new()))),
+        };
+        let mut interpreter = Interpreter::new(memory_config, stream_config);
+
+        let err = interpreter.run("+[\n  -, ,]").unwrap_err();
+        assert_eq!(err.to_string(), "the input at 2:6 reads EOF");
+    }
+}
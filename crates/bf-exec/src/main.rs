@@ -9,21 +9,116 @@
 //!
 
 use std::error::Error;
-use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 
-use bf_exec::Interpreter;
-use clap::{builder::PathBufValueParser, command, value_parser, Arg, ArgMatches};
+use bf_exec::{Interpreter, InterpreterError, Target};
+use clap::{
+    builder::PathBufValueParser, command, value_parser, Arg, ArgAction, ArgMatches, Command,
+};
+use common::compiler::Report;
 use common::execution::memory::config::{self as memory_config, Config as MemoryConfig};
+use common::execution::memory::dump::Format as DumpFormat;
 use common::execution::stream::config::{self as stream_config, Config as StreamConfig};
 
 fn main() {
     let matches = input();
-    let (memory_config, stream_config, path) = parse(&matches);
 
-    let code = match std::fs::read_to_string(path) {
-        Ok(code) => code,
+    let result = match matches.subcommand() {
+        Some(("compile", matches)) => compile(matches),
+        Some(("run", matches)) => run_bytecode(matches),
+        _ => run_source(&matches),
+    };
+
+    if let Err(e) = result {
+        print_error(e);
+        process::exit(1);
+    }
+}
+
+fn run_source(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let memory_config = parse_memory_config(matches);
+    memory_config.check().map_err(InterpreterError::from)?;
+    let stream_config = parse_stream_config(matches);
+    let opt_report = matches.get_flag("OPT_REPORT");
+    let target = matches
+        .get_one::<String>("EMIT")
+        .map(|target| match target.as_str() {
+            "c" => Target::C,
+            "rust" => Target::Rust,
+            "rust-module" => Target::RustModule,
+            "wat" => Target::Wat,
+            "asm" => Target::Asm,
+            "ir" => Target::Ir,
+            "bf" => Target::Brainfuck,
+            _ => unreachable!(),
+        });
+    let path = matches.get_one::<PathBuf>("SOURCE").unwrap();
+    let code = read(path, |path| std::fs::read_to_string(path));
+
+    match target {
+        Some(target) => emit(memory_config, code, target),
+        None => run(
+            memory_config,
+            stream_config,
+            code,
+            opt_report,
+            use_jit(matches),
+            parse_dump(matches),
+        ),
+    }
+}
+
+fn compile(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let memory_config = parse_memory_config(matches);
+    let path = matches.get_one::<PathBuf>("SOURCE").unwrap();
+    let code = read(path, |path| std::fs::read_to_string(path));
+    let out = match matches.get_one::<PathBuf>("OUT") {
+        Some(out) => out.clone(),
+        None => path.with_extension("bfc"),
+    };
+
+    let bytecode = bf_exec::compile(&code, memory_config)?;
+
+    if let Err(e) = std::fs::write(&out, bytecode) {
+        eprintln!("error: couldn't write {}", out.display());
+        eprintln!("caused by: {e}");
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn run_bytecode(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let stream_config = parse_stream_config(matches);
+    let path = matches.get_one::<PathBuf>("SOURCE").unwrap();
+    let (memory_config, instructions) = bf_exec::load(&read(path, |path| std::fs::read(path)))?;
+
+    let mut interpreter = Interpreter::new(memory_config, stream_config);
+    #[cfg(feature = "jit")]
+    interpreter.set_jit(use_jit(matches));
+    let result = interpreter.execute(instructions);
+    dump_memory(&interpreter, parse_dump(matches));
+    result?;
+    Ok(())
+}
+
+#[cfg(feature = "jit")]
+fn use_jit(matches: &ArgMatches) -> bool {
+    !matches.get_flag("NO_JIT")
+}
+
+#[cfg(not(feature = "jit"))]
+fn use_jit(_matches: &ArgMatches) -> bool {
+    false
+}
+
+/// Read the file, or exit if it can't be read.
+fn read<T>(path: &Path, read: impl FnOnce(&Path) -> std::io::Result<T>) -> T {
+    match read(path) {
+        Ok(content) => content,
         Err(e) => {
             match e.kind() {
                 ErrorKind::NotFound => eprintln!("error: couldn't find {}", path.display()),
@@ -35,10 +130,25 @@ fn main() {
 
             process::exit(1);
         }
+    }
+}
+
+/// Write the memory to the file if it's asked for, or exit if it can't be
+/// written.
+fn dump_memory(interpreter: &Interpreter, dump: Option<(PathBuf, DumpFormat)>) {
+    let Some((path, format)) = dump else {
+        return;
     };
 
-    if let Err(e) = run(memory_config, stream_config, code) {
-        print_error(e);
+    let result = File::create(&path).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        interpreter.memory().dump(format, &mut out)?;
+        out.flush()
+    });
+
+    if let Err(e) = result {
+        eprintln!("error: couldn't write {}", path.display());
+        eprintln!("caused by: {e}");
         process::exit(1);
     }
 }
@@ -55,7 +165,97 @@ fn print_error(e: Box<dyn Error>) {
 
 fn input() -> ArgMatches {
     let cmd = command!();
+    let cmd = cmd.args(memory_args());
+    let cmd = cmd.args(stream_args());
+    let cmd = cmd.args(dump_args());
     let cmd = cmd.arg(
+        Arg::new("OPT_REPORT")
+            .long("opt-report")
+            .action(ArgAction::SetTrue)
+            .next_line_help(true)
+            .help("print what the optimizer rewrote to stderr before running.\n")
+            .long_help(
+                "print what the optimizer rewrote to stderr before running, including \
+                the rule and the source location of each rewrite.",
+            ),
+    );
+    let cmd = cmd.arg(
+        Arg::new("EMIT")
+            .long("emit")
+            .required(false)
+            .value_parser(["c", "rust", "rust-module", "wat", "asm", "ir", "bf"])
+            .next_line_help(true)
+            .help("translate the program into another language instead of running it.\n")
+            .long_help(
+                "translate the program into another language and print it to stdout \
+                instead of running it. The memory options are kept in the generated code.\n\n \
+                - c: a C program\n \
+                - rust: a Rust program\n \
+                - rust-module: a Rust module exposing `run(input, output)`\n \
+                - wat: a WebAssembly text format module importing `env.read` and `env.write`\n \
+                - asm: x86-64 Linux assembly for the GNU assembler\n \
+                - ir: the compiled instructions with their addresses and source text\n \
+                - bf: the optimized program in brainfuck with the comments removed",
+            ),
+    );
+    let cmd = cmd.args(jit_args());
+    let cmd = cmd.arg(
+        Arg::new("SOURCE")
+            .required(true)
+            .value_parser(PathBufValueParser::new())
+            .next_line_help(true)
+            .help("the path of the brainfuck program source code file.\n")
+            .long_help("the path of the brainfuck program source code file."),
+    );
+    let cmd = cmd
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true);
+    let cmd = cmd.subcommand(
+        Command::new("compile")
+            .about("compile the program into bytecode instead of running it.")
+            .args(memory_args())
+            .arg(
+                Arg::new("OUT")
+                    .short('o')
+                    .value_name("FILE")
+                    .value_parser(PathBufValueParser::new())
+                    .next_line_help(true)
+                    .help("the path of the bytecode file [default: <SOURCE>.bfc]\n")
+                    .long_help(
+                        "the path of the bytecode file, which is the source code file with \
+                        the `.bfc` extension by default.",
+                    ),
+            )
+            .arg(
+                Arg::new("SOURCE")
+                    .required(true)
+                    .value_parser(PathBufValueParser::new())
+                    .next_line_help(true)
+                    .help("the path of the brainfuck program source code file.\n")
+                    .long_help("the path of the brainfuck program source code file."),
+            ),
+    );
+    let cmd = cmd.subcommand(
+        Command::new("run")
+            .about("run a program compiled by `compile` with its memory options.")
+            .args(stream_args())
+            .args(dump_args())
+            .args(jit_args())
+            .arg(
+                Arg::new("SOURCE")
+                    .required(true)
+                    .value_parser(PathBufValueParser::new())
+                    .next_line_help(true)
+                    .help("the path of the bytecode file.\n")
+                    .long_help("the path of the bytecode file."),
+            ),
+    );
+
+    cmd.get_matches()
+}
+
+fn memory_args() -> [Arg; 10] {
+    [
         Arg::new("LEN")
             .long("len")
             .required(false)
@@ -63,13 +263,24 @@ fn input() -> ArgMatches {
             .default_value("32768")
             .next_line_help(true)
             .help("the memory (tape) length the program will use.\n")
-            .long_help("the memory (tape) length the program will use."),
-    );
-    let cmd = cmd.arg(
+            .long_help(
+                "the memory (tape) length the program will use, which is the initial one if \
+                the memory grows dynamically.",
+            ),
+        Arg::new("MAX_LEN")
+            .long("max-len")
+            .required(false)
+            .value_parser(value_parser!(usize))
+            .next_line_help(true)
+            .help("the maximum memory (tape) length when it grows dynamically.\n")
+            .long_help(
+                "the maximum memory (tape) length when it grows dynamically, which is \
+                unlimited by default.",
+            ),
         Arg::new("ADDR")
             .long("addr")
             .required(false)
-            .value_parser(["unsigned", "signed"])
+            .value_parser(["unsigned", "signed", "circular", "dynamic"])
             .default_value("unsigned")
             .next_line_help(true)
             .help("the address range of the memory (tape).\n")
@@ -78,25 +289,34 @@ fn input() -> ArgMatches {
                 h.push_str("the address range of the memory (tape).\n");
                 h.push('\n');
                 h.push_str(" - unsigned: [0, len - 1]\n");
-                h.push_str(" - signed: [-ceil(len / 2), ceil(len / 2) - 1]");
+                h.push_str(" - signed: [-ceil(len / 2), ceil(len / 2) - 1]\n");
+                h.push_str(" - circular: [0, len - 1], wrapping around at both ends\n");
+                h.push_str(
+                    " - dynamic: [0, len - 1] at first, growing in both directions on demand",
+                );
                 h
             }),
-    );
-    let cmd = cmd.arg(
         Arg::new("CELL")
             .long("cell")
             .required(false)
-            .value_parser(["int8", "int32"])
+            .value_parser(parse_cell)
             .default_value("int8")
             .next_line_help(true)
             .help("the data type of one cell in the memory (tape).\n")
-            .long_help("the data type of one cell in the memory (tape)."),
-    );
-    let cmd = cmd.arg(
+            .long_help({
+                let mut h = String::new();
+                h.push_str("the data type of one cell in the memory (tape).\n");
+                h.push('\n');
+                h.push_str(" - int8, int16, int32, int64: signed integers\n");
+                h.push_str(" - uint8, uint16, uint32, uint64: unsigned integers\n");
+                h.push_str(" - int<N>, uint<N>: integers of N bits, where N is in [1, 64]\n");
+                h.push_str(" - unbounded: arbitrary-precision integers, which never overflow");
+                h
+            }),
         Arg::new("OVERFLOW")
             .long("overflow")
             .required(false)
-            .value_parser(["wrap", "error"])
+            .value_parser(["wrap", "error", "saturate", "error-on-negative"])
             .default_value("wrap")
             .next_line_help(true)
             .help("the operation the interpreter should do when an overflow error occurs.\n")
@@ -109,15 +329,18 @@ fn input() -> ArgMatches {
                 h.push_str(
                     " - wrap: automatically wrap the value in cell (e.g.: `127 + 1` => `-127`)\n",
                 );
-                h.push_str(" - error: throw an error and abort");
+                h.push_str(" - error: throw an error and abort\n");
+                h.push_str(" - saturate: clamp the value to the range of cell (e.g.: `127 + 1` => `127`)\n");
+                h.push_str(
+                    " - error-on-negative: throw an error and abort if the value goes below 0, \
+                    and wrap it to 0 after the maximum",
+                );
                 h
             }),
-    );
-    let cmd = cmd.arg(
         Arg::new("EOF")
             .long("eof")
             .required(false)
-            .value_parser(["zero", "keep", "ignore"])
+            .value_parser(parse_eof)
             .default_value("ignore")
             .next_line_help(true)
             .help("the operation the interpreter should do when an `EOF` is read.\n")
@@ -127,11 +350,66 @@ fn input() -> ArgMatches {
                 h.push('\n');
                 h.push_str(" - zero: turn `EOF` to `0`\n");
                 h.push_str(" - keep: keep what the `EOF` is and return it (`EOF == -1`)\n");
-                h.push_str(" - ignore: ignore this input and leave the cell unchanged");
+                h.push_str(" - ignore: ignore this input and leave the cell unchanged\n");
+                h.push_str(" - error: throw an error and abort\n");
+                h.push_str(" - <n>: turn `EOF` to the integer n (e.g.: `255`)");
                 h
             }),
-    );
-    let cmd = cmd.arg(
+        Arg::new("STORAGE")
+            .long("storage")
+            .required(false)
+            .value_parser(["dense", "sparse"])
+            .default_value("dense")
+            .next_line_help(true)
+            .help("how the cells in the memory (tape) are stored.\n")
+            .long_help({
+                let mut h = String::new();
+                h.push_str("how the cells in the memory (tape) are stored.\n");
+                h.push('\n');
+                h.push_str(" - dense: allocate all the cells at once\n");
+                h.push_str(
+                    " - sparse: allocate the cells page by page when they're written to, \
+                    which allows a huge `len`",
+                );
+                h
+            }),
+        Arg::new("TAPE_INIT")
+            .long("tape-init")
+            .value_name("FILE")
+            .required(false)
+            .value_parser(PathBufValueParser::new())
+            .conflicts_with("TAPE_INIT_STR")
+            .next_line_help(true)
+            .help("preload the bytes of the file to the memory (tape) before running.\n")
+            .long_help(
+                "preload the bytes of the file to the memory (tape) before running, one byte \
+                per cell from `--tape-init-addr`. The bytes are wrapped to the cells.",
+            ),
+        Arg::new("TAPE_INIT_STR")
+            .long("tape-init-str")
+            .value_name("STRING")
+            .required(false)
+            .next_line_help(true)
+            .help("preload the UTF-8 bytes of the string to the memory (tape) before running.\n")
+            .long_help(
+                "preload the UTF-8 bytes of the string to the memory (tape) before running, \
+                one byte per cell from `--tape-init-addr`. The bytes are wrapped to the cells.",
+            ),
+        Arg::new("TAPE_INIT_ADDR")
+            .long("tape-init-addr")
+            .value_name("ADDR")
+            .required(false)
+            .value_parser(value_parser!(isize))
+            .allow_negative_numbers(true)
+            .default_value("0")
+            .next_line_help(true)
+            .help("the address of the first cell preloaded by `--tape-init(-str)`.\n")
+            .long_help("the address of the first cell preloaded by `--tape-init` or `--tape-init-str`."),
+    ]
+}
+
+fn stream_args() -> [Arg; 2] {
+    [
         Arg::new("INPUT")
             .long("input")
             .required(false)
@@ -140,8 +418,6 @@ fn input() -> ArgMatches {
             .next_line_help(true)
             .help("the input stream type.\n")
             .long_help("the input stream type."),
-    );
-    let cmd = cmd.arg(
         Arg::new("OUTPUT")
             .long("output")
             .required(false)
@@ -150,46 +426,166 @@ fn input() -> ArgMatches {
             .next_line_help(true)
             .help("the output stream type.\n")
             .long_help("the output stream type."),
-    );
-    let cmd = cmd.arg(
-        Arg::new("SOURCE")
-            .required(true)
+    ]
+}
+
+fn dump_args() -> [Arg; 2] {
+    [
+        Arg::new("DUMP_MEMORY")
+            .long("dump-memory")
+            .value_name("FILE")
+            .required(false)
             .value_parser(PathBufValueParser::new())
             .next_line_help(true)
-            .help("the path of the brainfuck program source code file.\n")
-            .long_help("the path of the brainfuck program source code file."),
-    );
+            .help("write the memory (tape) and the pointer to the file after running.\n")
+            .long_help(
+                "write the memory (tape) and the pointer to the file after running, even if \
+                the program failed. Only the cells from the leftmost to the rightmost one \
+                which isn't zero or the pointer points to are written.",
+            ),
+        Arg::new("DUMP_FORMAT")
+            .long("dump-format")
+            .required(false)
+            .value_parser(["raw", "hex", "csv", "json"])
+            .default_value("hex")
+            .next_line_help(true)
+            .help("the format of the file written by `--dump-memory`.\n")
+            .long_help({
+                let mut h = String::new();
+                h.push_str("the format of the file written by `--dump-memory`.\n");
+                h.push('\n');
+                h.push_str(" - raw: the lowest byte of each cell, without the pointer\n");
+                h.push_str(" - hex: the pointer and the cells like `hexdump -C`\n");
+                h.push_str(" - csv: a line of `address,value,pointer` for each cell\n");
+                h.push_str(" - json: an object with `pointer`, `start` and `cells`");
+                h
+            }),
+    ]
+}
 
-    cmd.get_matches()
+#[cfg(feature = "jit")]
+fn jit_args() -> Vec<Arg> {
+    vec![Arg::new("NO_JIT")
+        .long("no-jit")
+        .action(ArgAction::SetTrue)
+        .next_line_help(true)
+        .help("interpret the program instead of compiling it into machine code.\n")
+        .long_help("interpret the program instead of compiling it into machine code.")]
+}
+
+#[cfg(not(feature = "jit"))]
+fn jit_args() -> Vec<Arg> {
+    vec![]
 }
 
-fn parse(matches: &ArgMatches) -> (MemoryConfig, StreamConfig, &PathBuf) {
-    let memory_config = MemoryConfig {
+fn parse_memory_config(matches: &ArgMatches) -> MemoryConfig {
+    MemoryConfig {
         len: *matches.get_one::<usize>("LEN").unwrap(),
         addr: match matches.get_one::<String>("ADDR").unwrap().as_str() {
             "unsigned" => memory_config::Addr::Unsigned,
             "signed" => memory_config::Addr::Signed,
+            "circular" => memory_config::Addr::Circular,
+            "dynamic" => memory_config::Addr::Dynamic {
+                max: matches.get_one::<usize>("MAX_LEN").copied(),
+            },
             _ => unreachable!(),
         },
-        cell: match matches.get_one::<String>("CELL").unwrap().as_str() {
-            "int8" => memory_config::Cell::I8,
-            "int32" => memory_config::Cell::I32,
-            _ => unreachable!(),
-        },
+        cell: matches
+            .get_one::<memory_config::Cell>("CELL")
+            .unwrap()
+            .clone(),
         overflow: match matches.get_one::<String>("OVERFLOW").unwrap().as_str() {
             "wrap" => memory_config::Overflow::Wrap,
             "error" => memory_config::Overflow::Error,
+            "saturate" => memory_config::Overflow::Saturate,
+            "error-on-negative" => memory_config::Overflow::ErrorOnNegative,
             _ => unreachable!(),
         },
-        eof: match matches.get_one::<String>("EOF").unwrap().as_str() {
-            "zero" => memory_config::Eof::Zero,
-            "keep" => memory_config::Eof::Keep,
-            "ignore" => memory_config::Eof::Ignore,
+        eof: matches
+            .get_one::<memory_config::Eof>("EOF")
+            .unwrap()
+            .clone(),
+        storage: match matches.get_one::<String>("STORAGE").unwrap().as_str() {
+            "dense" => memory_config::Storage::Dense,
+            "sparse" => memory_config::Storage::Sparse,
             _ => unreachable!(),
         },
+        init: parse_init(matches),
+    }
+}
+
+fn parse_init(matches: &ArgMatches) -> Option<memory_config::Init> {
+    let data = match matches.get_one::<PathBuf>("TAPE_INIT") {
+        Some(path) => read(path, |path| std::fs::read(path)),
+        None => matches
+            .get_one::<String>("TAPE_INIT_STR")?
+            .as_bytes()
+            .to_vec(),
+    };
+
+    Some(memory_config::Init {
+        addr: *matches.get_one::<isize>("TAPE_INIT_ADDR").unwrap(),
+        data,
+    })
+}
+
+fn parse_cell(s: &str) -> Result<memory_config::Cell, String> {
+    if s == "unbounded" {
+        return Ok(memory_config::Cell::Unbounded);
+    }
+
+    let (signed, bits) = match s.strip_prefix('u') {
+        Some(bits) => (false, bits),
+        None => (true, s),
+    };
+    let bits = bits
+        .strip_prefix("int")
+        .and_then(|bits| bits.parse::<u32>().ok())
+        .filter(|bits| (1..=64).contains(bits))
+        .ok_or_else(|| {
+            format!("expected unbounded, int<N> or uint<N> with N in [1, 64], got `{s}`")
+        })?;
+
+    Ok(match (bits, signed) {
+        (8, true) => memory_config::Cell::I8,
+        (8, false) => memory_config::Cell::U8,
+        (16, true) => memory_config::Cell::I16,
+        (16, false) => memory_config::Cell::U16,
+        (32, true) => memory_config::Cell::I32,
+        (32, false) => memory_config::Cell::U32,
+        (64, true) => memory_config::Cell::I64,
+        (64, false) => memory_config::Cell::U64,
+        (bits, signed) => memory_config::Cell::Bits { bits, signed },
+    })
+}
+
+fn parse_eof(s: &str) -> Result<memory_config::Eof, String> {
+    match s {
+        "zero" => Ok(memory_config::Eof::Zero),
+        "keep" => Ok(memory_config::Eof::Keep),
+        "ignore" => Ok(memory_config::Eof::Ignore),
+        "error" => Ok(memory_config::Eof::Error),
+        _ => s
+            .parse()
+            .map(memory_config::Eof::Value)
+            .map_err(|_| format!("expected zero, keep, ignore, error or an integer, got `{s}`")),
+    }
+}
+
+fn parse_dump(matches: &ArgMatches) -> Option<(PathBuf, DumpFormat)> {
+    let path = matches.get_one::<PathBuf>("DUMP_MEMORY")?;
+    let format = match matches.get_one::<String>("DUMP_FORMAT").unwrap().as_str() {
+        "raw" => DumpFormat::Raw,
+        "hex" => DumpFormat::Hex,
+        "csv" => DumpFormat::Csv,
+        "json" => DumpFormat::Json,
+        _ => unreachable!(),
     };
+    Some((path.clone(), format))
+}
 
-    let stream_config = StreamConfig {
+fn parse_stream_config(matches: &ArgMatches) -> StreamConfig {
+    StreamConfig {
         input: match matches.get_one::<String>("INPUT").unwrap().as_str() {
             "null" => stream_config::Input::Null,
             "std" => stream_config::Input::Standard,
@@ -200,18 +596,71 @@ fn parse(matches: &ArgMatches) -> (MemoryConfig, StreamConfig, &PathBuf) {
             "int-std" => stream_config::Output::IntStandard,
             _ => unreachable!(),
         },
-    };
-
-    let source = matches.get_one::<PathBuf>("SOURCE").unwrap();
-    (memory_config, stream_config, source)
+    }
 }
 
+#[cfg_attr(not(feature = "jit"), allow(unused_variables))]
 fn run(
     memory_config: MemoryConfig,
     stream_config: StreamConfig,
     code: String,
+    opt_report: bool,
+    jit: bool,
+    dump: Option<(PathBuf, DumpFormat)>,
 ) -> Result<(), Box<dyn Error>> {
     let mut interpreter = Interpreter::new(memory_config, stream_config);
-    interpreter.run(&code)?;
+    #[cfg(feature = "jit")]
+    interpreter.set_jit(jit);
+
+    if opt_report {
+        let (_, report) = interpreter.compile_with_report(&code)?;
+        print_report(&report, &code);
+    }
+    let result = interpreter.run(&code);
+    dump_memory(&interpreter, dump);
+    result?;
+    Ok(())
+}
+
+fn emit(memory_config: MemoryConfig, code: String, target: Target) -> Result<(), Box<dyn Error>> {
+    print!("{}", bf_exec::emit(&code, target, memory_config)?);
     Ok(())
 }
+
+fn print_report(report: &Report, code: &str) {
+    const SNIPPET_LEN: usize = 32;
+
+    eprintln!("optimization report:");
+
+    for rewrite in &report.rewrites {
+        match rewrite.span {
+            Some(span) => {
+                let (line, column) = span.location(code);
+                let text = &code[span.start..span.end];
+                // Cut on a char boundary, counting chars on both sides.
+                let end = text
+                    .char_indices()
+                    .nth(SNIPPET_LEN)
+                    .map_or(text.len(), |(i, _)| i);
+                let snippet: String = text[..end]
+                    .chars()
+                    .map(|c| if c.is_whitespace() { ' ' } else { c })
+                    .collect();
+                let ellipsis = if end < text.len() { "..." } else { "" };
+                eprintln!(
+                    "  {:<20} {line}:{column} `{snippet}{ellipsis}`",
+                    rewrite.rule
+                );
+            }
+            None => eprintln!("  {}", rewrite.rule),
+        }
+    }
+
+    eprintln!();
+
+    for line in report.to_string().lines() {
+        eprintln!("  {line}");
+    }
+
+    eprintln!();
+}
@@ -12,7 +12,7 @@ pub enum AddError {
     Overflow { source: MemoryError },
 }
 
-pub fn execute(memory: &mut Memory, addr: isize, val: i32) -> Result<()> {
+pub fn execute(memory: &mut Memory, addr: isize, val: i128) -> Result<()> {
     match memory.add_at(addr, val) {
         Ok(()) => Ok(()),
         Err(e) => match e {
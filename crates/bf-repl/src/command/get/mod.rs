@@ -9,7 +9,7 @@ pub enum GetError {
     OutOfBound { source: MemoryError },
 }
 
-pub fn execute(memory: &Memory, addr: isize) -> Result<i32> {
+pub fn execute(memory: &Memory, addr: isize) -> Result<i128> {
     match memory.get_at(addr) {
         Ok(res) => Ok(res),
         Err(e) => Err(GetError::OutOfBound { source: e }),
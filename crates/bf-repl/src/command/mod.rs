@@ -20,8 +20,8 @@ pub enum Command {
     Get { addr: isize },
     Position,
     Run { code: String },
-    Add { addr: isize, val: i32 },
-    Set { addr: isize, val: i32 },
+    Add { addr: isize, val: i128 },
+    Set { addr: isize, val: i128 },
     View { range: AddrRange },
     Help,
     Exit,
@@ -7,3 +7,28 @@ type Result<T> = std::result::Result<T, RunError>;
 pub fn execute(interpreter: &mut Interpreter, code: &str) -> Result<()> {
     interpreter.run(code)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{get, Command};
+    use crate::parser::Parser;
+
+    #[test]
+    fn run_on_kept_memory() {
+        let parser = Parser::new();
+        let mut interpreter = Interpreter::new(Default::default(), Default::default());
+
+        for line in ["set 0 5", "run [-]"] {
+            let cmd = parser.parse(line).unwrap();
+            cmd.execute(&mut interpreter).unwrap();
+        }
+
+        // The loop in the front of the code runs, for the memory is kept
+        // between the commands.
+        let Ok(Command::Get { addr }) = parser.parse("get 0") else {
+            unreachable!()
+        };
+        assert_eq!(get::execute(interpreter.memory(), addr), Ok(0));
+    }
+}
@@ -10,14 +10,17 @@ pub enum SetError {
     OutOfBound { source: MemoryError },
     #[snafu(display("value is overflowed"))]
     Overflow { source: MemoryError },
+    #[snafu(display("value is EOF"))]
+    Eof { source: MemoryError },
 }
 
-pub fn execute(memory: &mut Memory, addr: isize, val: i32) -> Result<()> {
+pub fn execute(memory: &mut Memory, addr: isize, val: i128) -> Result<()> {
     match memory.set_at(addr, val) {
         Ok(()) => Ok(()),
         Err(e) => match e {
             e @ MemoryError::AccessOutOfBounds { .. } => Err(SetError::OutOfBound { source: e }),
             e @ MemoryError::SetOverflow { .. } => Err(SetError::Overflow { source: e }),
+            e @ MemoryError::UnexpectedEof => Err(SetError::Eof { source: e }),
             _ => unreachable!(),
         },
     }
@@ -21,7 +21,7 @@ impl Interpreter {
     pub fn run(&mut self, code: &str) -> Result<()> {
         let compiler = Compiler::new();
         let instructions = compiler.compile(code)?;
-        let mut processor = Processor::new(instructions);
+        let mut processor = Processor::new(instructions)?;
         processor.run(&mut self.context)?;
         Ok(())
     }
@@ -41,6 +41,7 @@ pub enum InterpreterError {
     Parse { source: ParseError },
     #[snafu(display("an error occurred when running the code"))]
     Runtime { source: ProcessorError },
+    #[allow(dead_code)]
     #[snafu(display("the program hasn't been loaded yet"))]
     Uninitialized,
 }
@@ -0,0 +1,685 @@
+//! A compact binary format for compiled programs.
+//!
+//! A file starts with the magic bytes `BFBC` and a little-endian `u16`
+//! version, followed by the memory config the program was compiled for and
+//! the instructions. Integers are LEB128-encoded, signed ones after zigzag
+//! encoding, and each instruction is an opcode byte followed by its operands.
+
+use snafu::prelude::*;
+
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList, VerifyError};
+use crate::execution::memory::config::{
+    Addr, Cell, Config as MemoryConfig, Eof, Init, Overflow, Storage,
+};
+
+pub const MAGIC: [u8; 4] = *b"BFBC";
+pub const VERSION: u16 = 1;
+
+const ADD: u8 = 0;
+const SEEK: u8 = 1;
+const CLEAR: u8 = 2;
+const ADD_UNTIL_ZERO: u8 = 3;
+const INPUT: u8 = 4;
+const OUTPUT: u8 = 5;
+const JUMP: u8 = 6;
+const JUMP_IF_ZERO: u8 = 7;
+const HALT: u8 = 8;
+
+pub type Result<T> = std::result::Result<T, BytecodeError>;
+
+#[derive(Snafu, Debug, PartialEq, Eq)]
+pub enum BytecodeError {
+    #[snafu(display("not a compiled brainfuck program"))]
+    InvalidMagic,
+    #[snafu(display("unsupported bytecode version {version}, expected {VERSION}"))]
+    UnsupportedVersion { version: u16 },
+    #[snafu(display("unexpected end of bytecode at byte {offset}"))]
+    UnexpectedEnd { offset: usize },
+    #[snafu(display("invalid memory config at byte {offset}"))]
+    InvalidConfig { offset: usize },
+    #[snafu(display("invalid opcode {opcode} at byte {offset}"))]
+    InvalidOpcode { opcode: u8, offset: usize },
+    #[snafu(display("operand out of range at byte {offset}"))]
+    OperandOutOfRange { offset: usize },
+    #[snafu(display("unexpected bytes after the program at byte {offset}"))]
+    TrailingBytes { offset: usize },
+    #[snafu(display("the instructions are invalid"))]
+    Invalid { source: VerifyError },
+}
+
+/// A compiled program with the memory config it expects to run with.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub config: MemoryConfig,
+    pub instructions: InstructionList,
+}
+
+impl Program {
+    pub fn new(config: MemoryConfig, instructions: InstructionList) -> Self {
+        Self {
+            config,
+            instructions,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer { bytes: vec![] };
+        w.bytes.extend_from_slice(&MAGIC);
+        w.bytes.extend_from_slice(&VERSION.to_le_bytes());
+        w.config(&self.config);
+        w.unsigned(self.instructions.0.len() as u64);
+
+        for ins in &self.instructions.0 {
+            w.instruction(ins);
+        }
+
+        w.bytes
+    }
+
+    /// Decode a program and check that it can be run safely.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut r = Reader { bytes, offset: 0 };
+
+        if r.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return InvalidMagicSnafu.fail();
+        }
+
+        let version = u16::from_le_bytes(r.take(2)?.try_into().unwrap());
+        ensure!(version == VERSION, UnsupportedVersionSnafu { version });
+
+        let config = r.config()?;
+        let len = r.len()?;
+        // Every instruction takes at least a byte.
+        let mut instructions = Vec::with_capacity(len.min(r.remaining()));
+
+        for _ in 0..len {
+            instructions.push(r.instruction()?);
+        }
+
+        ensure!(
+            r.offset == bytes.len(),
+            TrailingBytesSnafu { offset: r.offset }
+        );
+        let instructions = InstructionList(instructions);
+        instructions.verify().context(InvalidSnafu)?;
+        Ok(Self::new(config, instructions))
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn unsigned(&mut self, mut val: u64) {
+        loop {
+            let byte = (val & 0x7F) as u8;
+            val >>= 7;
+
+            if val == 0 {
+                self.bytes.push(byte);
+                break;
+            }
+
+            self.bytes.push(byte | 0x80);
+        }
+    }
+
+    fn signed(&mut self, val: i64) {
+        self.unsigned(((val << 1) ^ (val >> 63)) as u64);
+    }
+
+    fn config(&mut self, config: &MemoryConfig) {
+        self.unsigned(config.len as u64);
+
+        match config.addr {
+            Addr::Unsigned => self.bytes.push(0),
+            Addr::Signed => self.bytes.push(1),
+            Addr::Dynamic { max: None } => self.bytes.push(2),
+            Addr::Dynamic { max: Some(max) } => {
+                self.bytes.push(3);
+                self.unsigned(max as u64);
+            }
+            Addr::Circular => self.bytes.push(4),
+        }
+
+        match config.cell {
+            Cell::I8 => self.bytes.push(0),
+            Cell::I32 => self.bytes.push(1),
+            Cell::U8 => self.bytes.push(2),
+            Cell::I16 => self.bytes.push(3),
+            Cell::U16 => self.bytes.push(4),
+            Cell::U32 => self.bytes.push(5),
+            Cell::I64 => self.bytes.push(6),
+            Cell::U64 => self.bytes.push(7),
+            Cell::Bits { bits, signed } => {
+                self.bytes.push(8);
+                self.bytes.push(bits as u8);
+                self.bytes.push(signed as u8);
+            }
+            Cell::Unbounded => self.bytes.push(9),
+        }
+
+        self.bytes.push(match config.overflow {
+            Overflow::Error => 0,
+            Overflow::Wrap => 1,
+            Overflow::Saturate => 2,
+            Overflow::ErrorOnNegative => 3,
+        });
+        match config.eof {
+            Eof::Zero => self.bytes.push(0),
+            Eof::Keep => self.bytes.push(1),
+            Eof::Ignore => self.bytes.push(2),
+            Eof::Value(val) => {
+                self.bytes.push(3);
+                self.signed(val);
+            }
+            Eof::Error => self.bytes.push(4),
+        }
+
+        self.bytes.push(match config.storage {
+            Storage::Dense => 0,
+            Storage::Sparse => 1,
+        });
+
+        match &config.init {
+            None => self.bytes.push(0),
+            Some(Init { addr, data }) => {
+                self.bytes.push(1);
+                self.signed(*addr as i64);
+                self.unsigned(data.len() as u64);
+                self.bytes.extend_from_slice(data);
+            }
+        }
+    }
+
+    fn instruction(&mut self, ins: &Instruction) {
+        match ins {
+            Instruction::Add { val } => {
+                self.bytes.push(ADD);
+                self.signed(*val as i64);
+            }
+            Instruction::Seek { offset } => {
+                self.bytes.push(SEEK);
+                self.signed(*offset as i64);
+            }
+            Instruction::Clear => self.bytes.push(CLEAR),
+            Instruction::AddUntilZero { target } => {
+                self.bytes.push(ADD_UNTIL_ZERO);
+                self.unsigned(target.len() as u64);
+
+                for AddUntilZeroArg { offset, times } in target {
+                    self.signed(*offset as i64);
+                    self.signed(*times as i64);
+                }
+            }
+            Instruction::Input => self.bytes.push(INPUT),
+            Instruction::Output => self.bytes.push(OUTPUT),
+            Instruction::Jump { target } => {
+                self.bytes.push(JUMP);
+                self.unsigned(*target as u64);
+            }
+            Instruction::JumpIfZero { target } => {
+                self.bytes.push(JUMP_IF_ZERO);
+                self.unsigned(*target as u64);
+            }
+            Instruction::Halt => self.bytes.push(HALT),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .context(UnexpectedEndSnafu {
+                offset: self.bytes.len(),
+            })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn unsigned(&mut self) -> Result<u64> {
+        let start = self.offset;
+        let mut val = 0u64;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.byte()?;
+            ensure!(
+                shift < 64 && ((byte & 0x7F) as u64) << shift >> shift == (byte & 0x7F) as u64,
+                OperandOutOfRangeSnafu { offset: start }
+            );
+            val |= ((byte & 0x7F) as u64) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+    }
+
+    fn signed(&mut self) -> Result<i64> {
+        let val = self.unsigned()?;
+        Ok((val >> 1) as i64 ^ -((val & 1) as i64))
+    }
+
+    /// Read a signed operand which must fit in `T`.
+    fn operand<T: TryFrom<i64>>(&mut self) -> Result<T> {
+        let start = self.offset;
+        T::try_from(self.signed()?)
+            .ok()
+            .context(OperandOutOfRangeSnafu { offset: start })
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let start = self.offset;
+        usize::try_from(self.unsigned()?)
+            .ok()
+            .context(OperandOutOfRangeSnafu { offset: start })
+    }
+
+    fn config(&mut self) -> Result<MemoryConfig> {
+        let start = self.offset;
+        let len = self.len()?;
+        let invalid = InvalidConfigSnafu { offset: start };
+        let addr = match self.byte()? {
+            0 => Addr::Unsigned,
+            1 => Addr::Signed,
+            2 => Addr::Dynamic { max: None },
+            3 => Addr::Dynamic {
+                max: Some(self.len()?),
+            },
+            4 => Addr::Circular,
+            _ => return invalid.fail(),
+        };
+        let cell = match self.byte()? {
+            0 => Cell::I8,
+            1 => Cell::I32,
+            2 => Cell::U8,
+            3 => Cell::I16,
+            4 => Cell::U16,
+            5 => Cell::U32,
+            6 => Cell::I64,
+            7 => Cell::U64,
+            8 => {
+                let bits = self.byte()? as u32;
+                let signed = match self.byte()? {
+                    0 => false,
+                    1 => true,
+                    _ => return invalid.fail(),
+                };
+                ensure!((1..=64).contains(&bits), invalid);
+                Cell::Bits { bits, signed }
+            }
+            9 => Cell::Unbounded,
+            _ => return invalid.fail(),
+        };
+        let overflow = match self.byte()? {
+            0 => Overflow::Error,
+            1 => Overflow::Wrap,
+            2 => Overflow::Saturate,
+            3 => Overflow::ErrorOnNegative,
+            _ => return invalid.fail(),
+        };
+        let eof = match self.byte()? {
+            0 => Eof::Zero,
+            1 => Eof::Keep,
+            2 => Eof::Ignore,
+            3 => Eof::Value(self.signed()?),
+            4 => Eof::Error,
+            _ => return invalid.fail(),
+        };
+        let storage = match self.byte()? {
+            0 => Storage::Dense,
+            1 => Storage::Sparse,
+            _ => return invalid.fail(),
+        };
+        let init = match self.byte()? {
+            0 => None,
+            1 => {
+                let addr = self.operand()?;
+                let len = self.len()?;
+                ensure!(
+                    len <= self.remaining(),
+                    UnexpectedEndSnafu {
+                        offset: self.bytes.len()
+                    }
+                );
+                Some(Init {
+                    addr,
+                    data: self.take(len)?.to_vec(),
+                })
+            }
+            _ => return invalid.fail(),
+        };
+        ensure!(len != 0 && len <= isize::MAX as usize, invalid);
+
+        let config = MemoryConfig {
+            len,
+            addr,
+            cell,
+            overflow,
+            eof,
+            storage,
+            init,
+        };
+        // The memory is only allocated when the program runs, which is
+        // rejected here if it's too large or the preloaded data doesn't fit.
+        ensure!(config.check().is_ok(), invalid);
+        Ok(config)
+    }
+
+    fn instruction(&mut self) -> Result<Instruction> {
+        let offset = self.offset;
+        let ins = match self.byte()? {
+            ADD => Instruction::Add {
+                val: self.operand()?,
+            },
+            SEEK => Instruction::Seek {
+                offset: self.operand()?,
+            },
+            CLEAR => Instruction::Clear,
+            ADD_UNTIL_ZERO => {
+                let len = self.len()?;
+                // Every target takes at least two bytes.
+                let mut target = Vec::with_capacity(len.min(self.remaining() / 2));
+
+                for _ in 0..len {
+                    target.push(AddUntilZeroArg::new(self.operand()?, self.operand()?));
+                }
+
+                Instruction::AddUntilZero { target }
+            }
+            INPUT => Instruction::Input,
+            OUTPUT => Instruction::Output,
+            JUMP => Instruction::Jump {
+                target: self.len()?,
+            },
+            JUMP_IF_ZERO => Instruction::JumpIfZero {
+                target: self.len()?,
+            },
+            HALT => Instruction::Halt,
+            opcode => return InvalidOpcodeSnafu { opcode, offset }.fail(),
+        };
+        Ok(ins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::execution::memory::MAX_DENSE_LEN;
+
+    fn program() -> Program {
+        let instructions = Compiler::new().compile(",[->+++<]>[.-]").unwrap();
+        let config = MemoryConfig {
+            len: 300,
+            addr: Addr::Signed,
+            cell: Cell::I32,
+            overflow: Overflow::Wrap,
+            eof: Eof::Keep,
+            storage: Storage::Sparse,
+            init: None,
+        };
+        Program::new(config, instructions)
+    }
+
+    #[test]
+    fn round_trip() {
+        let program = program();
+        let bytes = program.encode();
+        assert_eq!(&bytes[..6], b"BFBC\x01\x00");
+
+        let decoded = Program::decode(&bytes).unwrap();
+        assert_eq!(decoded.instructions, program.instructions);
+        assert_eq!(
+            format!("{:?}", decoded.config),
+            format!("{:?}", program.config)
+        );
+
+        let extremes = InstructionList(vec![
+            Instruction::Add { val: i32::MIN },
+            Instruction::Seek { offset: isize::MAX },
+            Instruction::AddUntilZero {
+                target: vec![AddUntilZeroArg::new(isize::MIN, i32::MAX)],
+            },
+            Instruction::Halt,
+        ]);
+        let program = Program::new(MemoryConfig::default(), extremes);
+        let decoded = Program::decode(&program.encode()).unwrap();
+        assert_eq!(decoded.instructions, program.instructions);
+
+        for max in [None, Some(1000)] {
+            let config = MemoryConfig {
+                addr: Addr::Dynamic { max },
+                ..MemoryConfig::default()
+            };
+            let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+            let decoded = Program::decode(&program.encode()).unwrap();
+            assert!(matches!(decoded.config.addr, Addr::Dynamic { max: m } if m == max));
+        }
+
+        let config = MemoryConfig {
+            addr: Addr::Circular,
+            ..MemoryConfig::default()
+        };
+        let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+        let decoded = Program::decode(&program.encode()).unwrap();
+        assert!(matches!(decoded.config.addr, Addr::Circular));
+
+        for cell in [
+            Cell::U8,
+            Cell::I16,
+            Cell::U16,
+            Cell::U32,
+            Cell::I64,
+            Cell::U64,
+            Cell::Bits {
+                bits: 12,
+                signed: false,
+            },
+            Cell::Unbounded,
+        ] {
+            let config = MemoryConfig {
+                cell,
+                ..MemoryConfig::default()
+            };
+            let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+            let decoded = Program::decode(&program.encode()).unwrap();
+            assert_eq!(
+                format!("{:?}", decoded.config.cell),
+                format!("{:?}", program.config.cell)
+            );
+        }
+
+        for eof in [Eof::Value(-1000), Eof::Error] {
+            let config = MemoryConfig {
+                eof,
+                ..MemoryConfig::default()
+            };
+            let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+            let decoded = Program::decode(&program.encode()).unwrap();
+            assert_eq!(
+                format!("{:?}", decoded.config.eof),
+                format!("{:?}", program.config.eof)
+            );
+        }
+
+        let config = MemoryConfig {
+            addr: Addr::Signed,
+            init: Some(Init {
+                addr: -3,
+                data: b"\x00\xffbf".to_vec(),
+            }),
+            ..MemoryConfig::default()
+        };
+        let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+        let decoded = Program::decode(&program.encode()).unwrap();
+        assert_eq!(
+            format!("{:?}", decoded.config.init),
+            format!("{:?}", program.config.init)
+        );
+
+        for overflow in [Overflow::Saturate, Overflow::ErrorOnNegative] {
+            let config = MemoryConfig {
+                overflow,
+                ..MemoryConfig::default()
+            };
+            let program = Program::new(config, InstructionList(vec![Instruction::Halt]));
+            let decoded = Program::decode(&program.encode()).unwrap();
+            assert_eq!(
+                format!("{:?}", decoded.config.overflow),
+                format!("{:?}", program.config.overflow)
+            );
+        }
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let bytes = program().encode();
+
+        assert_eq!(
+            Program::decode(b"#!bf").unwrap_err(),
+            BytecodeError::InvalidMagic
+        );
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(
+            Program::decode(&newer).unwrap_err(),
+            BytecodeError::UnsupportedVersion { version: 2 }
+        );
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(
+            Program::decode(truncated).unwrap_err(),
+            BytecodeError::UnexpectedEnd {
+                offset: truncated.len()
+            }
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(HALT);
+        assert_eq!(
+            Program::decode(&trailing).unwrap_err(),
+            BytecodeError::TrailingBytes {
+                offset: bytes.len()
+            }
+        );
+
+        // The header, the config and the length of the instructions.
+        let start = MAGIC.len() + 2 + 8 + 1;
+
+        let mut opcode = bytes.clone();
+        opcode[start] = 42;
+        assert_eq!(
+            Program::decode(&opcode).unwrap_err(),
+            BytecodeError::InvalidOpcode {
+                opcode: 42,
+                offset: start
+            }
+        );
+
+        let mut config = bytes.clone();
+        config[MAGIC.len() + 2 + 2] = 9;
+        assert_eq!(
+            Program::decode(&config).unwrap_err(),
+            BytecodeError::InvalidConfig {
+                offset: MAGIC.len() + 2
+            }
+        );
+
+        // The preloaded data doesn't fit in the memory.
+        let config = MemoryConfig {
+            len: 2,
+            init: Some(Init {
+                addr: 1,
+                data: b"bf".to_vec(),
+            }),
+            ..MemoryConfig::default()
+        };
+        let init = Program::new(config, InstructionList(vec![Instruction::Halt])).encode();
+        assert_eq!(
+            Program::decode(&init).unwrap_err(),
+            BytecodeError::InvalidConfig {
+                offset: MAGIC.len() + 2
+            }
+        );
+
+        // A tiny file can't make the memory or the preloaded data huge.
+        let config = MemoryConfig {
+            len: MAX_DENSE_LEN + 1,
+            ..MemoryConfig::default()
+        };
+        let huge = Program::new(config, InstructionList(vec![Instruction::Halt])).encode();
+        assert_eq!(
+            Program::decode(&huge).unwrap_err(),
+            BytecodeError::InvalidConfig {
+                offset: MAGIC.len() + 2
+            }
+        );
+
+        let config = MemoryConfig {
+            init: Some(Init {
+                addr: 0,
+                data: b"bf".to_vec(),
+            }),
+            ..MemoryConfig::default()
+        };
+        let mut huge = Program::new(config, InstructionList(vec![Instruction::Halt])).encode();
+        let len = huge.windows(3).position(|w| w == b"\x02bf").unwrap();
+        huge.splice(len..len + 1, [0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(
+            Program::decode(&huge).unwrap_err(),
+            BytecodeError::UnexpectedEnd { offset: huge.len() }
+        );
+
+        let jump = InstructionList(vec![Instruction::Jump { target: 5 }, Instruction::Halt]);
+        let jump = Program::new(MemoryConfig::default(), jump).encode();
+        assert_eq!(
+            Program::decode(&jump).unwrap_err(),
+            BytecodeError::Invalid {
+                source: VerifyError::InvalidJumpTarget { addr: 0, target: 5 }
+            }
+        );
+
+        let halt = InstructionList(vec![Instruction::Output]);
+        let halt = Program::new(MemoryConfig::default(), halt).encode();
+        assert_eq!(
+            Program::decode(&halt).unwrap_err(),
+            BytecodeError::Invalid {
+                source: VerifyError::MissingHalt
+            }
+        );
+
+        let mut add = Program::new(
+            program().config,
+            InstructionList(vec![Instruction::Seek { offset: 0 }, Instruction::Halt]),
+        )
+        .encode();
+        let operand = start + 1;
+        // Replace the seek by an add of 2^31, which doesn't fit in an `i32`.
+        add.splice(start..operand + 1, [ADD, 0x80, 0x80, 0x80, 0x80, 0x10]);
+        assert_eq!(
+            Program::decode(&add).unwrap_err(),
+            BytecodeError::OperandOutOfRange { offset: operand }
+        );
+    }
+}
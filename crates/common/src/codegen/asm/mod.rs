@@ -0,0 +1,753 @@
+//! Translate an `InstructionList` into x86-64 assembly for the GNU assembler.
+//!
+//! The program runs on Linux without any library, doing I/O with raw system
+//! calls. Output is buffered and flushed before reading input and on exit.
+//! An out-of-bounds seek or an overflow under `Overflow::Error` prints an
+//! error to `stderr` and exits with status 1.
+//!
+//! Registers used across the whole program:
+//!
+//! - `%rbx`: the address of the current cell
+//! - `%r12`: the address of the leftmost cell
+//! - `%r13`: the address of the rightmost cell
+//! - `%r14`: the end of the buffered output
+
+use super::{check_config, expand, structure, Node, Result, UnsupportedSnafu, Writer};
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+use crate::execution::memory::config::{Cell, Config as MemoryConfig, Eof, Overflow};
+
+/// The size of the output buffer in bytes.
+const BUFFER_SIZE: usize = 4096;
+
+/// The zeroed bytes on both sides of the tape, which let the scan loops read
+/// 16 cells at once without checking the bounds in every step.
+const PADDING: usize = 16;
+
+pub struct Emitter {
+    config: MemoryConfig,
+}
+
+impl Emitter {
+    pub fn new(config: MemoryConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn emit(&self, instructions: &InstructionList) -> Result<String> {
+        check_config("asm", &self.config)?;
+        let instructions = expand(instructions, &self.config)?;
+        let nodes = structure(&instructions)?;
+        let mut w = Writer::new("    ");
+
+        self.emit_prelude(&mut w);
+        w.indent();
+
+        let mut i = 0;
+
+        while i < nodes.len() {
+            if let [Node::LoopStart { addr, .. }, Node::Instruction {
+                ins: Instruction::Seek { offset },
+                ..
+            }, Node::LoopEnd { .. }, ..] = nodes[i..]
+            {
+                self.emit_scan(&mut w, addr, *offset)?;
+                i += 3;
+                continue;
+            }
+
+            match nodes[i] {
+                Node::Instruction { addr, ins } => self.emit_instruction(&mut w, addr, ins)?,
+                Node::LoopStart { addr, .. } => {
+                    w.line(format!("{} $0, (%rbx)", self.cmp()));
+                    w.line(format!("je .Lend{addr}"));
+                    label(&mut w, format!(".Lloop{addr}"));
+                }
+                Node::LoopEnd { start, .. } => {
+                    w.line(format!("{} $0, (%rbx)", self.cmp()));
+                    w.line(format!("jne .Lloop{start}"));
+                    label(&mut w, format!(".Lend{start}"));
+                }
+            }
+
+            i += 1;
+        }
+
+        w.line("call flush");
+        w.line("mov $60, %eax");
+        w.line("xor %edi, %edi");
+        w.line("syscall");
+        w.dedent();
+        w.line("");
+        w.raw(ROUTINES);
+        Ok(w.finish())
+    }
+
+    fn size(&self) -> isize {
+        match self.config.cell {
+            Cell::I8 => 1,
+            Cell::I32 => 4,
+            _ => unreachable!(),
+        }
+    }
+
+    fn cmp(&self) -> &'static str {
+        match self.config.cell {
+            Cell::I8 => "cmpb",
+            Cell::I32 => "cmpl",
+            _ => unreachable!(),
+        }
+    }
+
+    /// Convert an offset in cells to one in bytes, which must fit in a 32-bit
+    /// displacement.
+    fn displacement(&self, offset: isize) -> Result<i32> {
+        offset
+            .checked_mul(self.size())
+            .and_then(|offset| i32::try_from(offset).ok())
+            .ok_or_else(|| {
+                UnsupportedSnafu {
+                    backend: "asm",
+                    feature: format!("seeking by {offset} cells at once"),
+                }
+                .build()
+            })
+    }
+
+    fn emit_instruction(&self, w: &mut Writer, addr: usize, ins: &Instruction) -> Result<()> {
+        match ins {
+            Instruction::Add { val } => self.emit_add(w, *val),
+            Instruction::Seek { offset } => {
+                let disp = self.displacement(*offset)?;
+                w.line(format!("add ${disp}, %rbx"));
+
+                if disp < 0 {
+                    w.line("cmp %r12, %rbx");
+                    w.line("jb seek_error");
+                } else {
+                    w.line("cmp %r13, %rbx");
+                    w.line("ja seek_error");
+                }
+            }
+            Instruction::Clear => {
+                // A negative cell overflows before it's counted down to 0.
+                if let Overflow::Error = self.config.overflow {
+                    w.line(format!("cmp{} $0, (%rbx)", self.suffix()));
+                    w.line("jl overflow_error");
+                }
+
+                w.line(format!("mov{} $0, (%rbx)", self.suffix()));
+            }
+            Instruction::AddUntilZero { target } => self.emit_add_until_zero(w, addr, target)?,
+            Instruction::Input => w.line("call input"),
+            Instruction::Output => {
+                w.line(format!("{} (%rbx), %eax", self.load()));
+                w.line("call output");
+            }
+            Instruction::Jump { .. } | Instruction::JumpIfZero { .. } | Instruction::Halt => {
+                unreachable!()
+            }
+        }
+
+        Ok(())
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self.config.cell {
+            Cell::I8 => "b",
+            Cell::I32 => "l",
+            _ => unreachable!(),
+        }
+    }
+
+    /// The instruction loading a cell into `%eax` with sign extension.
+    fn load(&self) -> &'static str {
+        match self.config.cell {
+            Cell::I8 => "movsbl",
+            Cell::I32 => "movl",
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_add(&self, w: &mut Writer, val: i32) {
+        match (&self.config.cell, &self.config.overflow) {
+            (Cell::I8, Overflow::Wrap) => w.line(format!("addb ${}, (%rbx)", val as i8)),
+            (Cell::I32, Overflow::Wrap) => w.line(format!("addl ${val}, (%rbx)")),
+            (Cell::I8, Overflow::Error) if i8::try_from(val).is_ok() => {
+                w.line(format!("addb ${val}, (%rbx)"));
+                w.line("jo overflow_error");
+            }
+            (Cell::I8, Overflow::Error) => {
+                w.line("movsbq (%rbx), %rax");
+                w.line(format!("add ${val}, %rax"));
+                w.line("call store");
+            }
+            (Cell::I32, Overflow::Error) => {
+                w.line(format!("addl ${val}, (%rbx)"));
+                w.line("jo overflow_error");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn emit_add_until_zero(
+        &self,
+        w: &mut Writer,
+        addr: usize,
+        target: &[AddUntilZeroArg],
+    ) -> Result<()> {
+        w.line(format!("{} (%rbx), %eax", self.load()));
+        w.line("test %eax, %eax");
+        w.line(format!("jz .Lskip{addr}"));
+
+        // A negative counter overflows before it reaches 0.
+        if let Overflow::Error = self.config.overflow {
+            w.line("js overflow_error");
+        }
+
+        // All the targets are checked at once before any of them is changed.
+        let min = target.iter().map(|arg| arg.offset).min().unwrap_or(0);
+        let max = target.iter().map(|arg| arg.offset).max().unwrap_or(0);
+
+        if min < 0 {
+            w.line(format!("lea {}(%rbx), %rdx", self.displacement(min)?));
+            w.line("cmp %r12, %rdx");
+            w.line("jb seek_error");
+        }
+
+        if max > 0 {
+            w.line(format!("lea {}(%rbx), %rdx", self.displacement(max)?));
+            w.line("cmp %r13, %rdx");
+            w.line("ja seek_error");
+        }
+
+        w.line(format!("mov{} $0, (%rbx)", self.suffix()));
+
+        if let Overflow::Error = self.config.overflow {
+            w.line("movslq %eax, %rax");
+        }
+
+        for AddUntilZeroArg { offset, times } in target {
+            let disp = self.displacement(*offset)?;
+
+            match (&self.config.cell, &self.config.overflow) {
+                (Cell::I8, Overflow::Wrap) => {
+                    w.line(format!("imul ${times}, %eax, %ecx"));
+                    w.line(format!("addb %cl, {disp}(%rbx)"));
+                }
+                (Cell::I32, Overflow::Wrap) => {
+                    w.line(format!("imul ${times}, %eax, %ecx"));
+                    w.line(format!("addl %ecx, {disp}(%rbx)"));
+                }
+                (cell, Overflow::Error) => {
+                    let (load, min, max, store) = match cell {
+                        Cell::I8 => ("movsbq", i8::MIN as i64, i8::MAX as i64, "movb %dl"),
+                        Cell::I32 => ("movslq", i32::MIN as i64, i32::MAX as i64, "movl %edx"),
+                        _ => unreachable!(),
+                    };
+                    w.line(format!("imul ${times}, %rax, %rcx"));
+                    w.line(format!("{load} {disp}(%rbx), %rdx"));
+                    w.line("add %rcx, %rdx");
+                    w.line(format!("cmp ${max}, %rdx"));
+                    w.line("jg overflow_error");
+                    w.line(format!("cmp ${min}, %rdx"));
+                    w.line("jl overflow_error");
+                    w.line(format!("{store}, {disp}(%rbx)"));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        label(w, format!(".Lskip{addr}"));
+        Ok(())
+    }
+
+    /// Emit a loop which only seeks, e.g. `[>]`, to find a zero cell.
+    fn emit_scan(&self, w: &mut Writer, addr: usize, offset: isize) -> Result<()> {
+        let disp = self.displacement(offset)?;
+
+        match (&self.config.cell, offset) {
+            // Compare 16 cells at once with SSE2. The padding around the tape
+            // guarantees a zero is found before reading beyond it.
+            (Cell::I8, 1) => {
+                w.line("pxor %xmm0, %xmm0");
+                label(w, format!(".Lloop{addr}"));
+                w.line("movdqu (%rbx), %xmm1");
+                w.line("pcmpeqb %xmm0, %xmm1");
+                w.line("pmovmskb %xmm1, %eax");
+                w.line("test %eax, %eax");
+                w.line(format!("jnz .Lend{addr}"));
+                w.line("add $16, %rbx");
+                w.line(format!("jmp .Lloop{addr}"));
+                label(w, format!(".Lend{addr}"));
+                w.line("bsf %eax, %eax");
+                w.line("add %rax, %rbx");
+                w.line("cmp %r13, %rbx");
+                w.line("ja seek_error");
+            }
+            (Cell::I8, -1) => {
+                w.line("pxor %xmm0, %xmm0");
+                label(w, format!(".Lloop{addr}"));
+                w.line("movdqu -15(%rbx), %xmm1");
+                w.line("pcmpeqb %xmm0, %xmm1");
+                w.line("pmovmskb %xmm1, %eax");
+                w.line("test %eax, %eax");
+                w.line(format!("jnz .Lend{addr}"));
+                w.line("sub $16, %rbx");
+                w.line(format!("jmp .Lloop{addr}"));
+                label(w, format!(".Lend{addr}"));
+                w.line("bsr %eax, %eax");
+                w.line("lea -15(%rbx,%rax), %rbx");
+                w.line("cmp %r12, %rbx");
+                w.line("jb seek_error");
+            }
+            _ => {
+                w.line(format!("{} $0, (%rbx)", self.cmp()));
+                w.line(format!("je .Lend{addr}"));
+                label(w, format!(".Lloop{addr}"));
+                w.line(format!("add ${disp}, %rbx"));
+
+                if disp < 0 {
+                    w.line("cmp %r12, %rbx");
+                    w.line("jb seek_error");
+                } else {
+                    w.line("cmp %r13, %rbx");
+                    w.line("ja seek_error");
+                }
+
+                w.line(format!("{} $0, (%rbx)", self.cmp()));
+                w.line(format!("jne .Lloop{addr}"));
+                label(w, format!(".Lend{addr}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn emit_prelude(&self, w: &mut Writer) {
+        let range = self.config.range();
+        let size = self.size();
+        let seek_error = format!(
+            "error: try to seek pointer out of [{}, {}]\\n",
+            range.left, range.right
+        );
+
+        w.line("# Generated from a brainfuck program by brainfuck-interpreter.");
+        w.line("# Build with `as -o prog.o prog.s && ld -o prog prog.o`.");
+        w.line("");
+        w.line("    .bss");
+        w.line("    .balign 16");
+        w.line(format!("    .skip {PADDING}"));
+        w.line("tape:");
+        w.line(format!("    .skip {}", range.len() as isize * size));
+        w.line(format!("    .skip {PADDING}"));
+        w.line("buffer:");
+        w.line(format!("    .skip {BUFFER_SIZE}"));
+        w.line("byte:");
+        w.line("    .skip 1");
+        w.line("");
+        w.line("    .section .rodata");
+        w.line("seek_error_message:");
+        w.line(format!("    .ascii \"{seek_error}\""));
+        w.line("seek_error_end:");
+        w.line("overflow_error_message:");
+        w.line("    .ascii \"error: cell overflowed\\n\"");
+        w.line("overflow_error_end:");
+        w.line("");
+        w.line("    .text");
+        w.line("    .globl _start");
+        w.line("_start:");
+        w.line(format!("    lea tape+{}(%rip), %rbx", -range.left * size));
+        w.line("    lea tape(%rip), %r12");
+        w.line(format!(
+            "    lea tape+{}(%rip), %r13",
+            (range.len() as isize - 1) * size
+        ));
+        w.line("    lea buffer(%rip), %r14");
+        w.line("");
+
+        // `input` and `store` depend on the config, so they're generated here
+        // and placed before `_start` falls through to the program.
+        w.line("    jmp main");
+        w.line("");
+        self.emit_store(w);
+        self.emit_input(w);
+        w.line("main:");
+    }
+
+    /// Emit `store`, which stores `%rax` to the current cell.
+    fn emit_store(&self, w: &mut Writer) {
+        w.line("store:");
+
+        if let Overflow::Error = self.config.overflow {
+            let (min, max) = match self.config.cell {
+                Cell::I8 => (i8::MIN as i64, i8::MAX as i64),
+                Cell::I32 => (i32::MIN as i64, i32::MAX as i64),
+                _ => unreachable!(),
+            };
+            w.line(format!("    cmp ${max}, %rax"));
+            w.line("    jg overflow_error");
+            w.line(format!("    cmp ${min}, %rax"));
+            w.line("    jl overflow_error");
+        }
+
+        match self.config.cell {
+            Cell::I8 => w.line("    mov %al, (%rbx)"),
+            Cell::I32 => w.line("    mov %eax, (%rbx)"),
+            _ => unreachable!(),
+        }
+
+        w.line("    ret");
+        w.line("");
+    }
+
+    /// Emit `input`, which reads a byte into the current cell.
+    fn emit_input(&self, w: &mut Writer) {
+        w.line("input:");
+        w.line("    call flush");
+        w.line("    xor %eax, %eax");
+        w.line("    xor %edi, %edi");
+        w.line("    lea byte(%rip), %rsi");
+        w.line("    mov $1, %edx");
+        w.line("    syscall");
+        w.line("    cmp $1, %rax");
+        w.line("    jne .Leof");
+        w.line("    movzbl byte(%rip), %eax");
+        w.line("    jmp store");
+        w.line(".Leof:");
+
+        match self.config.eof {
+            Eof::Zero => {
+                w.line("    xor %eax, %eax");
+                w.line("    jmp store");
+            }
+            Eof::Keep => {
+                w.line("    mov $-1, %rax");
+                w.line("    jmp store");
+            }
+            Eof::Ignore => w.line("    ret"),
+            _ => unreachable!(),
+        }
+
+        w.line("");
+    }
+}
+
+fn label(w: &mut Writer, name: impl AsRef<str>) {
+    w.raw(&format!("{}:\n", name.as_ref()));
+}
+
+/// The routines which don't depend on the config.
+const ROUTINES: &str = r#"# Write the buffered output to stdout.
+flush:
+    lea buffer(%rip), %rsi
+.Lflush:
+    mov %r14, %rdx
+    sub %rsi, %rdx
+    jle .Lflushed
+    mov $1, %eax
+    mov $1, %edi
+    syscall
+    test %rax, %rax
+    jle .Lflushed
+    add %rax, %rsi
+    jmp .Lflush
+.Lflushed:
+    lea buffer(%rip), %r14
+    ret
+
+# Buffer `%eax` as a Unicode scalar value encoded in UTF-8.
+output:
+    lea buffer+4092(%rip), %rcx
+    cmp %rcx, %r14
+    jbe .Lencode
+    push %rax
+    call flush
+    pop %rax
+.Lencode:
+    cmp $0x80, %eax
+    jb .Lone
+    cmp $0x800, %eax
+    jb .Ltwo
+    cmp $0xD800, %eax
+    jb .Lthree
+    cmp $0xE000, %eax
+    jb .Lreplace
+    cmp $0x10000, %eax
+    jb .Lthree
+    cmp $0x110000, %eax
+    jb .Lfour
+.Lreplace:
+    mov $0xFFFD, %eax
+    jmp .Lthree
+.Lone:
+    mov %al, (%r14)
+    inc %r14
+    ret
+.Ltwo:
+    mov %eax, %ecx
+    shr $6, %ecx
+    or $0xC0, %ecx
+    mov %cl, (%r14)
+    and $0x3F, %eax
+    or $0x80, %eax
+    mov %al, 1(%r14)
+    add $2, %r14
+    ret
+.Lthree:
+    mov %eax, %ecx
+    shr $12, %ecx
+    or $0xE0, %ecx
+    mov %cl, (%r14)
+    mov %eax, %ecx
+    shr $6, %ecx
+    and $0x3F, %ecx
+    or $0x80, %ecx
+    mov %cl, 1(%r14)
+    and $0x3F, %eax
+    or $0x80, %eax
+    mov %al, 2(%r14)
+    add $3, %r14
+    ret
+.Lfour:
+    mov %eax, %ecx
+    shr $18, %ecx
+    or $0xF0, %ecx
+    mov %cl, (%r14)
+    mov %eax, %ecx
+    shr $12, %ecx
+    and $0x3F, %ecx
+    or $0x80, %ecx
+    mov %cl, 1(%r14)
+    mov %eax, %ecx
+    shr $6, %ecx
+    and $0x3F, %ecx
+    or $0x80, %ecx
+    mov %cl, 2(%r14)
+    and $0x3F, %eax
+    or $0x80, %eax
+    mov %al, 3(%r14)
+    add $4, %r14
+    ret
+
+seek_error:
+    lea seek_error_message(%rip), %rsi
+    lea seek_error_end(%rip), %rdx
+    jmp fail
+
+overflow_error:
+    lea overflow_error_message(%rip), %rsi
+    lea overflow_error_end(%rip), %rdx
+
+# Flush the output, write the message from `%rsi` to `%rdx` to stderr and exit.
+fail:
+    push %rsi
+    push %rdx
+    call flush
+    pop %rdx
+    pop %rsi
+    sub %rsi, %rdx
+    mov $1, %eax
+    mov $2, %edi
+    syscall
+    mov $60, %eax
+    mov $1, %edi
+    syscall
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::CodegenError;
+    use crate::compiler::Compiler;
+
+    fn emit(code: &str, config: MemoryConfig) -> String {
+        let instructions = Compiler::new().compile(code).unwrap();
+        Emitter::new(config).emit(&instructions).unwrap()
+    }
+
+    #[test]
+    fn emit_program() {
+        let code = emit(",[->++<]>[.>]", MemoryConfig::default());
+        let main = "main:
+    call input
+    movsbl (%rbx), %eax
+    test %eax, %eax
+    jz .Lskip1
+    js overflow_error
+    lea 1(%rbx), %rdx
+    cmp %r13, %rdx
+    ja seek_error
+    movb $0, (%rbx)
+    movslq %eax, %rax
+    imul $2, %rax, %rcx
+    movsbq 1(%rbx), %rdx
+    add %rcx, %rdx
+    cmp $127, %rdx
+    jg overflow_error
+    cmp $-128, %rdx
+    jl overflow_error
+    movb %dl, 1(%rbx)
+.Lskip1:
+    add $1, %rbx
+    cmp %r13, %rbx
+    ja seek_error
+    cmpb $0, (%rbx)
+    je .Lend3
+.Lloop3:
+    movsbl (%rbx), %eax
+    call output
+    add $1, %rbx
+    cmp %r13, %rbx
+    ja seek_error
+    cmpb $0, (%rbx)
+    jne .Lloop3
+.Lend3:
+    call flush
+";
+        assert!(code.contains(main));
+    }
+
+    #[test]
+    fn emit_scan() {
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let code = emit("+[<]>>[>>]", config.clone());
+        assert!(code.contains("    movdqu -15(%rbx), %xmm1\n"));
+        assert!(code.contains(".Lloop5:\n    add $2, %rbx\n"));
+
+        let config = MemoryConfig {
+            cell: Cell::I32,
+            ..config
+        };
+        let code = emit("+[<]", config);
+        assert!(!code.contains("movdqu"));
+        assert!(code.contains(".Lloop1:\n    add $-4, %rbx\n    cmp %r12, %rbx\n"));
+    }
+
+    #[test]
+    fn emit_unsupported() {
+        let instructions = InstructionList(vec![
+            Instruction::Seek {
+                offset: isize::MAX / 2,
+            },
+            Instruction::Halt,
+        ]);
+        let res = Emitter::new(MemoryConfig::default()).emit(&instructions);
+        assert!(matches!(res, Err(CodegenError::Unsupported { .. })));
+    }
+
+    /// Assemble and link the program with `as` and `ld` and run it, or return
+    /// `None` if they aren't available.
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn assemble_and_run(code: &str, config: MemoryConfig, input: &[u8]) -> Option<(i32, Vec<u8>)> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        let dir = std::env::temp_dir().join(format!("bf-codegen-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.s");
+        let object = dir.join("main.o");
+        let binary = dir.join("main");
+        std::fs::write(&source, emit(code, config)).unwrap();
+
+        let status = Command::new("as")
+            .arg("-o")
+            .arg(&object)
+            .arg(&source)
+            .status()
+            .ok()?;
+        assert!(status.success());
+        let status = Command::new("ld")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&object)
+            .status()
+            .ok()?;
+        assert!(status.success());
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        Some((output.status.code().unwrap(), output.stdout))
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn run_assembled_program() {
+        let hello = include_str!("../../../../../examples/helloworld.bf");
+
+        let Some((status, output)) = assemble_and_run(hello, MemoryConfig::default(), b"") else {
+            return;
+        };
+        assert_eq!(status, 0);
+        assert_eq!(output, b"Hello World!\n");
+
+        let config = MemoryConfig {
+            cell: Cell::I32,
+            eof: Eof::Zero,
+            ..Default::default()
+        };
+        let (_, output) = assemble_and_run(",[.,]", config, "añ".as_bytes()).unwrap();
+        let expected: String = "añ".bytes().map(char::from).collect();
+        assert_eq!(output, expected.as_bytes());
+
+        // The repeated offset only overflows if the products are added at once.
+        let code = format!("++>{}<[->+<>-<]>.", "+".repeat(126));
+        let (status, output) = assemble_and_run(&code, MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"~");
+
+        // A negative counter overflows before it reaches 0, unless it wraps.
+        for code in ["-[-]", "-[->+<]"] {
+            let (status, _) = assemble_and_run(code, MemoryConfig::default(), b"").unwrap();
+            assert_eq!(status, 1);
+        }
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = assemble_and_run("-[->-<]>.", config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"\x01");
+
+        // Scan to the left and then back to the right, both across more than
+        // 16 cells.
+        let code = format!("{}[<]>.[>]<.", ">+".repeat(20));
+        let (status, output) = assemble_and_run(&code, MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"\x01\x01");
+
+        // Scan beyond the bounds in both directions.
+        let config = MemoryConfig {
+            len: 40,
+            ..Default::default()
+        };
+        let fill = "+>".repeat(39) + "+";
+        let (status, output) =
+            assemble_and_run(&(fill.clone() + ".[<]"), config.clone(), b"").unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(output, b"\x01");
+        let code = format!("{fill}{}[>]", "<".repeat(39));
+        let (status, _) = assemble_and_run(&code, config, b"").unwrap();
+        assert_eq!(status, 1);
+
+        let (status, _) = assemble_and_run(&"+".repeat(128), MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = assemble_and_run(&("-".repeat(191) + "."), config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"A");
+    }
+}
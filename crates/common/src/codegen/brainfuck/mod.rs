@@ -0,0 +1,137 @@
+//! Translate a `SyntaxTree` back into brainfuck.
+//!
+//! `Clear` is expanded into `[-]` and `AddUntilZero` into the loop it's
+//! optimized from, so compiling the generated code gives the same
+//! `InstructionList` again. The comments and the layout of the original code
+//! are lost, and the code is wrapped into lines of `LINE_LEN` characters.
+
+use super::Result;
+use crate::compiler::{AddUntilZeroArg, SyntaxTree};
+
+/// The maximum number of characters in a line.
+pub const LINE_LEN: usize = 80;
+
+pub struct Emitter;
+
+impl Emitter {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn emit(&self, syntax_tree: &SyntaxTree) -> Result<String> {
+        let root = match syntax_tree {
+            SyntaxTree::Root { block } => block,
+            _ => unreachable!(),
+        };
+        let mut code = String::new();
+        Self::emit_block(&mut code, root);
+
+        let mut res = String::with_capacity(code.len() + code.len() / LINE_LEN + 1);
+
+        // All the characters are ASCII.
+        for line in code.as_bytes().chunks(LINE_LEN) {
+            res.push_str(std::str::from_utf8(line).unwrap());
+            res.push('\n');
+        }
+
+        Ok(res)
+    }
+
+    fn emit_block(code: &mut String, block: &[SyntaxTree]) {
+        for node in block {
+            match node {
+                SyntaxTree::Add { val, .. } => Self::emit_add(code, *val as i64),
+                SyntaxTree::Seek { offset, .. } => Self::emit_seek(code, *offset as isize),
+                SyntaxTree::Clear { .. } => code.push_str("[-]"),
+                SyntaxTree::AddUntilZero { target, .. } => {
+                    code.push_str("[-");
+                    let mut current = 0;
+
+                    for AddUntilZeroArg { offset, times } in target {
+                        Self::emit_seek(code, offset - current);
+                        Self::emit_add(code, *times as i64);
+                        current = *offset;
+                    }
+
+                    Self::emit_seek(code, -current);
+                    code.push(']');
+                }
+                SyntaxTree::Input { .. } => code.push(','),
+                SyntaxTree::Output => code.push('.'),
+                SyntaxTree::Loop { block, .. } => {
+                    code.push('[');
+                    Self::emit_block(code, block);
+                    code.push(']');
+                }
+                SyntaxTree::Root { .. } => unreachable!(),
+            }
+        }
+    }
+
+    fn emit_add(code: &mut String, val: i64) {
+        // Keep the adds apart, e.g. the ones to the same cell in `[->+<>-<]`,
+        // or they would be combined by the lexer.
+        if code.ends_with(['+', '-']) {
+            code.push_str("<>");
+        }
+
+        let c = if val > 0 { '+' } else { '-' };
+        code.extend(std::iter::repeat_n(c, val.unsigned_abs() as usize));
+    }
+
+    fn emit_seek(code: &mut String, offset: isize) {
+        let c = if offset > 0 { '>' } else { '<' };
+        code.extend(std::iter::repeat_n(c, offset.unsigned_abs()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Compiler, Optimizer};
+
+    fn emit(code: &str) -> String {
+        let syntax_tree = Compiler::new().parse(code).unwrap();
+        Emitter::new().emit(&syntax_tree).unwrap()
+    }
+
+    #[test]
+    fn emit_program() {
+        assert_eq!(
+            emit("Read a number ,[ and double it: ->++<]>. [-]"),
+            ",[->++<]>.[-]\n"
+        );
+        assert_eq!(
+            emit("+>>[-<<+++>>>-<]<<[.[-]]"),
+            "+>>[-<<+++>>>-<]<<[.[-]]\n"
+        );
+        assert_eq!(emit(",[->+<>-<]>."), ",[->+<>-<]>.\n");
+        assert_eq!(emit(""), "");
+
+        let long = emit(&"+>".repeat(50));
+        assert_eq!(long.lines().map(str::len).collect::<Vec<_>>(), [80, 20]);
+    }
+
+    #[test]
+    fn recompile() {
+        const PROGRAMS: &[&str] = &[
+            include_str!("../../../../../examples/helloworld.bf"),
+            include_str!("../../../../../examples/squares.bf"),
+            include_str!("../../../../../examples/hanoi.bf"),
+            include_str!("../../../../../examples/mandelbrot.bf"),
+            include_str!("../../../../../examples/self-interpreter.bf"),
+            ",[->+<>-<]>.",
+        ];
+
+        for compiler in [Compiler::new(), Compiler::with_optimizer(Optimizer::new())] {
+            for code in PROGRAMS {
+                let syntax_tree = compiler.parse(code).unwrap();
+                let decompiled = Emitter::new().emit(&syntax_tree).unwrap();
+                assert_eq!(
+                    compiler.compile(&decompiled).unwrap(),
+                    compiler.compile(code).unwrap()
+                );
+            }
+        }
+    }
+}
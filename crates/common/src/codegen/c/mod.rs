@@ -0,0 +1,367 @@
+//! Translate an `InstructionList` into a standalone C program.
+//!
+//! The program keeps the same semantics as `Processor` running with the given
+//! `MemoryConfig`, including bounds checks, overflow and EOF handling. Input
+//! is read from `stdin` and output is written to `stdout` as UTF-8 characters.
+
+use super::{check_config, expand, structure, Node, Result, Writer};
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+use crate::execution::memory::config::{Cell, Config as MemoryConfig, Eof, Overflow};
+
+pub struct Emitter {
+    config: MemoryConfig,
+}
+
+impl Emitter {
+    pub fn new(config: MemoryConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn emit(&self, instructions: &InstructionList) -> Result<String> {
+        check_config("c", &self.config)?;
+        let instructions = expand(instructions, &self.config)?;
+        let nodes = structure(&instructions)?;
+        let mut w = Writer::new("    ");
+
+        self.emit_prelude(&mut w);
+        w.line("int main(void) {");
+        w.indent();
+
+        for node in nodes {
+            match node {
+                Node::Instruction { ins, .. } => Self::emit_instruction(&mut w, ins),
+                Node::LoopStart { .. } => {
+                    w.line("while (memory[ptr - LEFT] != 0) {");
+                    w.indent();
+                }
+                Node::LoopEnd { .. } => {
+                    w.dedent();
+                    w.line("}");
+                }
+            }
+        }
+
+        w.line("fflush(stdout);");
+        w.line("return 0;");
+        w.dedent();
+        w.line("}");
+        Ok(w.finish())
+    }
+
+    fn emit_instruction(w: &mut Writer, ins: &Instruction) {
+        match ins {
+            Instruction::Add { val } => w.line(format!("add({val});")),
+            Instruction::Seek { offset } => w.line(format!("seek({offset});")),
+            Instruction::Clear => w.line("clear();"),
+            Instruction::AddUntilZero { target } => {
+                w.line("if (memory[ptr - LEFT] != 0) {");
+                w.indent();
+                w.line("int64_t val = memory[ptr - LEFT];");
+                w.line("clear();");
+
+                for AddUntilZeroArg { offset, times } in target {
+                    w.line(format!("seek({offset});"));
+                    w.line(format!("add(val * {times});"));
+                    w.line(format!("seek({});", -offset));
+                }
+
+                w.dedent();
+                w.line("}");
+            }
+            Instruction::Input => w.line("input();"),
+            Instruction::Output => w.line("output(memory[ptr - LEFT]);"),
+            Instruction::Jump { .. } | Instruction::JumpIfZero { .. } | Instruction::Halt => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn emit_prelude(&self, w: &mut Writer) {
+        let range = self.config.range();
+        let (cell, ucell, min, max) = match self.config.cell {
+            Cell::I8 => ("int8_t", "uint8_t", "INT8_MIN", "INT8_MAX"),
+            Cell::I32 => ("int32_t", "uint32_t", "INT32_MIN", "INT32_MAX"),
+            _ => unreachable!(),
+        };
+
+        w.line("/* Generated from a brainfuck program by brainfuck-interpreter. */");
+        w.line("#include <stdint.h>");
+        w.line("#include <stdio.h>");
+        w.line("#include <stdlib.h>");
+        w.line("");
+        w.line(format!("typedef {cell} cell;"));
+        w.line(format!("typedef {ucell} ucell;"));
+        w.line("");
+        w.line(format!("#define CELL_MIN {min}"));
+        w.line(format!("#define CELL_MAX {max}"));
+        w.line(format!("#define LEFT {}L", range.left));
+        w.line(format!("#define RIGHT {}L", range.right));
+        w.line("");
+        w.raw(COMMON);
+        w.line("");
+
+        w.line("/* Store `before + add` to the current cell, handling overflow. */");
+        w.line("static void store(int64_t before, int64_t add) {");
+        w.indent();
+        w.line("int64_t val = before + add;");
+
+        match self.config.overflow {
+            Overflow::Error => {
+                w.line("if (val < CELL_MIN || val > CELL_MAX) {");
+                w.indent();
+                w.line("if (add == 0) {");
+                w.line("    fprintf(stderr, \"error: %lld will overflow\\n\", (long long)val);");
+                w.line("} else {");
+                w.line("    fprintf(stderr, \"error: %lld + %lld will overflow\\n\",");
+                w.line("            (long long)before, (long long)add);");
+                w.line("}");
+                w.line("fail();");
+                w.dedent();
+                w.line("}");
+                w.line("");
+                w.line("memory[ptr - LEFT] = (cell)val;");
+            }
+            Overflow::Wrap => w.line("memory[ptr - LEFT] = (cell)(ucell)(uint64_t)val;"),
+            _ => unreachable!(),
+        }
+
+        w.dedent();
+        w.line("}");
+        w.line("");
+        w.line("static void add(int64_t add) {");
+        w.line("    store(memory[ptr - LEFT], add);");
+        w.line("}");
+        w.line("");
+        w.line("/* Clear the current cell as if it's counted down to 0. */");
+        w.line("static void clear(void) {");
+        w.indent();
+
+        // A negative cell overflows before it reaches 0.
+        if let Overflow::Error = self.config.overflow {
+            w.line("if (memory[ptr - LEFT] < 0) {");
+            w.line("    store(CELL_MIN, -1);");
+            w.line("}");
+            w.line("");
+        }
+
+        w.line("memory[ptr - LEFT] = 0;");
+        w.dedent();
+        w.line("}");
+        w.line("");
+        w.line("static void input(void) {");
+        w.indent();
+        w.line("int c = getchar();");
+        w.line("");
+
+        match self.config.eof {
+            Eof::Zero => w.line("store(c == EOF ? 0 : c, 0);"),
+            Eof::Keep => w.line("store(c == EOF ? -1 : c, 0);"),
+            Eof::Ignore => {
+                w.line("if (c != EOF) {");
+                w.line("    store(c, 0);");
+                w.line("}");
+            }
+            _ => unreachable!(),
+        }
+
+        w.dedent();
+        w.line("}");
+        w.line("");
+    }
+}
+
+/// The part of the prelude which doesn't depend on the config.
+const COMMON: &str = r#"static cell memory[RIGHT - LEFT + 1];
+static long ptr = 0;
+
+_Noreturn static void fail(void) {
+    fflush(stdout);
+    exit(1);
+}
+
+static void seek(long offset) {
+    long target = ptr + offset;
+
+    if (target < LEFT || target > RIGHT) {
+        fprintf(stderr, "error: try to seek pointer from %ld to %ld, which is out of [%ld, %ld]\n",
+                ptr, target, LEFT, RIGHT);
+        fail();
+    }
+
+    ptr = target;
+}
+
+/* Write the cell as a Unicode scalar value encoded in UTF-8. */
+static void output(int64_t val) {
+    uint32_t c = (uint32_t)val;
+
+    if (c > 0x10FFFF || (c >= 0xD800 && c <= 0xDFFF)) {
+        c = 0xFFFD;
+    }
+
+    if (c < 0x80) {
+        putchar(c);
+    } else if (c < 0x800) {
+        putchar(0xC0 | c >> 6);
+        putchar(0x80 | (c & 0x3F));
+    } else if (c < 0x10000) {
+        putchar(0xE0 | c >> 12);
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    } else {
+        putchar(0xF0 | c >> 18);
+        putchar(0x80 | (c >> 12 & 0x3F));
+        putchar(0x80 | (c >> 6 & 0x3F));
+        putchar(0x80 | (c & 0x3F));
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::execution::memory::config::{Addr, Storage};
+
+    fn emit(code: &str, config: MemoryConfig) -> String {
+        let instructions = Compiler::new().compile(code).unwrap();
+        Emitter::new(config).emit(&instructions).unwrap()
+    }
+
+    #[test]
+    fn emit_program() {
+        let code = emit(",[->++<]>.", MemoryConfig::default());
+        let main = "int main(void) {
+    input();
+    if (memory[ptr - LEFT] != 0) {
+        int64_t val = memory[ptr - LEFT];
+        clear();
+        seek(1);
+        add(val * 2);
+        seek(-1);
+    }
+    seek(1);
+    output(memory[ptr - LEFT]);
+    fflush(stdout);
+    return 0;
+}
+";
+        assert!(code.ends_with(main));
+        assert!(code.contains("typedef int8_t cell;"));
+        assert!(code.contains("#define RIGHT 32767L"));
+    }
+
+    #[test]
+    fn emit_with_config() {
+        let config = MemoryConfig {
+            len: 10,
+            addr: Addr::Signed,
+            cell: Cell::I32,
+            overflow: Overflow::Wrap,
+            eof: Eof::Zero,
+            storage: Storage::Dense,
+            init: None,
+        };
+        let code = emit("+", config);
+        assert!(code.contains("typedef int32_t cell;"));
+        assert!(code.contains("#define LEFT -5L"));
+        assert!(code.contains("#define RIGHT 4L"));
+        assert!(code.contains("(cell)(ucell)(uint64_t)val"));
+        assert!(code.contains("store(c == EOF ? 0 : c, 0);"));
+    }
+
+    /// Compile the program with the system C compiler and run it, or return
+    /// `None` if there is no C compiler.
+    fn compile_and_run(code: &str, config: MemoryConfig, input: &[u8]) -> Option<(i32, Vec<u8>)> {
+        let dir = std::env::temp_dir().join(format!("bf-codegen-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join(format!("{:x}.c", hash(code)));
+        let binary = source.with_extension("out");
+        std::fs::write(&source, emit(code, config)).unwrap();
+
+        let status = Command::new("cc")
+            .arg("-O1")
+            .arg("-o")
+            .arg(&binary)
+            .arg(&source)
+            .status()
+            .ok()?;
+        assert!(status.success());
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        Some((output.status.code().unwrap(), output.stdout))
+    }
+
+    fn hash(code: &str) -> u64 {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        code.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn run_compiled_program() {
+        let hello = include_str!("../../../../../examples/helloworld.bf");
+
+        let Some((status, output)) = compile_and_run(hello, MemoryConfig::default(), b"") else {
+            return;
+        };
+        assert_eq!(status, 0);
+        assert_eq!(output, b"Hello World!\n");
+
+        // Every byte is printed as the character with the same code point.
+        let config = MemoryConfig {
+            cell: Cell::I32,
+            eof: Eof::Zero,
+            ..Default::default()
+        };
+        let (_, output) = compile_and_run(",[.,]", config, "añ".as_bytes()).unwrap();
+        let expected: String = "añ".bytes().map(char::from).collect();
+        assert_eq!(output, expected.as_bytes());
+
+        let (status, _) = compile_and_run("<", MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+
+        let (status, _) = compile_and_run(&"+".repeat(128), MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+
+        // The repeated offset only overflows if the products are added at once.
+        let code = format!("++>{}<[->+<>-<]>.", "+".repeat(126));
+        let (status, output) = compile_and_run(&code, MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"~");
+
+        // A negative counter overflows before it reaches 0, unless it wraps.
+        for code in ["-[-]", "-[->+<]"] {
+            let (status, _) = compile_and_run(code, MemoryConfig::default(), b"").unwrap();
+            assert_eq!(status, 1);
+        }
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = compile_and_run("-[->-<]>.", config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"\x01");
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = compile_and_run(&("+".repeat(321) + "."), config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, "A".as_bytes());
+    }
+}
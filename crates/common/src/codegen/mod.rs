@@ -0,0 +1,408 @@
+//! Backends translating a compiled brainfuck program into other languages.
+
+pub mod asm;
+pub mod brainfuck;
+pub mod c;
+pub mod rust;
+pub mod wat;
+
+use std::borrow::Cow;
+
+use snafu::prelude::*;
+
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList, SyntaxTree};
+use crate::execution::memory::config::{Addr, Cell, Config as MemoryConfig, Eof, Overflow};
+
+pub type Result<T> = std::result::Result<T, CodegenError>;
+
+#[derive(Snafu, Debug, PartialEq, Eq)]
+pub enum CodegenError {
+    #[snafu(display("the {backend} backend doesn't support {feature}"))]
+    Unsupported {
+        backend: &'static str,
+        feature: String,
+    },
+    #[snafu(display("instruction {addr} doesn't form a well-nested loop"))]
+    Unstructured { addr: usize },
+}
+
+/// Check that the generated code can work like the memory built from the
+/// config, which is always an empty fixed-size tape of `i8` or `i32` cells
+/// which either wrap or report an error on overflow, and reads EOF as 0 or -1
+/// or ignores it.
+pub(crate) fn check_config(backend: &'static str, config: &MemoryConfig) -> Result<()> {
+    let feature = match config.addr {
+        _ if config.init.is_some() => "a preloaded tape".to_string(),
+        Addr::Dynamic { .. } => "a dynamically growing tape".to_string(),
+        Addr::Circular => "a circular tape".to_string(),
+        Addr::Unsigned | Addr::Signed => match (&config.cell, &config.overflow, &config.eof) {
+            (Cell::I8 | Cell::I32, Overflow::Error | Overflow::Wrap, eof) => match eof {
+                Eof::Zero | Eof::Keep | Eof::Ignore => return Ok(()),
+                Eof::Value(val) => format!("turning EOF to {val}"),
+                Eof::Error => "reporting EOF as an error".to_string(),
+            },
+            (Cell::I8 | Cell::I32, overflow, _) => format!("{overflow:?} overflow"),
+            (cell, ..) => format!("{cell:?} cells"),
+        },
+    };
+    UnsupportedSnafu { backend, feature }.fail()
+}
+
+/// Return whether an `AddUntilZero` has to be run as the loop it's optimized
+/// from, which is the case when it adds to a cell more than once in every
+/// iteration and the cells don't wrap. The overflow is then checked in each
+/// iteration like `Processor` does, instead of once for the products.
+fn runs_as_loop(config: &MemoryConfig, target: &[AddUntilZeroArg]) -> bool {
+    !matches!(config.overflow, Overflow::Wrap) && AddUntilZeroArg::repeats_offset(target)
+}
+
+/// Turn the `AddUntilZero`s which [`runs_as_loop`] back into loops.
+pub(crate) fn expand<'a>(
+    instructions: &'a InstructionList,
+    config: &MemoryConfig,
+) -> Result<Cow<'a, InstructionList>> {
+    let expanded = |ins: &Instruction| matches!(ins, Instruction::AddUntilZero { target } if runs_as_loop(config, target));
+
+    if !instructions.0.iter().any(expanded) {
+        return Ok(Cow::Borrowed(instructions));
+    }
+
+    // The jumps can only be moved along with their targets in a well-nested
+    // list.
+    structure(instructions)?;
+
+    let list = &instructions.0;
+    // The address of every instruction in the expanded list.
+    let mut addrs = Vec::with_capacity(list.len());
+    let mut len = 0;
+
+    for ins in list {
+        addrs.push(len);
+        len += match ins {
+            // `JumpIfZero`, `Add`, the targets and `Jump`.
+            Instruction::AddUntilZero { target } if expanded(ins) => 3 + 3 * target.len(),
+            _ => 1,
+        };
+    }
+
+    let mut res = Vec::with_capacity(len);
+
+    for ins in list {
+        match ins {
+            Instruction::AddUntilZero { target } if expanded(ins) => {
+                let start = res.len();
+                res.push(Instruction::JumpIfZero {
+                    target: start + 3 + 3 * target.len(),
+                });
+                res.push(Instruction::Add { val: -1 });
+
+                for AddUntilZeroArg { offset, times } in target {
+                    res.push(Instruction::Seek { offset: *offset });
+                    res.push(Instruction::Add { val: *times });
+                    res.push(Instruction::Seek { offset: -offset });
+                }
+
+                res.push(Instruction::Jump { target: start });
+            }
+            Instruction::Jump { target } => res.push(Instruction::Jump {
+                target: addrs[*target],
+            }),
+            Instruction::JumpIfZero { target } => res.push(Instruction::JumpIfZero {
+                target: addrs[*target],
+            }),
+            ins => res.push(ins.clone()),
+        }
+    }
+
+    Ok(Cow::Owned(InstructionList(res)))
+}
+
+/// Turn the `AddUntilZero`s which [`runs_as_loop`] back into loops.
+pub(crate) fn expand_tree<'a>(tree: &'a SyntaxTree, config: &MemoryConfig) -> Cow<'a, SyntaxTree> {
+    fn any(block: &[SyntaxTree], config: &MemoryConfig) -> bool {
+        block.iter().any(|node| match node {
+            SyntaxTree::AddUntilZero { target, .. } => runs_as_loop(config, target),
+            SyntaxTree::Loop { block, .. } | SyntaxTree::Root { block } => any(block, config),
+            _ => false,
+        })
+    }
+
+    fn map(block: &[SyntaxTree], config: &MemoryConfig) -> Vec<SyntaxTree> {
+        block
+            .iter()
+            .map(|node| match node {
+                SyntaxTree::AddUntilZero { target, span } if runs_as_loop(config, target) => {
+                    let mut block = vec![SyntaxTree::Add {
+                        val: -1,
+                        span: *span,
+                    }];
+
+                    for AddUntilZeroArg { offset, times } in target {
+                        block.push(SyntaxTree::Seek {
+                            offset: *offset as i32,
+                            span: *span,
+                        });
+                        block.push(SyntaxTree::Add {
+                            val: *times,
+                            span: *span,
+                        });
+                        block.push(SyntaxTree::Seek {
+                            offset: -*offset as i32,
+                            span: *span,
+                        });
+                    }
+
+                    SyntaxTree::Loop { block, span: *span }
+                }
+                SyntaxTree::Loop { block, span } => SyntaxTree::Loop {
+                    block: map(block, config),
+                    span: *span,
+                },
+                node => node.clone(),
+            })
+            .collect()
+    }
+
+    match tree {
+        SyntaxTree::Root { block } if any(block, config) => Cow::Owned(SyntaxTree::Root {
+            block: map(block, config),
+        }),
+        tree => Cow::Borrowed(tree),
+    }
+}
+
+/// A buffer of generated code which keeps track of the indentation.
+pub(crate) struct Writer {
+    code: String,
+    indent: usize,
+    unit: &'static str,
+}
+
+impl Writer {
+    pub(crate) fn new(unit: &'static str) -> Self {
+        Self {
+            code: String::new(),
+            indent: 0,
+            unit,
+        }
+    }
+
+    /// Append a line with the current indentation, or an empty line.
+    pub(crate) fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+
+        if !line.is_empty() {
+            for _ in 0..self.indent {
+                self.code.push_str(self.unit);
+            }
+        }
+
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+
+    /// Append some lines verbatim.
+    pub(crate) fn raw(&mut self, text: &str) {
+        self.code.push_str(text);
+    }
+
+    pub(crate) fn indent(&mut self) {
+        self.indent += 1;
+    }
+
+    pub(crate) fn dedent(&mut self) {
+        self.indent -= 1;
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.code
+    }
+}
+
+/// An instruction list viewed as nested loops, which is what most of the
+/// target languages expect.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Node<'a> {
+    /// Any instruction other than `Jump`, `JumpIfZero` and `Halt`.
+    Instruction { addr: usize, ins: &'a Instruction },
+    /// The `JumpIfZero` starting a loop.
+    LoopStart { addr: usize, end: usize },
+    /// The `Jump` closing a loop.
+    LoopEnd { addr: usize, start: usize },
+}
+
+/// Check that every `JumpIfZero` is paired with a `Jump` jumping back to it,
+/// the loops are well nested and the list ends with its only `Halt`, and then
+/// flatten the list into `Node`s.
+pub(crate) fn structure(instructions: &InstructionList) -> Result<Vec<Node<'_>>> {
+    let list = &instructions.0;
+    let mut nodes = Vec::with_capacity(list.len());
+    let mut starts = vec![];
+
+    for (addr, ins) in list.iter().enumerate() {
+        match ins {
+            Instruction::JumpIfZero { target } => {
+                let end = target.wrapping_sub(1);
+                let paired = *target > addr + 1
+                    && matches!(list.get(end), Some(Instruction::Jump { target }) if *target == addr);
+                ensure!(paired, UnstructuredSnafu { addr });
+                starts.push(end);
+                nodes.push(Node::LoopStart { addr, end });
+            }
+            Instruction::Jump { target } => {
+                ensure!(starts.pop() == Some(addr), UnstructuredSnafu { addr });
+                nodes.push(Node::LoopEnd {
+                    addr,
+                    start: *target,
+                });
+            }
+            Instruction::Halt => {
+                ensure!(
+                    addr + 1 == list.len() && starts.is_empty(),
+                    UnstructuredSnafu { addr }
+                );
+            }
+            ins => nodes.push(Node::Instruction { addr, ins }),
+        }
+    }
+
+    ensure!(
+        list.last() == Some(&Instruction::Halt),
+        UnstructuredSnafu { addr: list.len() }
+    );
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Assembler, Compiler};
+    use crate::execution::memory::config::Init;
+
+    #[test]
+    fn structure_compiled_program() {
+        let instructions = Compiler::new().compile(",[.[,]]").unwrap();
+        let nodes = structure(&instructions).unwrap();
+        let expected = vec![
+            Node::Instruction {
+                addr: 0,
+                ins: &Instruction::Input,
+            },
+            Node::LoopStart { addr: 1, end: 6 },
+            Node::Instruction {
+                addr: 2,
+                ins: &Instruction::Output,
+            },
+            Node::LoopStart { addr: 3, end: 5 },
+            Node::Instruction {
+                addr: 4,
+                ins: &Instruction::Input,
+            },
+            Node::LoopEnd { addr: 5, start: 3 },
+            Node::LoopEnd { addr: 6, start: 1 },
+        ];
+        assert_eq!(nodes, expected);
+    }
+
+    #[test]
+    fn expand_repeated_offsets() {
+        let instructions = Assembler::new()
+            .assemble("L0:\njz L4\nmul [1:1, 1:-1]\nmul [1:2]\njump L0\nL4:\nhalt")
+            .unwrap();
+        let wrap = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..MemoryConfig::default()
+        };
+        assert!(matches!(expand(&instructions, &wrap), Ok(Cow::Borrowed(_))));
+
+        let expected = InstructionList(vec![
+            Instruction::JumpIfZero { target: 12 },
+            Instruction::JumpIfZero { target: 10 },
+            Instruction::Add { val: -1 },
+            Instruction::Seek { offset: 1 },
+            Instruction::Add { val: 1 },
+            Instruction::Seek { offset: -1 },
+            Instruction::Seek { offset: 1 },
+            Instruction::Add { val: -1 },
+            Instruction::Seek { offset: -1 },
+            Instruction::Jump { target: 1 },
+            Instruction::AddUntilZero {
+                target: vec![AddUntilZeroArg::new(1, 2)],
+            },
+            Instruction::Jump { target: 0 },
+            Instruction::Halt,
+        ]);
+        let expanded = expand(&instructions, &MemoryConfig::default()).unwrap();
+        assert_eq!(expanded.as_ref(), &expected);
+        assert_eq!(expanded.verify(), Ok(()));
+    }
+
+    #[test]
+    fn unsupported_config() {
+        let compiler = Compiler::new();
+        let instructions = compiler.compile("+[>+]").unwrap();
+        let syntax_tree = compiler.parse("+[>+]").unwrap();
+
+        let configs = [
+            MemoryConfig {
+                addr: Addr::Dynamic { max: None },
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                addr: Addr::Circular,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                cell: Cell::U8,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                overflow: Overflow::Saturate,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                eof: Eof::Value(255),
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                init: Some(Init {
+                    addr: 0,
+                    data: b"bf".to_vec(),
+                }),
+                ..MemoryConfig::default()
+            },
+        ];
+
+        for config in configs {
+            for res in [
+                c::Emitter::new(config.clone()).emit(&instructions),
+                rust::Emitter::new(config.clone(), rust::Form::Program).emit(&syntax_tree),
+                wat::Emitter::new(config.clone()).emit(&instructions),
+                asm::Emitter::new(config).emit(&instructions),
+            ] {
+                assert!(matches!(res, Err(CodegenError::Unsupported { .. })));
+            }
+        }
+    }
+
+    #[test]
+    fn structure_invalid_program() {
+        let instructions = InstructionList(vec![
+            Instruction::JumpIfZero { target: 3 },
+            Instruction::JumpIfZero { target: 4 },
+            Instruction::Jump { target: 0 },
+            Instruction::Jump { target: 1 },
+            Instruction::Halt,
+        ]);
+        assert_eq!(
+            structure(&instructions),
+            Err(CodegenError::Unstructured { addr: 2 })
+        );
+
+        let instructions = InstructionList(vec![Instruction::Output]);
+        assert_eq!(
+            structure(&instructions),
+            Err(CodegenError::Unstructured { addr: 1 })
+        );
+    }
+}
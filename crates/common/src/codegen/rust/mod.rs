@@ -0,0 +1,534 @@
+//! Translate a `SyntaxTree` into Rust source code.
+//!
+//! The generated code uses no `unsafe` and keeps the same semantics as
+//! `Processor` running with the given `MemoryConfig`. It exposes
+//! `run(input, output)`, and a program additionally has a `main` running it
+//! with `stdin` and `stdout`.
+
+use super::{check_config, expand_tree, Result, Writer};
+use crate::compiler::{AddUntilZeroArg, SyntaxTree};
+use crate::execution::memory::config::{Cell, Config as MemoryConfig, Eof, Overflow};
+
+/// The maximum number of statements a loop may have before it's moved into a
+/// function.
+const MAX_INLINE_SIZE: usize = 16;
+
+/// The maximum number of statements emitted directly into a function.
+const MAX_FUNCTION_LEN: usize = 128;
+
+/// What kind of Rust source file to generate.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Form {
+    /// A binary crate root with a `main` function.
+    Program,
+    /// A module to be embedded into another crate.
+    Module,
+}
+
+pub struct Emitter {
+    config: MemoryConfig,
+    form: Form,
+}
+
+impl Emitter {
+    pub fn new(config: MemoryConfig, form: Form) -> Self {
+        Self { config, form }
+    }
+
+    pub fn emit(&self, syntax_tree: &SyntaxTree) -> Result<String> {
+        check_config("rust", &self.config)?;
+        let syntax_tree = expand_tree(syntax_tree, &self.config);
+        let root = match syntax_tree.as_ref() {
+            SyntaxTree::Root { block } => block,
+            _ => unreachable!(),
+        };
+        let mut w = Writer::new("    ");
+        let mut functions = vec![];
+
+        let mut body = Writer::new("    ");
+        body.indent();
+        Self::emit_body(&mut body, root, &mut functions);
+
+        self.emit_prelude(&mut w);
+
+        for function in functions {
+            w.raw(&function);
+            w.line("");
+        }
+
+        w.line("fn execute(m: &mut Machine) -> Result<(), Error> {");
+        w.raw(&body.finish());
+        w.line("    Ok(())");
+        w.line("}");
+        w.line("");
+        w.raw(RUN);
+
+        if self.form == Form::Program {
+            w.line("");
+            w.raw(MAIN);
+        }
+
+        Ok(w.finish())
+    }
+
+    /// Emit the body of a function, which is split into several functions if
+    /// it's too long.
+    fn emit_body(w: &mut Writer, block: &[SyntaxTree], functions: &mut Vec<String>) {
+        if block.len() <= MAX_FUNCTION_LEN {
+            Self::emit_block(w, block, functions);
+            return;
+        }
+
+        for chunk in block.chunks(MAX_FUNCTION_LEN) {
+            let mut body = Writer::new("    ");
+            body.indent();
+            Self::emit_block(&mut body, chunk, functions);
+
+            let name = format!("block_{}", functions.len());
+            let mut f = Writer::new("    ");
+            f.line(format!(
+                "fn {name}(m: &mut Machine) -> Result<(), Error> {{"
+            ));
+            f.raw(&body.finish());
+            f.line("    Ok(())");
+            f.line("}");
+            functions.push(f.finish());
+            w.line(format!("{name}(m)?;"));
+        }
+    }
+
+    /// Emit the statements of a block. The large loops are moved into their
+    /// own functions, since `rustc` takes a very long time to compile a huge
+    /// function.
+    fn emit_block(w: &mut Writer, block: &[SyntaxTree], functions: &mut Vec<String>) {
+        for node in block {
+            match node {
+                SyntaxTree::Add { val, .. } => w.line(format!("m.add({val})?;")),
+                SyntaxTree::Seek { offset, .. } => w.line(format!("m.seek({offset})?;")),
+                SyntaxTree::Clear { .. } => w.line("m.clear()?;"),
+                SyntaxTree::AddUntilZero { target, .. } => {
+                    w.line("if m.get() != 0 {");
+                    w.indent();
+                    w.line("let val = m.get();");
+                    w.line("m.clear()?;");
+
+                    for AddUntilZeroArg { offset, times } in target {
+                        w.line(format!("m.seek({offset})?;"));
+                        w.line(format!("m.add(val * {times})?;"));
+                        w.line(format!("m.seek({})?;", -offset));
+                    }
+
+                    w.dedent();
+                    w.line("}");
+                }
+                SyntaxTree::Input { .. } => w.line("m.input()?;"),
+                SyntaxTree::Output => w.line("m.output()?;"),
+                SyntaxTree::Loop { block, .. } if Self::size(block) > MAX_INLINE_SIZE => {
+                    let mut body = Writer::new("    ");
+                    body.indent();
+                    body.indent();
+                    Self::emit_body(&mut body, block, functions);
+
+                    let name = format!("loop_{}", functions.len());
+                    let mut f = Writer::new("    ");
+                    f.line(format!(
+                        "fn {name}(m: &mut Machine) -> Result<(), Error> {{"
+                    ));
+                    f.line("    while m.get() != 0 {");
+                    f.raw(&body.finish());
+                    f.line("    }");
+                    f.line("    Ok(())");
+                    f.line("}");
+                    functions.push(f.finish());
+                    w.line(format!("{name}(m)?;"));
+                }
+                SyntaxTree::Loop { block, .. } => {
+                    w.line("while m.get() != 0 {");
+                    w.indent();
+                    Self::emit_block(w, block, functions);
+                    w.dedent();
+                    w.line("}");
+                }
+                SyntaxTree::Root { .. } => unreachable!(),
+            }
+        }
+    }
+
+    /// Count the statements of a block which will be emitted inline.
+    fn size(block: &[SyntaxTree]) -> usize {
+        block
+            .iter()
+            .map(|node| match node {
+                SyntaxTree::Loop { block, .. } => {
+                    let size = Self::size(block);
+                    if size > MAX_INLINE_SIZE {
+                        1
+                    } else {
+                        1 + size
+                    }
+                }
+                _ => 1,
+            })
+            .sum()
+    }
+
+    fn emit_prelude(&self, w: &mut Writer) {
+        let range = self.config.range();
+        let cell = match self.config.cell {
+            Cell::I8 => "i8",
+            Cell::I32 => "i32",
+            _ => unreachable!(),
+        };
+        let checked = matches!(self.config.overflow, Overflow::Error);
+
+        w.line("//! Generated from a brainfuck program by brainfuck-interpreter.");
+        w.line("");
+        w.line("#![forbid(unsafe_code)]");
+        w.line("// Not every program uses all the operations.");
+        w.line("#![allow(dead_code)]");
+        w.line("");
+        w.line("use std::fmt::{Display, Formatter};");
+        w.line("use std::io::{Read, Write};");
+        w.line("");
+        w.line(format!("type Cell = {cell};"));
+        w.line("");
+        w.line(format!("const LEFT: isize = {};", range.left));
+        w.line(format!("const RIGHT: isize = {};", range.right));
+        w.line("");
+
+        w.line("#[derive(Debug)]");
+        w.line("pub enum Error {");
+        w.line("    Seek { from: isize, to: isize },");
+
+        if checked {
+            w.line("    AddOverflow { before: i64, add: i64 },");
+            w.line("    SetOverflow { val: i64 },");
+        }
+
+        w.line("    Io(std::io::Error),");
+        w.line("}");
+        w.line("");
+        w.line("impl Display for Error {");
+        w.line("    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {");
+        w.line("        match self {");
+        w.line("            Error::Seek { from, to } => write!(");
+        w.line("                f,");
+        w.line("                \"try to seek pointer from {from} to {to}, which is out of [{LEFT}, {RIGHT}]\"");
+        w.line("            ),");
+
+        if checked {
+            w.line("            Error::AddOverflow { before, add } => write!(f, \"{before} + {add} will overflow\"),");
+            w.line("            Error::SetOverflow { val } => write!(f, \"{val} will overflow\"),");
+        }
+
+        w.line("            Error::Io(e) => write!(f, \"{e}\"),");
+        w.line("        }");
+        w.line("    }");
+        w.line("}");
+        w.line("");
+        w.raw(MACHINE);
+        w.line("");
+
+        w.line("    fn add(&mut self, add: i64) -> Result<(), Error> {");
+
+        if checked {
+            w.line("        let before = self.get();");
+            w.line("        let val = before + add;");
+            w.line("");
+            w.line("        if val < Cell::MIN as i64 || val > Cell::MAX as i64 {");
+            w.line("            return Err(Error::AddOverflow { before, add });");
+            w.line("        }");
+            w.line("");
+            w.line("        self.memory[(self.ptr - LEFT) as usize] = val as Cell;");
+        } else {
+            w.line("        let val = self.get() + add;");
+            w.line("        self.memory[(self.ptr - LEFT) as usize] = val as Cell;");
+        }
+
+        w.line("        Ok(())");
+        w.line("    }");
+        w.line("");
+        w.line("    fn set(&mut self, val: i64) -> Result<(), Error> {");
+
+        if checked {
+            w.line("        if val < Cell::MIN as i64 || val > Cell::MAX as i64 {");
+            w.line("            return Err(Error::SetOverflow { val });");
+            w.line("        }");
+            w.line("");
+        }
+
+        w.line("        self.memory[(self.ptr - LEFT) as usize] = val as Cell;");
+        w.line("        Ok(())");
+        w.line("    }");
+        w.line("");
+        w.line("    /// Clear the current cell as if it's counted down to 0.");
+        w.line("    fn clear(&mut self) -> Result<(), Error> {");
+
+        // A negative cell overflows before it reaches 0.
+        if checked {
+            w.line("        if self.get() < 0 {");
+            w.line(
+                "            return Err(Error::AddOverflow { before: Cell::MIN as i64, add: -1 });",
+            );
+            w.line("        }");
+            w.line("");
+        }
+
+        w.line("        self.memory[(self.ptr - LEFT) as usize] = 0;");
+        w.line("        Ok(())");
+        w.line("    }");
+        w.line("");
+        w.line("    fn input(&mut self) -> Result<(), Error> {");
+        w.line("        let mut buf = [0; 1];");
+        w.line("        let val = match self.input.read(&mut buf) {");
+        w.line("            Ok(0) | Err(_) => -1,");
+        w.line("            Ok(_) => buf[0] as i64,");
+        w.line("        };");
+        w.line("");
+
+        match self.config.eof {
+            Eof::Zero => w.line("        self.set(if val == -1 { 0 } else { val })"),
+            Eof::Keep => w.line("        self.set(val)"),
+            Eof::Ignore => {
+                w.line("        if val != -1 {");
+                w.line("            self.set(val)?;");
+                w.line("        }");
+                w.line("");
+                w.line("        Ok(())");
+            }
+            _ => unreachable!(),
+        }
+
+        w.line("    }");
+        w.line("}");
+        w.line("");
+    }
+}
+
+/// The part of `Machine` which doesn't depend on the config.
+const MACHINE: &str = r#"impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Machine<'a> {
+    memory: Vec<Cell>,
+    ptr: isize,
+    input: &'a mut dyn Read,
+    output: &'a mut dyn Write,
+}
+
+impl Machine<'_> {
+    fn get(&self) -> i64 {
+        self.memory[(self.ptr - LEFT) as usize] as i64
+    }
+
+    fn seek(&mut self, offset: isize) -> Result<(), Error> {
+        let to = self.ptr + offset;
+
+        if !(LEFT..=RIGHT).contains(&to) {
+            return Err(Error::Seek { from: self.ptr, to });
+        }
+
+        self.ptr = to;
+        Ok(())
+    }
+
+    fn output(&mut self) -> Result<(), Error> {
+        let c = char::from_u32(self.get() as u32).unwrap_or('\u{FFFD}');
+        write!(self.output, "{c}")?;
+        Ok(())
+    }
+"#;
+
+const RUN: &str = r#"/// Run the program, reading bytes from `input` and writing UTF-8 characters
+/// to `output`.
+pub fn run(input: &mut dyn Read, output: &mut dyn Write) -> Result<(), Error> {
+    let mut m = Machine {
+        memory: vec![0; (RIGHT - LEFT + 1) as usize],
+        ptr: 0,
+        input,
+        output,
+    };
+    let result = execute(&mut m);
+    m.output.flush()?;
+    result
+}
+"#;
+
+const MAIN: &str = r#"fn main() {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+
+    if let Err(e) = run(&mut input, &mut output) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+}
+"#;
+
+#[cfg(test)]
+mod tests {
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::execution::memory::config::{Addr, Storage};
+
+    fn emit(code: &str, config: MemoryConfig, form: Form) -> String {
+        let syntax_tree = Compiler::new().parse(code).unwrap();
+        Emitter::new(config, form).emit(&syntax_tree).unwrap()
+    }
+
+    #[test]
+    fn emit_program() {
+        let code = emit(",[[->++<]>.<,]", MemoryConfig::default(), Form::Program);
+        let execute = "fn execute(m: &mut Machine) -> Result<(), Error> {
+    m.input()?;
+    while m.get() != 0 {
+        if m.get() != 0 {
+            let val = m.get();
+            m.clear()?;
+            m.seek(1)?;
+            m.add(val * 2)?;
+            m.seek(-1)?;
+        }
+        m.seek(1)?;
+        m.output()?;
+        m.seek(-1)?;
+        m.input()?;
+    }
+    Ok(())
+}
+";
+        assert!(code.contains(execute));
+        assert!(code.contains("#![forbid(unsafe_code)]"));
+        assert!(code.contains("type Cell = i8;"));
+        assert!(code.contains("fn main() {"));
+    }
+
+    #[test]
+    fn emit_module() {
+        let config = MemoryConfig {
+            len: 10,
+            addr: Addr::Signed,
+            cell: Cell::I32,
+            overflow: Overflow::Wrap,
+            eof: Eof::Ignore,
+            storage: Storage::Dense,
+            init: None,
+        };
+        let code = emit("+", config, Form::Module);
+        assert!(code.contains("type Cell = i32;"));
+        assert!(code.contains("const LEFT: isize = -5;"));
+        assert!(code.contains("const RIGHT: isize = 4;"));
+        assert!(code.contains("pub fn run("));
+        assert!(!code.contains("AddOverflow"));
+        assert!(!code.contains("fn main()"));
+    }
+
+    #[test]
+    fn emit_large_blocks() {
+        let code = format!("+[{}-]", ">+".repeat(MAX_INLINE_SIZE));
+        let code = emit(&code, MemoryConfig::default(), Form::Module);
+        assert!(code.contains("fn loop_0(m: &mut Machine) -> Result<(), Error> {"));
+        assert!(code.contains("    loop_0(m)?;"));
+
+        let code = ">+".repeat(MAX_FUNCTION_LEN);
+        let code = emit(&code, MemoryConfig::default(), Form::Module);
+        assert!(code.contains("fn block_0(m: &mut Machine) -> Result<(), Error> {"));
+        assert!(code.contains("    block_1(m)?;"));
+        assert!(!code.contains("block_2"));
+    }
+
+    /// Compile the program with `rustc` and run it, or return `None` if
+    /// `rustc` isn't available.
+    fn compile_and_run(code: &str, config: MemoryConfig, input: &[u8]) -> Option<(i32, Vec<u8>)> {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("bf-codegen-rust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("main.rs");
+        let binary = dir.join("main");
+        std::fs::write(&source, emit(code, config, Form::Program)).unwrap();
+
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+        let output = Command::new(rustc)
+            .args(["--edition", "2021", "-D", "warnings", "-o"])
+            .arg(&binary)
+            .arg(&source)
+            .output()
+            .ok()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        Some((output.status.code().unwrap(), output.stdout))
+    }
+
+    #[test]
+    fn run_compiled_program() {
+        let hello = include_str!("../../../../../examples/helloworld.bf");
+
+        let Some((status, output)) = compile_and_run(hello, MemoryConfig::default(), b"") else {
+            return;
+        };
+        assert_eq!(status, 0);
+        assert_eq!(output, b"Hello World!\n");
+
+        let config = MemoryConfig {
+            cell: Cell::I32,
+            eof: Eof::Zero,
+            ..Default::default()
+        };
+        let (_, output) = compile_and_run(",[.,]", config, "añ".as_bytes()).unwrap();
+        let expected: String = "añ".bytes().map(char::from).collect();
+        assert_eq!(output, expected.as_bytes());
+
+        let (status, output) = compile_and_run(".<", MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(output, b"\0");
+
+        // The repeated offset only overflows if the products are added at once.
+        let code = format!("++>{}<[->+<>-<]>.", "+".repeat(126));
+        let (status, output) = compile_and_run(&code, MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"~");
+
+        // A negative counter overflows before it reaches 0, unless it wraps.
+        for code in ["-[-]", "-[->+<]"] {
+            let (status, _) = compile_and_run(code, MemoryConfig::default(), b"").unwrap();
+            assert_eq!(status, 1);
+        }
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = compile_and_run("-[->-<]>.", config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"\x01");
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = compile_and_run(&("-".repeat(191) + "."), config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, b"A");
+    }
+}
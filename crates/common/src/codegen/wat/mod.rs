@@ -0,0 +1,419 @@
+//! Translate an `InstructionList` into a WebAssembly text format module.
+//!
+//! The module imports `env.read`, which returns the next input byte or -1 on
+//! EOF like `InStream`, and `env.write`, which receives the value of the cell
+//! like `OutStream`. It exports its linear memory as `memory` and the program
+//! as `run`. An out-of-bounds seek or an overflow under `Overflow::Error`
+//! traps with `unreachable`.
+
+use super::{check_config, expand, structure, Node, Result, Writer};
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+use crate::execution::memory::config::{Cell, Config as MemoryConfig, Eof, Overflow};
+
+/// The size of a WebAssembly page in bytes.
+const PAGE_SIZE: usize = 65536;
+
+pub struct Emitter {
+    config: MemoryConfig,
+}
+
+impl Emitter {
+    pub fn new(config: MemoryConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn emit(&self, instructions: &InstructionList) -> Result<String> {
+        check_config("wat", &self.config)?;
+        let instructions = expand(instructions, &self.config)?;
+        let nodes = structure(&instructions)?;
+        let mut w = Writer::new("  ");
+
+        w.line(";; Generated from a brainfuck program by brainfuck-interpreter.");
+        w.line("(module");
+        w.indent();
+        self.emit_prelude(&mut w);
+        w.line("(func $run (export \"run\")");
+        w.indent();
+        w.line("(local $val i64)");
+
+        for node in nodes {
+            match node {
+                Node::Instruction { ins, .. } => Self::emit_instruction(&mut w, ins),
+                Node::LoopStart { addr, .. } => {
+                    w.line(format!("block $break{addr}"));
+                    w.indent();
+                    w.line(format!("loop $continue{addr}"));
+                    w.indent();
+                    w.line("call $get");
+                    w.line("i32.eqz");
+                    w.line(format!("br_if $break{addr}"));
+                }
+                Node::LoopEnd { start, .. } => {
+                    w.line(format!("br $continue{start}"));
+                    w.dedent();
+                    w.line("end");
+                    w.dedent();
+                    w.line("end");
+                }
+            }
+        }
+
+        w.dedent();
+        w.line(")");
+        w.dedent();
+        w.line(")");
+        Ok(w.finish())
+    }
+
+    fn emit_instruction(w: &mut Writer, ins: &Instruction) {
+        match ins {
+            Instruction::Add { val } => {
+                w.line(format!("i64.const {val}"));
+                w.line("call $add");
+            }
+            Instruction::Seek { offset } => {
+                w.line(format!("i64.const {offset}"));
+                w.line("call $seek");
+            }
+            Instruction::Clear => w.line("call $clear"),
+            Instruction::AddUntilZero { target } => {
+                w.line("call $get");
+                w.line("if");
+                w.indent();
+                w.line("call $get");
+                w.line("i64.extend_i32_s");
+                w.line("local.set $val");
+                w.line("call $clear");
+
+                for AddUntilZeroArg { offset, times } in target {
+                    w.line(format!("i64.const {offset}"));
+                    w.line("call $seek");
+                    w.line("local.get $val");
+                    w.line(format!("i64.const {times}"));
+                    w.line("i64.mul");
+                    w.line("call $add");
+                    w.line(format!("i64.const {}", -offset));
+                    w.line("call $seek");
+                }
+
+                w.dedent();
+                w.line("end");
+            }
+            Instruction::Input => w.line("call $input"),
+            Instruction::Output => {
+                w.line("call $get");
+                w.line("call $write");
+            }
+            Instruction::Jump { .. } | Instruction::JumpIfZero { .. } | Instruction::Halt => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn emit_prelude(&self, w: &mut Writer) {
+        let range = self.config.range();
+        let (size, load, store, min, max) = match self.config.cell {
+            Cell::I8 => (
+                1,
+                "i32.load8_s",
+                "i32.store8",
+                i8::MIN as i64,
+                i8::MAX as i64,
+            ),
+            Cell::I32 => (4, "i32.load", "i32.store", i32::MIN as i64, i32::MAX as i64),
+            _ => unreachable!(),
+        };
+        let bytes = range.len() * size;
+        let pages = bytes.div_ceil(PAGE_SIZE).max(1);
+
+        w.line("(import \"env\" \"read\" (func $read (result i32)))");
+        w.line("(import \"env\" \"write\" (func $write (param i32)))");
+        w.line(format!("(memory (export \"memory\") {pages})"));
+        w.line("");
+        w.line(";; The byte offset of the current cell.");
+        w.line(format!(
+            "(global $ptr (mut i32) (i32.const {}))",
+            -range.left * size as isize
+        ));
+        w.line("");
+
+        w.line("(func $get (result i32)");
+        w.line("  global.get $ptr");
+        w.line(format!("  {load})"));
+        w.line("");
+        w.line(";; Clear the current cell as if it's counted down to 0.");
+        w.line("(func $clear");
+
+        // A negative cell overflows before it reaches 0.
+        if let Overflow::Error = self.config.overflow {
+            w.line("  call $get");
+            w.line("  i32.const 0");
+            w.line("  i32.lt_s");
+            w.line("  if");
+            w.line("    unreachable");
+            w.line("  end");
+        }
+
+        w.line("  global.get $ptr");
+        w.line("  i32.const 0");
+        w.line(format!("  {store})"));
+        w.line("");
+
+        w.line("(func $seek (param $offset i64)");
+        w.line("  (local $target i64)");
+        w.line("  global.get $ptr");
+        w.line("  i64.extend_i32_u");
+        w.line("  local.get $offset");
+        w.line(format!("  i64.const {size}"));
+        w.line("  i64.mul");
+        w.line("  i64.add");
+        w.line("  local.tee $target");
+        w.line(format!("  i64.const {bytes}"));
+        w.line("  i64.ge_u");
+        w.line("  if");
+        w.line("    unreachable");
+        w.line("  end");
+        w.line("  local.get $target");
+        w.line("  i32.wrap_i64");
+        w.line("  global.set $ptr)");
+        w.line("");
+
+        w.line(";; Store the value to the current cell, handling overflow.");
+        w.line("(func $set (param $val i64)");
+
+        if let Overflow::Error = self.config.overflow {
+            w.line("  local.get $val");
+            w.line(format!("  i64.const {min}"));
+            w.line("  i64.lt_s");
+            w.line("  local.get $val");
+            w.line(format!("  i64.const {max}"));
+            w.line("  i64.gt_s");
+            w.line("  i32.or");
+            w.line("  if");
+            w.line("    unreachable");
+            w.line("  end");
+        }
+
+        w.line("  global.get $ptr");
+        w.line("  local.get $val");
+        w.line("  i32.wrap_i64");
+        w.line(format!("  {store})"));
+        w.line("");
+
+        w.line("(func $add (param $val i64)");
+        w.line("  call $get");
+        w.line("  i64.extend_i32_s");
+        w.line("  local.get $val");
+        w.line("  i64.add");
+        w.line("  call $set)");
+        w.line("");
+
+        w.line("(func $input");
+        w.line("  (local $c i32)");
+        w.line("  call $read");
+        w.line("  local.set $c");
+
+        match self.config.eof {
+            Eof::Zero => {
+                w.line("  i32.const 0");
+                w.line("  local.get $c");
+                w.line("  local.get $c");
+                w.line("  i32.const -1");
+                w.line("  i32.eq");
+                w.line("  select");
+                w.line("  i64.extend_i32_s");
+                w.line("  call $set)");
+            }
+            Eof::Keep => {
+                w.line("  local.get $c");
+                w.line("  i64.extend_i32_s");
+                w.line("  call $set)");
+            }
+            Eof::Ignore => {
+                w.line("  local.get $c");
+                w.line("  i32.const -1");
+                w.line("  i32.ne");
+                w.line("  if");
+                w.line("    local.get $c");
+                w.line("    i64.extend_i32_s");
+                w.line("    call $set");
+                w.line("  end)");
+            }
+            _ => unreachable!(),
+        }
+
+        w.line("");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::execution::memory::config::{Addr, Storage};
+
+    fn emit(code: &str, config: MemoryConfig) -> String {
+        let instructions = Compiler::new().compile(code).unwrap();
+        Emitter::new(config).emit(&instructions).unwrap()
+    }
+
+    /// Assemble the module, disassemble it back to text and assemble it again.
+    fn round_trip(code: &str) -> Vec<u8> {
+        let binary = wat::parse_str(code).unwrap();
+        let text = wasmprinter::print_bytes(&binary).unwrap();
+        assert_eq!(wat::parse_str(&text).unwrap(), binary);
+        binary
+    }
+
+    #[test]
+    fn emit_program() {
+        let code = emit(",[.[-]>+<]", MemoryConfig::default());
+        let run = "  (func $run (export \"run\")
+    (local $val i64)
+    call $input
+    block $break1
+      loop $continue1
+        call $get
+        i32.eqz
+        br_if $break1
+        call $get
+        call $write
+        call $clear
+        i64.const 1
+        call $seek
+        i64.const 1
+        call $add
+        i64.const -1
+        call $seek
+        br $continue1
+      end
+    end
+  )
+)
+";
+        assert!(code.ends_with(run));
+        assert!(code.contains("(memory (export \"memory\") 1)"));
+        round_trip(&code);
+    }
+
+    #[test]
+    fn emit_with_config() {
+        let config = MemoryConfig {
+            len: 65536,
+            addr: Addr::Signed,
+            cell: Cell::I32,
+            overflow: Overflow::Wrap,
+            eof: Eof::Ignore,
+            storage: Storage::Dense,
+            init: None,
+        };
+        let code = emit("+[->+++<]", config);
+        assert!(code.contains("(memory (export \"memory\") 4)"));
+        assert!(code.contains("(global $ptr (mut i32) (i32.const 131072))"));
+        assert!(code.contains("  i32.load)"));
+        assert!(!code.contains("i64.lt_s"));
+        round_trip(&code);
+    }
+
+    /// Run the module with Node.js, or return `None` if it isn't available.
+    /// The output is the values passed to `write`.
+    fn run(code: &str, config: MemoryConfig, input: &[u8]) -> Option<(i32, Vec<i32>)> {
+        const SCRIPT: &str = r#"
+            const fs = require("fs");
+            const [wasm, input] = process.argv.slice(1).map((path) => fs.readFileSync(path));
+            const output = [];
+            let pos = 0;
+            const env = {
+                read: () => (pos < input.length ? input[pos++] : -1),
+                write: (c) => output.push(c),
+            };
+            WebAssembly.instantiate(wasm, { env }).then(({ instance }) => {
+                try {
+                    instance.exports.run();
+                } catch (e) {
+                    process.exitCode = 1;
+                }
+                console.log(output.join(" "));
+            });
+        "#;
+
+        let dir = std::env::temp_dir().join(format!("bf-codegen-wat-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("main.wasm");
+        let input_path = dir.join("input");
+        std::fs::write(&module, round_trip(&emit(code, config))).unwrap();
+        std::fs::write(&input_path, input).unwrap();
+
+        let output = Command::new("node")
+            .arg("-e")
+            .arg(SCRIPT)
+            .arg(&module)
+            .arg(&input_path)
+            .output()
+            .ok()?;
+        std::fs::remove_dir_all(&dir).ok();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let values = stdout
+            .split_whitespace()
+            .map(|value| value.parse().unwrap())
+            .collect();
+        Some((output.status.code().unwrap(), values))
+    }
+
+    #[test]
+    fn run_module() {
+        let hello = include_str!("../../../../../examples/helloworld.bf");
+
+        let Some((status, output)) = run(hello, MemoryConfig::default(), b"") else {
+            return;
+        };
+        let expected: Vec<i32> = "Hello World!\n".bytes().map(i32::from).collect();
+        assert_eq!(status, 0);
+        assert_eq!(output, expected);
+
+        let config = MemoryConfig {
+            cell: Cell::I32,
+            eof: Eof::Keep,
+            ..Default::default()
+        };
+        let (_, output) = run(",.,.,.", config, &[7, 200]).unwrap();
+        assert_eq!(output, vec![7, 200, -1]);
+
+        let (status, output) = run(".<", MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(output, vec![0]);
+
+        let (status, _) = run(&"+".repeat(128), MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 1);
+
+        // The repeated offset only overflows if the products are added at once.
+        let code = format!("++>{}<[->+<>-<]>.", "+".repeat(126));
+        let (status, output) = run(&code, MemoryConfig::default(), b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, vec![126]);
+
+        // A negative counter overflows before it reaches 0, unless it wraps.
+        for code in ["-[-]", "-[->+<]"] {
+            let (status, _) = run(code, MemoryConfig::default(), b"").unwrap();
+            assert_eq!(status, 1);
+        }
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = run("-[->-<]>.", config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, vec![1]);
+
+        let config = MemoryConfig {
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (status, output) = run(&("-".repeat(129) + "."), config, b"").unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(output, vec![127]);
+    }
+}
@@ -0,0 +1,355 @@
+//! Parse the human-readable form of an `InstructionList` back into it.
+//!
+//! The syntax is the one printed by `Disassembler`. Each line contains at
+//! most one instruction, optionally preceded by labels like `loop:` and its
+//! address, which must be the actual one. Comments start with `;`. The jumps
+//! must form well-nested loops as checked by `InstructionList::verify`.
+//!
+//! ```text
+//! start:
+//!     jz end          ; [->++<]
+//!     mul [1:2]
+//!     jump start
+//! end:
+//!     halt
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use snafu::prelude::*;
+
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList, VerifyError};
+
+pub type Result<T> = std::result::Result<T, AssembleError>;
+
+#[derive(Snafu, Debug, PartialEq, Eq)]
+pub enum AssembleError {
+    #[snafu(display("unknown instruction `{name}` on line {line}"))]
+    UnknownInstruction { line: usize, name: String },
+    #[snafu(display("invalid operand `{operand}` on line {line}"))]
+    InvalidOperand { line: usize, operand: String },
+    #[snafu(display("missing operand on line {line}"))]
+    MissingOperand { line: usize },
+    #[snafu(display("unexpected operand `{operand}` on line {line}"))]
+    UnexpectedOperand { line: usize, operand: String },
+    #[snafu(display("address {found} on line {line} should be {expected}"))]
+    AddressMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[snafu(display("label `{label}` is defined again on line {line}"))]
+    DuplicateLabel { line: usize, label: String },
+    #[snafu(display("label `{label}` on line {line} isn't defined"))]
+    UndefinedLabel { line: usize, label: String },
+    #[snafu(display("label `{label}` on line {line} doesn't point to an instruction"))]
+    DanglingLabel { line: usize, label: String },
+    #[snafu(display("the instructions are invalid"))]
+    Invalid { source: VerifyError },
+}
+
+pub struct Assembler;
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn assemble(&self, text: &str) -> Result<InstructionList> {
+        let mut labels = HashMap::new();
+        let mut instructions = vec![];
+        // The addresses of the jumps with their labels and lines.
+        let mut jumps = vec![];
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split(';').next().unwrap();
+            let mut rest = line.trim();
+
+            while let Some((label, after)) = split_label(rest) {
+                ensure!(
+                    labels
+                        .insert(label, (instructions.len(), line_number))
+                        .is_none(),
+                    DuplicateLabelSnafu {
+                        line: line_number,
+                        label,
+                    }
+                );
+                rest = after.trim_start();
+            }
+
+            if rest.is_empty() {
+                continue;
+            }
+
+            let (mut name, mut operand) = split_word(rest);
+
+            if name.starts_with(|c: char| c.is_ascii_digit()) {
+                let found: usize = parse(name, line_number)?;
+                ensure!(
+                    found == instructions.len(),
+                    AddressMismatchSnafu {
+                        line: line_number,
+                        expected: instructions.len(),
+                        found,
+                    }
+                );
+                (name, operand) = split_word(operand);
+            }
+
+            let ins = match name {
+                "add" => Instruction::Add {
+                    val: parse(required(operand, line_number)?, line_number)?,
+                },
+                "seek" => Instruction::Seek {
+                    offset: parse(required(operand, line_number)?, line_number)?,
+                },
+                "mul" => Instruction::AddUntilZero {
+                    target: parse_target(required(operand, line_number)?, line_number)?,
+                },
+                "jump" | "jz" => {
+                    let label = required(operand, line_number)?;
+                    ensure!(
+                        is_label(label),
+                        InvalidOperandSnafu {
+                            line: line_number,
+                            operand: label,
+                        }
+                    );
+                    jumps.push((instructions.len(), label, line_number));
+
+                    // The targets are filled after all the labels are known.
+                    if name == "jump" {
+                        Instruction::Jump { target: 0 }
+                    } else {
+                        Instruction::JumpIfZero { target: 0 }
+                    }
+                }
+                "clear" | "input" | "output" | "halt" => {
+                    ensure!(
+                        operand.is_empty(),
+                        UnexpectedOperandSnafu {
+                            line: line_number,
+                            operand,
+                        }
+                    );
+
+                    match name {
+                        "clear" => Instruction::Clear,
+                        "input" => Instruction::Input,
+                        "output" => Instruction::Output,
+                        _ => Instruction::Halt,
+                    }
+                }
+                _ => {
+                    return UnknownInstructionSnafu {
+                        line: line_number,
+                        name,
+                    }
+                    .fail()
+                }
+            };
+
+            instructions.push(ins);
+        }
+
+        for (addr, label, line) in jumps {
+            let &(target, _) = labels
+                .get(label)
+                .context(UndefinedLabelSnafu { line, label })?;
+            ensure!(
+                target < instructions.len(),
+                DanglingLabelSnafu { line, label }
+            );
+
+            match &mut instructions[addr] {
+                Instruction::Jump { target: t } | Instruction::JumpIfZero { target: t } => {
+                    *t = target
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let instructions = InstructionList(instructions);
+        instructions.verify().context(InvalidSnafu)?;
+        Ok(instructions)
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Split `label: rest` into the label and the rest.
+fn split_label(s: &str) -> Option<(&str, &str)> {
+    let (label, rest) = s.split_once(':')?;
+    is_label(label).then_some((label, rest))
+}
+
+/// Split the first word from the rest, which is trimmed.
+fn split_word(s: &str) -> (&str, &str) {
+    match s.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (s, ""),
+    }
+}
+
+fn required(operand: &str, line: usize) -> Result<&str> {
+    ensure!(!operand.is_empty(), MissingOperandSnafu { line });
+    Ok(operand)
+}
+
+fn parse<T: FromStr>(operand: &str, line: usize) -> Result<T> {
+    operand
+        .parse()
+        .ok()
+        .context(InvalidOperandSnafu { line, operand })
+}
+
+/// Parse the targets of `AddUntilZero` like `[1:2, -1:-1]`.
+fn parse_target(operand: &str, line: usize) -> Result<Vec<AddUntilZeroArg>> {
+    let inner = operand
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .context(InvalidOperandSnafu { line, operand })?
+        .trim();
+
+    if inner.is_empty() {
+        return Ok(vec![]);
+    }
+
+    inner
+        .split(',')
+        .map(|arg| {
+            let (offset, times) = arg
+                .split_once(':')
+                .context(InvalidOperandSnafu { line, operand: arg })?;
+            Ok(AddUntilZeroArg::new(
+                parse(offset.trim(), line)?,
+                parse(times.trim(), line)?,
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Compiler, Disassembler};
+
+    #[test]
+    fn assemble() {
+        let text = "
+            ; Move the doubled cell to the left.
+            input
+            start: jz end
+                mul [-1:2, 2:-1]   ; [-<++>>>-<<]
+                jump start
+            end:
+            0004 output
+            halt
+        ";
+        let expected = InstructionList(vec![
+            Instruction::Input,
+            Instruction::JumpIfZero { target: 4 },
+            Instruction::AddUntilZero {
+                target: vec![AddUntilZeroArg::new(-1, 2), AddUntilZeroArg::new(2, -1)],
+            },
+            Instruction::Jump { target: 1 },
+            Instruction::Output,
+            Instruction::Halt,
+        ]);
+        assert_eq!(Assembler::new().assemble(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn assemble_disassembled() {
+        let code = include_str!("../../../../../examples/hanoi.bf");
+        let (instructions, spans) = Compiler::new().compile_with_spans(code).unwrap();
+        let text = Disassembler::with_source(code, &spans).disassemble(&instructions);
+        assert_eq!(Assembler::new().assemble(&text).unwrap(), instructions);
+    }
+
+    #[test]
+    fn assemble_invalid() {
+        let assemble = |text| Assembler::new().assemble(text).unwrap_err();
+
+        assert_eq!(
+            assemble("add 1\nsub 1\nhalt"),
+            AssembleError::UnknownInstruction {
+                line: 2,
+                name: "sub".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("add 3000000000\nhalt"),
+            AssembleError::InvalidOperand {
+                line: 1,
+                operand: "3000000000".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("mul [1:2, 3]\nhalt"),
+            AssembleError::InvalidOperand {
+                line: 1,
+                operand: " 3".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("seek\nhalt"),
+            AssembleError::MissingOperand { line: 1 }
+        );
+        assert_eq!(
+            assemble("clear 1\nhalt"),
+            AssembleError::UnexpectedOperand {
+                line: 1,
+                operand: "1".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("0000 input\n0002 output\nhalt"),
+            AssembleError::AddressMismatch {
+                line: 2,
+                expected: 1,
+                found: 2
+            }
+        );
+        assert_eq!(
+            assemble("a: input\na: halt"),
+            AssembleError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("jz end\nhalt"),
+            AssembleError::UndefinedLabel {
+                line: 1,
+                label: "end".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("jz end\nhalt\nend:"),
+            AssembleError::DanglingLabel {
+                line: 1,
+                label: "end".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("input"),
+            AssembleError::Invalid {
+                source: VerifyError::MissingHalt
+            }
+        );
+        assert_eq!(
+            assemble("mul []\nhalt"),
+            AssembleError::Invalid {
+                source: VerifyError::EmptyTarget { addr: 0 }
+            }
+        );
+    }
+}
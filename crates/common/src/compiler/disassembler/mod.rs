@@ -0,0 +1,149 @@
+//! Print an `InstructionList` in a human-readable form.
+//!
+//! Each instruction is printed on its own line after its address. The jump
+//! targets are replaced with labels like `L4`, and `AddUntilZero` is printed
+//! as `mul [offset:times, ...]`. If the source code is given, the loops are
+//! followed by a comment with their source text.
+
+use std::fmt::Write;
+
+use crate::compiler::lexer::Span;
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+
+/// The longest source text shown in a comment.
+const SNIPPET_LEN: usize = 32;
+/// The column where the comments start.
+const COMMENT_COLUMN: usize = 40;
+
+pub struct Disassembler<'a> {
+    source: Option<(&'a str, &'a [Option<Span>])>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new() -> Self {
+        Self { source: None }
+    }
+
+    /// Show the source text of the instructions, whose spans are returned by
+    /// `Compiler::compile_with_spans`.
+    pub fn with_source(code: &'a str, spans: &'a [Option<Span>]) -> Self {
+        Self {
+            source: Some((code, spans)),
+        }
+    }
+
+    pub fn disassemble(&self, instructions: &InstructionList) -> String {
+        let instructions = &instructions.0;
+        let mut labels = vec![false; instructions.len() + 1];
+
+        for ins in instructions {
+            if let Instruction::Jump { target } | Instruction::JumpIfZero { target } = *ins {
+                if let Some(label) = labels.get_mut(target) {
+                    *label = true;
+                }
+            }
+        }
+
+        let width = instructions.len().to_string().len().max(4);
+        let mut res = String::new();
+
+        for (addr, ins) in instructions.iter().enumerate() {
+            if labels[addr] {
+                writeln!(res, "L{addr}:").unwrap();
+            }
+
+            let mut line = format!("  {addr:0width$}  {}", Self::format(ins));
+
+            if let Some(snippet) = self.snippet(addr) {
+                let padding = COMMENT_COLUMN.saturating_sub(line.len()).max(1);
+                write!(line, "{:padding$}; {snippet}", "").unwrap();
+            }
+
+            writeln!(res, "{line}").unwrap();
+        }
+
+        res
+    }
+
+    fn format(ins: &Instruction) -> String {
+        match ins {
+            Instruction::Add { val } => format!("add {val}"),
+            Instruction::Seek { offset } => format!("seek {offset}"),
+            Instruction::Clear => "clear".to_string(),
+            Instruction::AddUntilZero { target } => {
+                let target: Vec<_> = target
+                    .iter()
+                    .map(|AddUntilZeroArg { offset, times }| format!("{offset}:{times}"))
+                    .collect();
+                format!("mul [{}]", target.join(", "))
+            }
+            Instruction::Input => "input".to_string(),
+            Instruction::Output => "output".to_string(),
+            Instruction::Jump { target } => format!("jump L{target}"),
+            Instruction::JumpIfZero { target } => format!("jz L{target}"),
+            Instruction::Halt => "halt".to_string(),
+        }
+    }
+
+    /// Get the source text of the instruction with the whitespaces collapsed,
+    /// shortened if it's too long.
+    fn snippet(&self, addr: usize) -> Option<String> {
+        let (code, spans) = self.source?;
+        let span = (*spans.get(addr)?)?;
+        let text = code.get(span.start..span.end)?;
+        let mut snippet = String::new();
+
+        for c in text.chars().filter(|c| !c.is_whitespace()) {
+            if snippet.len() >= SNIPPET_LEN {
+                snippet.push_str("...");
+                break;
+            }
+
+            snippet.push(c);
+        }
+
+        Some(snippet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    #[test]
+    fn disassemble() {
+        let code = ",[.[-]>\n++[->+++<]<]";
+        let (instructions, spans) = Compiler::new().compile_with_spans(code).unwrap();
+
+        let text = Disassembler::with_source(code, &spans).disassemble(&instructions);
+        assert_eq!(
+            text,
+            "  0000  input                           ; ,
+L1:
+  0001  jz L9                           ; [.[-]>++[->+++<]<]
+  0002  output
+  0003  clear                           ; [-]
+  0004  seek 1                          ; >
+  0005  add 2                           ; ++
+  0006  mul [1:3]                       ; [->+++<]
+  0007  seek -1                         ; <
+  0008  jump L1
+L9:
+  0009  halt
+"
+        );
+
+        let text = Disassembler::new().disassemble(&instructions);
+        assert!(text.contains("  0001  jz L9\n"));
+    }
+
+    #[test]
+    fn disassemble_long_loop() {
+        let code = format!("+[{}-]", ">.".repeat(20));
+        let (instructions, spans) = Compiler::new().compile_with_spans(&code).unwrap();
+        let text = Disassembler::with_source(&code, &spans).disassemble(&instructions);
+        let snippet = format!("; [{}...\n", &">.".repeat(20)[..31]);
+        assert!(text.contains(&snippet), "{text}");
+    }
+}
@@ -1,6 +1,9 @@
+use snafu::prelude::*;
+
+use crate::compiler::lexer::Span;
 use crate::compiler::parser::{AddUntilZeroArg, SyntaxTree};
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     Add { val: i32 },
     Seek { offset: isize },
@@ -13,39 +16,124 @@ pub enum Instruction {
     Halt,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InstructionList(pub Vec<Instruction>);
 
+#[derive(Snafu, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    #[snafu(display("instruction {addr} jumps to {target}, which doesn't exist"))]
+    InvalidJumpTarget { addr: usize, target: usize },
+    #[snafu(display("instruction {addr} doesn't form a well-nested loop"))]
+    Unstructured { addr: usize },
+    #[snafu(display("`Halt` at {addr} isn't the last instruction"))]
+    MisplacedHalt { addr: usize },
+    #[snafu(display("the instructions don't end with `Halt`"))]
+    MissingHalt,
+    #[snafu(display("`AddUntilZero` at {addr} has no target"))]
+    EmptyTarget { addr: usize },
+}
+
 impl InstructionList {
     pub fn compile(syntax_tree: SyntaxTree) -> InstructionList {
+        Self::compile_with_spans(syntax_tree).0
+    }
+
+    /// Compile the syntax tree and return the span of the source code each
+    /// instruction comes from. A `JumpIfZero` has the span of the whole loop,
+    /// while `Output`, `Jump` and `Halt` have none.
+    pub fn compile_with_spans(syntax_tree: SyntaxTree) -> (InstructionList, Vec<Option<Span>>) {
         let root = match syntax_tree {
             SyntaxTree::Root { block: v } => v,
             _ => unreachable!(),
         };
 
         let mut ins = vec![];
-        InstructionList::compile_impl(&mut ins, root);
+        let mut spans = vec![];
+        InstructionList::compile_impl(&mut ins, &mut spans, root);
         ins.push(Instruction::Halt);
-        InstructionList(ins)
+        spans.push(None);
+        (InstructionList(ins), spans)
+    }
+
+    /// Check that the instructions can be run safely, which means every
+    /// `JumpIfZero` is paired with a `Jump` back to it as a well-nested loop,
+    /// every `AddUntilZero` has a target and only the last instruction is
+    /// `Halt`.
+    pub fn verify(&self) -> Result<(), VerifyError> {
+        let list = &self.0;
+        // The addresses of the `Jump`s closing the loops entered.
+        let mut ends = vec![];
+
+        for (addr, ins) in list.iter().enumerate() {
+            match ins {
+                Instruction::JumpIfZero { target } | Instruction::Jump { target } => {
+                    ensure!(
+                        *target < list.len(),
+                        InvalidJumpTargetSnafu {
+                            addr,
+                            target: *target
+                        }
+                    );
+                }
+                _ => {}
+            }
+
+            match ins {
+                Instruction::JumpIfZero { target } => {
+                    let paired = *target > addr + 1
+                        && matches!(list[*target - 1], Instruction::Jump { target } if target == addr);
+                    ensure!(paired, UnstructuredSnafu { addr });
+                    let end = *target - 1;
+                    ensure!(
+                        ends.last().is_none_or(|&outer| end < outer),
+                        UnstructuredSnafu { addr }
+                    );
+                    ends.push(end);
+                }
+                Instruction::Jump { .. } => {
+                    ensure!(ends.pop() == Some(addr), UnstructuredSnafu { addr });
+                }
+                Instruction::AddUntilZero { target } => {
+                    ensure!(!target.is_empty(), EmptyTargetSnafu { addr });
+                }
+                Instruction::Halt => {
+                    ensure!(addr + 1 == list.len(), MisplacedHaltSnafu { addr });
+                }
+                _ => {}
+            }
+        }
+
+        ensure!(list.last() == Some(&Instruction::Halt), MissingHaltSnafu);
+        Ok(())
     }
 
-    fn compile_impl(ins: &mut Vec<Instruction>, syntax_tree: Vec<SyntaxTree>) {
+    fn compile_impl(
+        ins: &mut Vec<Instruction>,
+        spans: &mut Vec<Option<Span>>,
+        syntax_tree: Vec<SyntaxTree>,
+    ) {
         for node in syntax_tree {
+            spans.push(node.span());
+
             match node {
-                SyntaxTree::Add { val } => ins.push(Instruction::Add { val }),
-                SyntaxTree::Seek { offset } => ins.push(Instruction::Seek {
+                SyntaxTree::Add { val, .. } => ins.push(Instruction::Add { val }),
+                SyntaxTree::Seek { offset, .. } => ins.push(Instruction::Seek {
                     offset: offset as isize,
                 }),
-                SyntaxTree::Clear => ins.push(Instruction::Clear),
-                SyntaxTree::AddUntilZero { target } => {
+                SyntaxTree::Clear { .. } => ins.push(Instruction::Clear),
+                // A loop like `[->><<]` only clears the cell.
+                SyntaxTree::AddUntilZero { target, .. } if target.is_empty() => {
+                    ins.push(Instruction::Clear)
+                }
+                SyntaxTree::AddUntilZero { target, .. } => {
                     ins.push(Instruction::AddUntilZero { target })
                 }
-                SyntaxTree::Input => ins.push(Instruction::Input),
+                SyntaxTree::Input { .. } => ins.push(Instruction::Input),
                 SyntaxTree::Output => ins.push(Instruction::Output),
-                SyntaxTree::Loop { block } => {
+                SyntaxTree::Loop { block, .. } => {
                     let loop_start_addr = ins.len();
                     ins.push(Instruction::JumpIfZero { target: 0 }); // 0 as a placeholder
-                    InstructionList::compile_impl(ins, block);
+                    InstructionList::compile_impl(ins, spans, block);
                     let loop_end_addr = ins.len();
                     ins.push(Instruction::Jump {
                         target: loop_start_addr,
@@ -56,6 +144,8 @@ impl InstructionList {
                 }
                 SyntaxTree::Root { block: _ } => unreachable!(),
             }
+
+            spans.resize(ins.len(), None);
         }
     }
 }
@@ -68,17 +158,33 @@ mod tests {
     fn compile() {
         let syntax_tree = SyntaxTree::Root {
             block: vec![
-                SyntaxTree::Input,
-                SyntaxTree::Add { val: 1 },
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
                 SyntaxTree::Loop {
                     block: vec![
-                        SyntaxTree::Seek { offset: -1 },
-                        SyntaxTree::Add { val: 1 },
-                        SyntaxTree::Seek { offset: 1 },
+                        SyntaxTree::Seek {
+                            offset: -1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::default(),
+                        },
                         SyntaxTree::Loop {
                             block: vec![SyntaxTree::Output],
+                            span: Span::default(),
                         },
                     ],
+                    span: Span::default(),
                 },
                 SyntaxTree::Output,
             ],
@@ -104,6 +210,111 @@ mod tests {
         assert_eq!(ins, expected);
     }
 
+    #[test]
+    fn compile_with_spans() {
+        let syntax_tree = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Input {
+                    span: Span::new(0, 1),
+                },
+                SyntaxTree::Loop {
+                    block: vec![
+                        SyntaxTree::Output,
+                        SyntaxTree::Clear {
+                            span: Span::new(3, 6),
+                        },
+                        SyntaxTree::Add {
+                            val: 2,
+                            span: Span::new(6, 8),
+                        },
+                    ],
+                    span: Span::new(1, 9),
+                },
+            ],
+        };
+
+        let (ins, spans) = InstructionList::compile_with_spans(syntax_tree);
+        assert_eq!(ins.0.len(), spans.len());
+        assert_eq!(
+            spans,
+            vec![
+                Some(Span::new(0, 1)),
+                Some(Span::new(1, 9)),
+                None,
+                Some(Span::new(3, 6)),
+                Some(Span::new(6, 8)),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn verify() {
+        let verify = |list: Vec<Instruction>| InstructionList(list).verify();
+
+        assert_eq!(
+            verify(vec![
+                Instruction::JumpIfZero { target: 3 },
+                Instruction::Output,
+                Instruction::Jump { target: 0 },
+                Instruction::Halt,
+            ]),
+            Ok(())
+        );
+        assert_eq!(
+            verify(vec![Instruction::Jump { target: 2 }, Instruction::Halt]),
+            Err(VerifyError::InvalidJumpTarget { addr: 0, target: 2 })
+        );
+        assert_eq!(
+            verify(vec![
+                Instruction::JumpIfZero { target: 3 },
+                Instruction::JumpIfZero { target: 4 },
+                Instruction::Jump { target: 0 },
+                Instruction::Jump { target: 1 },
+                Instruction::Halt,
+            ]),
+            Err(VerifyError::Unstructured { addr: 1 })
+        );
+        assert_eq!(
+            verify(vec![Instruction::Jump { target: 0 }, Instruction::Halt]),
+            Err(VerifyError::Unstructured { addr: 0 })
+        );
+        assert_eq!(
+            verify(vec![
+                Instruction::JumpIfZero { target: 0 },
+                Instruction::Halt
+            ]),
+            Err(VerifyError::Unstructured { addr: 0 })
+        );
+        assert_eq!(
+            verify(vec![Instruction::Halt, Instruction::Halt]),
+            Err(VerifyError::MisplacedHalt { addr: 0 })
+        );
+        assert_eq!(verify(vec![]), Err(VerifyError::MissingHalt));
+        assert_eq!(
+            verify(vec![
+                Instruction::AddUntilZero { target: vec![] },
+                Instruction::Halt
+            ]),
+            Err(VerifyError::EmptyTarget { addr: 0 })
+        );
+    }
+
+    #[test]
+    fn compile_empty_add_until_zero() {
+        let syntax_tree = SyntaxTree::Root {
+            block: vec![SyntaxTree::AddUntilZero {
+                target: vec![],
+                span: Span::default(),
+            }],
+        };
+        let ins = InstructionList::compile(syntax_tree);
+        let expected = InstructionList(vec![Instruction::Clear, Instruction::Halt]);
+        assert_eq!(ins, expected);
+        assert_eq!(ins.verify(), Ok(()));
+    }
+
     #[test]
     fn compile_from_empty_syntax_tree() {
         let ins = InstructionList::compile(SyntaxTree::Root { block: vec![] });
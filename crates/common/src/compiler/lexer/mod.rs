@@ -11,16 +11,59 @@ pub enum SingleToken {
 }
 
 type SingleTokenList = Vec<SingleToken>;
+type SpannedTokenList = Vec<(SingleToken, Span)>;
+
+/// A byte range `[start, end)` in the source code.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Return the smallest span which covers both `self` and `other`.
+    pub fn merge(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the 1-based line and column where the span starts in `code`.
+    pub fn location(&self, code: &str) -> (usize, usize) {
+        let before = &code[..self.start.min(code.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, column)
+    }
+}
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub struct Token {
     pub token: SingleToken,
     pub count: i32,
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token: SingleToken, count: i32) -> Self {
-        Self { token, count }
+        Self::with_span(token, count, Span::default())
+    }
+
+    pub fn with_span(token: SingleToken, count: i32, span: Span) -> Self {
+        Self { token, count, span }
     }
 }
 
@@ -28,26 +71,30 @@ impl Token {
 pub struct TokenList(pub Vec<Token>);
 
 impl TokenList {
-    /// Combine the same tokens (except `[` and ']') into a `Token`
-    /// which contains the count of them.
-    fn combine_same(tokens: SingleTokenList) -> TokenList {
+    /// Combine the same tokens (except `[`, ']' and `,`) into a `Token`
+    /// which contains the count of them. Every `,` is kept apart so that the
+    /// input reading EOF can be located in the source code.
+    fn combine_same(tokens: SpannedTokenList) -> TokenList {
         let mut res = vec![];
         let mut last = None::<SingleToken>;
         let mut now = None::<Token>;
 
-        for token in tokens {
+        for (token, span) in tokens {
             if let Some(last) = last {
                 if last == token
                     && token != SingleToken::LeftBracket
                     && token != SingleToken::RightBracket
+                    && token != SingleToken::Comma
                 {
-                    now.as_mut().unwrap().count += 1;
+                    let now = now.as_mut().unwrap();
+                    now.count += 1;
+                    now.span = now.span.merge(span);
                 } else {
                     res.push(now.take().unwrap());
-                    now = Some(Token::new(token, 1));
+                    now = Some(Token::with_span(token, 1, span));
                 }
             } else {
-                now = Some(Token::new(token, 1));
+                now = Some(Token::with_span(token, 1, span));
             }
 
             last = Some(token);
@@ -66,20 +113,17 @@ impl TokenList {
         let mut res = vec![];
         let mut now = None::<Token>;
 
-        for Token { token, count } in self.0 {
-            if let SingleToken::Add = token {
-                if now.is_none() {
-                    now = Some(Token::new(SingleToken::Add, 0));
-                }
+        for Token { token, count, span } in self.0 {
+            if let SingleToken::Add | SingleToken::Sub = token {
+                let now = now.get_or_insert(Token::with_span(SingleToken::Add, 0, span));
+                now.span = now.span.merge(span);
 
-                now.as_mut().unwrap().count += count;
-                continue;
-            } else if let SingleToken::Sub = token {
-                if now.is_none() {
-                    now = Some(Token::new(SingleToken::Add, 0));
+                if let SingleToken::Add = token {
+                    now.count += count;
+                } else {
+                    now.count -= count;
                 }
 
-                now.as_mut().unwrap().count -= count;
                 continue;
             }
 
@@ -89,7 +133,7 @@ impl TokenList {
                 }
             }
 
-            res.push(Token::new(token, count));
+            res.push(Token::with_span(token, count, span));
         }
 
         if let Some(now) = now.take() {
@@ -107,20 +151,17 @@ impl TokenList {
         let mut res = vec![];
         let mut now = None::<Token>;
 
-        for Token { token, count } in self.0 {
-            if let SingleToken::LessThan = token {
-                if now.is_none() {
-                    now = Some(Token::new(SingleToken::GreaterThan, 0));
-                }
+        for Token { token, count, span } in self.0 {
+            if let SingleToken::LessThan | SingleToken::GreaterThan = token {
+                let now = now.get_or_insert(Token::with_span(SingleToken::GreaterThan, 0, span));
+                now.span = now.span.merge(span);
 
-                now.as_mut().unwrap().count -= count;
-                continue;
-            } else if let SingleToken::GreaterThan = token {
-                if now.is_none() {
-                    now = Some(Token::new(SingleToken::GreaterThan, 0));
+                if let SingleToken::LessThan = token {
+                    now.count -= count;
+                } else {
+                    now.count += count;
                 }
 
-                now.as_mut().unwrap().count += count;
                 continue;
             }
 
@@ -130,7 +171,7 @@ impl TokenList {
                 }
             }
 
-            res.push(Token::new(token, count));
+            res.push(Token::with_span(token, count, span));
         }
 
         if let Some(now) = now.take() {
@@ -147,6 +188,16 @@ impl From<SingleTokenList> for TokenList {
     /// Combine the similar and adjacent tokens, such as `[Token::Add, Token::Add,
     /// Token::Sub]` to `[(Token::Add, 1)]`.
     fn from(tokens: SingleTokenList) -> TokenList {
+        let tokens = tokens
+            .into_iter()
+            .map(|token| (token, Span::default()))
+            .collect();
+        TokenList::from_spanned(tokens)
+    }
+}
+
+impl TokenList {
+    fn from_spanned(tokens: SpannedTokenList) -> TokenList {
         TokenList::combine_same(tokens)
             .combine_add_sub()
             .combine_less_greater()
@@ -154,15 +205,11 @@ impl From<SingleTokenList> for TokenList {
 }
 
 /// Split the program to some tokens and ignore what a brainfuck program doesn't
-/// contain.
-fn split(code: &str) -> Vec<char> {
-    code.chars().fold(Vec::new(), |mut v, c| match c {
-        c @ ('>' | '<' | '+' | '-' | '.' | ',' | '[' | ']') => {
-            v.push(c);
-            v
-        }
-        _ => v,
-    })
+/// contain. Every character comes with its byte offset in the program.
+fn split(code: &str) -> Vec<(usize, char)> {
+    code.char_indices()
+        .filter(|(_, c)| matches!(c, '>' | '<' | '+' | '-' | '.' | ',' | '[' | ']'))
+        .collect()
 }
 
 fn token(ch: char) -> SingleToken {
@@ -179,13 +226,16 @@ fn token(ch: char) -> SingleToken {
     }
 }
 
-fn build_single_token_list(code: &str) -> SingleTokenList {
-    split(code).into_iter().map(token).collect()
+fn build_single_token_list(code: &str) -> SpannedTokenList {
+    split(code)
+        .into_iter()
+        .map(|(pos, ch)| (token(ch), Span::new(pos, pos + 1)))
+        .collect()
 }
 
 /// Build a `TokenList` from a brainfuck program.
 pub fn build_token_list(code: &str) -> TokenList {
-    TokenList::from(build_single_token_list(code))
+    TokenList::from_spanned(build_single_token_list(code))
 }
 
 #[cfg(test)]
@@ -196,7 +246,8 @@ mod tests {
     fn split_code() {
         let code = "+ [>a+]>d.>-,.";
         let expected = vec!['+', '[', '>', '+', ']', '>', '.', '>', '-', ',', '.'];
-        assert_eq!(split(code), expected);
+        let actual: Vec<_> = split(code).into_iter().map(|(_, c)| c).collect();
+        assert_eq!(actual, expected);
     }
 
     #[test]
@@ -246,4 +297,27 @@ mod tests {
         let list: SingleTokenList = vec![];
         assert!(TokenList::from(list).0.is_empty());
     }
+
+    #[test]
+    fn token_span() {
+        let list = build_token_list("+ +[-]\n<x<.");
+        let expected = TokenList(vec![
+            Token::with_span(SingleToken::Add, 2, Span::new(0, 3)),
+            Token::with_span(SingleToken::LeftBracket, 1, Span::new(3, 4)),
+            Token::with_span(SingleToken::Add, -1, Span::new(4, 5)),
+            Token::with_span(SingleToken::RightBracket, 1, Span::new(5, 6)),
+            Token::with_span(SingleToken::GreaterThan, -2, Span::new(7, 10)),
+            Token::with_span(SingleToken::Dot, 1, Span::new(10, 11)),
+        ]);
+        assert_eq!(list, expected);
+        assert_eq!(Span::new(7, 10).location("+ +[-]\n<x<."), (2, 1));
+
+        // Every `,` keeps its own span.
+        let list = build_token_list(", ,");
+        let expected = TokenList(vec![
+            Token::with_span(SingleToken::Comma, 1, Span::new(0, 1)),
+            Token::with_span(SingleToken::Comma, 1, Span::new(2, 3)),
+        ]);
+        assert_eq!(list, expected);
+    }
 }
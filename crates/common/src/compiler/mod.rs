@@ -1,26 +1,135 @@
+mod assembler;
+mod disassembler;
 mod instruction;
 mod lexer;
 mod parser;
 
-pub use instruction::{Instruction, InstructionList};
+pub use assembler::{AssembleError, Assembler};
+pub use disassembler::Disassembler;
+pub use instruction::{Instruction, InstructionList, VerifyError};
 use lexer::build_token_list;
+pub use lexer::Span;
 use parser::Parser;
-pub use parser::{AddUntilZeroArg, ParseError};
+pub use parser::{
+    AddUntilZeroArg, AddUntilZeroRule, ClearRule, DeadCodeRule, Optimizer, ParseError, Report,
+    Rewrite, Rule, RuleMode, SyntaxTree,
+};
 
 pub type Result<T> = std::result::Result<T, ParseError>;
 
-pub struct Compiler;
+pub struct Compiler {
+    parser: Parser,
+}
 
 impl Compiler {
+    /// Create a compiler with the built-in optimization rules.
     pub fn new() -> Self {
-        Self
+        Self {
+            parser: Parser::new(),
+        }
     }
 
-    pub fn compile(&self, code: &str) -> Result<InstructionList> {
+    /// Create a compiler for a program starting on a fresh memory, which
+    /// assumes the memory is all zero when the program starts and removes the
+    /// code which never runs then. Don't use it if the memory is preloaded or
+    /// kept from an earlier run.
+    pub fn pristine() -> Self {
+        Self::with_optimizer(Optimizer::with_pristine_rules())
+    }
+
+    /// Create a compiler which optimizes the code with the given optimizer.
+    pub fn with_optimizer(optimizer: Optimizer) -> Self {
+        Self {
+            parser: Parser::with_optimizer(optimizer),
+        }
+    }
+
+    pub fn optimizer(&self) -> &Optimizer {
+        self.parser.optimizer()
+    }
+
+    /// Get the optimizer to register custom rules on it.
+    pub fn optimizer_mut(&mut self) -> &mut Optimizer {
+        self.parser.optimizer_mut()
+    }
+
+    /// Parse and optimize the code without lowering it into instructions.
+    pub fn parse(&self, code: &str) -> Result<SyntaxTree> {
         let token_list = build_token_list(code);
-        let parser = Parser::new();
-        let syntax_tree = parser.parse(token_list)?;
+        self.parser.parse(token_list)
+    }
+
+    pub fn compile(&self, code: &str) -> Result<InstructionList> {
+        let syntax_tree = self.parse(code)?;
         let instruction_list = InstructionList::compile(syntax_tree);
         Ok(instruction_list)
     }
+
+    /// Compile the code and return the span of the source code each
+    /// instruction comes from, if known.
+    pub fn compile_with_spans(&self, code: &str) -> Result<(InstructionList, Vec<Option<Span>>)> {
+        let syntax_tree = self.parse(code)?;
+        Ok(InstructionList::compile_with_spans(syntax_tree))
+    }
+
+    /// Compile the code and report what the optimizer did to it.
+    pub fn compile_with_report(&self, code: &str) -> Result<(InstructionList, Report)> {
+        let token_list = build_token_list(code);
+        let (syntax_tree, report) = self.parser.parse_with_report(token_list)?;
+        let instruction_list = InstructionList::compile(syntax_tree);
+        Ok((instruction_list, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Turn `[+]` into a `SyntaxTree::Clear`, which is only correct when the
+    /// cells wrap on overflow.
+    struct WrappingClearRule;
+
+    impl Rule for WrappingClearRule {
+        fn apply(&self, block: SyntaxTree) -> SyntaxTree {
+            match block {
+                SyntaxTree::Loop { block, span } => {
+                    if let [SyntaxTree::Add { val: 1, .. }] = block[..] {
+                        SyntaxTree::Clear { span }
+                    } else {
+                        SyntaxTree::Loop { block, span }
+                    }
+                }
+                otherwise => otherwise,
+            }
+        }
+    }
+
+    #[test]
+    fn compile_with_custom_rule() {
+        let mut compiler = Compiler::new();
+        compiler
+            .optimizer_mut()
+            .add_rule(Box::new(WrappingClearRule));
+        let ins = compiler.compile(",[+].").unwrap();
+        let expected = InstructionList(vec![
+            Instruction::Input,
+            Instruction::Clear,
+            Instruction::Output,
+            Instruction::Halt,
+        ]);
+        assert_eq!(ins, expected);
+    }
+
+    #[test]
+    fn compile_without_optimization() {
+        let compiler = Compiler::with_optimizer(Optimizer::new());
+        let ins = compiler.compile("[-]").unwrap();
+        let expected = InstructionList(vec![
+            Instruction::JumpIfZero { target: 3 },
+            Instruction::Add { val: -1 },
+            Instruction::Jump { target: 0 },
+            Instruction::Halt,
+        ]);
+        assert_eq!(ins, expected);
+    }
 }
@@ -2,26 +2,45 @@ mod optimizer;
 mod syntax;
 
 use crate::compiler::lexer::TokenList;
-use optimizer::Optimizer;
+pub use optimizer::{
+    AddUntilZeroRule, ClearRule, DeadCodeRule, Optimizer, Report, Rewrite, Rule, RuleMode,
+};
 use snafu::prelude::*;
 pub use syntax::{AddUntilZeroArg, SyntaxError, SyntaxTree};
 
 type Result<T> = std::result::Result<T, ParseError>;
 
-pub struct Parser;
+pub struct Parser {
+    optimizer: Optimizer,
+}
 
 impl Parser {
     pub fn new() -> Self {
-        Self
+        Self::with_optimizer(Optimizer::with_default_rules())
+    }
+
+    pub fn with_optimizer(optimizer: Optimizer) -> Self {
+        Self { optimizer }
+    }
+
+    pub fn optimizer(&self) -> &Optimizer {
+        &self.optimizer
+    }
+
+    pub fn optimizer_mut(&mut self) -> &mut Optimizer {
+        &mut self.optimizer
     }
 
     pub fn parse(&self, token_list: TokenList) -> Result<SyntaxTree> {
-        let mut optimizer = Optimizer::new();
-        optimizer.load_rules();
         let tree = SyntaxTree::build(token_list)?;
-        let tree = optimizer.optimize(tree);
+        let tree = self.optimizer.optimize(tree);
         Ok(tree)
     }
+
+    pub fn parse_with_report(&self, token_list: TokenList) -> Result<(SyntaxTree, Report)> {
+        let tree = SyntaxTree::build(token_list)?;
+        Ok(self.optimizer.optimize_with_report(tree))
+    }
 }
 
 #[derive(Debug, Snafu, PartialEq, Eq)]
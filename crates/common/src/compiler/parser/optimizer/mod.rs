@@ -1,45 +1,246 @@
+mod report;
+
 use crate::compiler::parser::syntax::AddUntilZeroArg;
 use crate::compiler::parser::syntax::SyntaxTree;
+pub use report::{Report, Rewrite};
+
+/// The upper bound of how many times a rule in `RuleMode::Fixpoint` is
+/// applied to a single node, which guarantees the optimization terminates
+/// even if a rule keeps rewriting its own output.
+const MAX_FIXPOINT_ITERATIONS: usize = 64;
+
+/// The default upper bound of how many times the whole tree is optimized.
+const DEFAULT_MAX_ITERATIONS: usize = 16;
 
+/// A rewrite applied to every node of a `SyntaxTree`.
+///
+/// A rule receives one node (including `SyntaxTree::Root` and `SyntaxTree::Loop`
+/// with their whole blocks) and returns the node it should be replaced with.
+/// Returning the node unchanged means the rule doesn't match.
 pub trait Rule {
+    /// The name shown in an optimization report, which is the type name by default.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
     fn apply(&self, block: SyntaxTree) -> SyntaxTree;
+
+    /// Apply the rule and return the node it should be replaced with, or give
+    /// the node back in `Err` if the rule doesn't match.
+    ///
+    /// The default implementation compares the node returned by `apply` with
+    /// a copy of the original one, which costs a clone of the whole subtree.
+    /// Rules knowing whether they match should override it to skip the copy.
+    fn try_apply(&self, block: SyntaxTree) -> Result<SyntaxTree, SyntaxTree> {
+        let before = block.clone();
+        let after = self.apply(block);
+
+        if after == before {
+            Err(after)
+        } else {
+            Ok(after)
+        }
+    }
+}
+
+/// How many times a rule is applied to a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleMode {
+    /// Apply the rule once.
+    Once,
+    /// Apply the rule again and again until it doesn't change the node any more.
+    Fixpoint,
 }
 
 pub struct Optimizer {
-    rules: Vec<Box<dyn Rule>>,
+    rules: Vec<(Box<dyn Rule>, RuleMode)>,
+    max_iterations: usize,
 }
 
 impl Optimizer {
     pub fn new() -> Self {
-        Self { rules: vec![] }
+        Self {
+            rules: vec![],
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+        }
+    }
+
+    /// Create an optimizer with the built-in rules loaded.
+    pub fn with_default_rules() -> Self {
+        let mut optimizer = Self::new();
+        optimizer.load_rules();
+        optimizer
+    }
+
+    /// Create an optimizer with the built-in rules loaded for a program
+    /// starting on a fresh memory, which is all zero when it starts.
+    pub fn with_pristine_rules() -> Self {
+        let mut optimizer = Self::new();
+        optimizer.load_rules_with(DeadCodeRule::pristine());
+        optimizer
+    }
+
+    /// Optimize the tree bottom-up, so a rewrite in a block can be followed by
+    /// the rewrites it makes possible in the outer blocks. The whole tree is
+    /// optimized again and again until no rule changes it any more, or the
+    /// number of iterations reaches the limit set by `set_max_iterations`.
+    pub fn optimize(&self, tree: SyntaxTree) -> SyntaxTree {
+        self.optimize_impl(tree, &mut None)
+    }
+
+    /// Optimize the tree and record every rewrite applied.
+    pub fn optimize_with_report(&self, tree: SyntaxTree) -> (SyntaxTree, Report) {
+        let mut rewrites = vec![];
+        let mut report = Report::before(&tree);
+        let tree = self.optimize_impl(tree, &mut Some(&mut rewrites));
+        report.after(&tree, rewrites);
+        (tree, report)
     }
 
-    pub fn optimize(&self, mut tree: SyntaxTree) -> SyntaxTree {
-        for rule in &self.rules {
-            tree = rule.apply(tree);
+    /// Set the maximum number of times the whole tree is optimized. `0`
+    /// disables the optimization.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    pub fn max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    fn optimize_impl(
+        &self,
+        mut tree: SyntaxTree,
+        rewrites: &mut Option<&mut Vec<Rewrite>>,
+    ) -> SyntaxTree {
+        for _ in 0..self.max_iterations {
+            let mut changed = false;
+            tree = self.optimize_node(tree, rewrites, &mut changed);
+
+            if !changed {
+                break;
+            }
         }
 
-        match tree {
+        tree
+    }
+
+    /// Optimize the children of the node first and then the node itself.
+    fn optimize_node(
+        &self,
+        tree: SyntaxTree,
+        rewrites: &mut Option<&mut Vec<Rewrite>>,
+        changed: &mut bool,
+    ) -> SyntaxTree {
+        let mut tree = match tree {
             SyntaxTree::Root { block } => SyntaxTree::Root {
-                block: block.into_iter().map(|tree| self.optimize(tree)).collect(),
+                block: block
+                    .into_iter()
+                    .map(|tree| self.optimize_node(tree, rewrites, changed))
+                    .collect(),
             },
-            SyntaxTree::Loop { block } => SyntaxTree::Loop {
-                block: block.into_iter().map(|tree| self.optimize(tree)).collect(),
+            SyntaxTree::Loop { block, span } => SyntaxTree::Loop {
+                block: block
+                    .into_iter()
+                    .map(|tree| self.optimize_node(tree, rewrites, changed))
+                    .collect(),
+                span,
             },
             otherwise => otherwise,
+        };
+
+        for (rule, mode) in &self.rules {
+            tree = Self::apply_rule(rule.as_ref(), *mode, tree, rewrites, changed);
         }
+
+        tree
+    }
+
+    fn apply_rule(
+        rule: &dyn Rule,
+        mode: RuleMode,
+        mut tree: SyntaxTree,
+        rewrites: &mut Option<&mut Vec<Rewrite>>,
+        changed: &mut bool,
+    ) -> SyntaxTree {
+        let iterations = match mode {
+            RuleMode::Once => 1,
+            RuleMode::Fixpoint => MAX_FIXPOINT_ITERATIONS,
+        };
+
+        for _ in 0..iterations {
+            // Only keep the original node when the rewrite has to be located
+            // for a report.
+            let before = rewrites.is_some().then(|| tree.clone());
+
+            tree = match rule.try_apply(tree) {
+                Ok(tree) => tree,
+                Err(tree) => return tree,
+            };
+
+            *changed = true;
+
+            if let (Some(rewrites), Some(before)) = (rewrites.as_mut(), before) {
+                rewrites.push(Rewrite::new(rule.name(), &before, &tree));
+            }
+        }
+
+        tree
     }
 
+    /// Load the built-in rules in their default order.
     pub fn load_rules(&mut self) {
+        self.load_rules_with(DeadCodeRule::new());
+    }
+
+    fn load_rules_with(&mut self, dead_code: DeadCodeRule) {
         self.add_rule(Box::new(ClearRule::new()));
         self.add_rule(Box::new(AddUntilZeroRule::new()));
+        self.add_rule(Box::new(dead_code));
+    }
+
+    /// Append a rule which is applied once per node after all the existing rules.
+    pub fn add_rule(&mut self, rule: Box<dyn Rule>) {
+        self.add_rule_with_mode(rule, RuleMode::Once);
+    }
+
+    /// Append a rule which is applied after all the existing rules.
+    pub fn add_rule_with_mode(&mut self, rule: Box<dyn Rule>, mode: RuleMode) {
+        self.rules.push((rule, mode));
     }
 
-    fn add_rule(&mut self, rule: Box<dyn Rule>) {
-        self.rules.push(rule);
+    /// Insert a rule at `index`, so it's applied before the rules behind it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > self.len()`.
+    pub fn insert_rule(&mut self, index: usize, rule: Box<dyn Rule>, mode: RuleMode) {
+        self.rules.insert(index, (rule, mode));
+    }
+
+    /// Remove all the rules.
+    pub fn clear_rules(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Return the number of rules loaded.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
     }
 }
 
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::with_default_rules()
+    }
+}
+
+/// Turn `[-]`, as well as a loop which only contains a `SyntaxTree::Clear`,
+/// into a `SyntaxTree::Clear`.
 pub struct ClearRule;
 
 impl ClearRule {
@@ -50,15 +251,19 @@ impl ClearRule {
 
 impl Rule for ClearRule {
     fn apply(&self, block: SyntaxTree) -> SyntaxTree {
+        self.try_apply(block).unwrap_or_else(|block| block)
+    }
+
+    fn try_apply(&self, block: SyntaxTree) -> Result<SyntaxTree, SyntaxTree> {
         match block {
-            SyntaxTree::Loop { block } => {
-                if block.len() == 1 && block[0] == (SyntaxTree::Add { val: -1 }) {
-                    SyntaxTree::Clear
+            SyntaxTree::Loop { block, span } => {
+                if let [SyntaxTree::Add { val: -1, .. } | SyntaxTree::Clear { .. }] = block[..] {
+                    Ok(SyntaxTree::Clear { span })
                 } else {
-                    SyntaxTree::Loop { block }
+                    Err(SyntaxTree::Loop { block, span })
                 }
             }
-            otherwise => otherwise,
+            otherwise => Err(otherwise),
         }
     }
 }
@@ -73,15 +278,19 @@ impl AddUntilZeroRule {
 
 impl Rule for AddUntilZeroRule {
     fn apply(&self, block: SyntaxTree) -> SyntaxTree {
-        let block = match block {
-            SyntaxTree::Loop { block } => block,
-            otherwise => return otherwise,
+        self.try_apply(block).unwrap_or_else(|block| block)
+    }
+
+    fn try_apply(&self, block: SyntaxTree) -> Result<SyntaxTree, SyntaxTree> {
+        let (block, span) = match block {
+            SyntaxTree::Loop { block, span } => (block, span),
+            otherwise => return Err(otherwise),
         };
 
         // Check whether the first character in code is `-`.
-        match block.get(0) {
-            Some(SyntaxTree::Add { val: -1 }) => (),
-            _ => return SyntaxTree::Loop { block },
+        match block.first() {
+            Some(SyntaxTree::Add { val: -1, .. }) => (),
+            _ => return Err(SyntaxTree::Loop { block, span }),
         }
 
         let mut current_offset = 0;
@@ -89,33 +298,180 @@ impl Rule for AddUntilZeroRule {
 
         for statement in block.iter().skip(1) {
             match statement {
-                SyntaxTree::Add { val } => {
+                SyntaxTree::Add { val, .. } => {
                     // Optimization fails if the program tries to change the
                     // counter inside a loop.
                     if current_offset == 0 {
-                        return SyntaxTree::Loop { block };
+                        return Err(SyntaxTree::Loop { block, span });
                     }
 
                     target.push(AddUntilZeroArg::new(current_offset, *val))
                 }
-                SyntaxTree::Seek { offset } => current_offset += *offset as isize,
-                _ => return SyntaxTree::Loop { block },
+                SyntaxTree::Seek { offset, .. } => current_offset += *offset as isize,
+                _ => return Err(SyntaxTree::Loop { block, span }),
             }
         }
 
         // Ensure the last behavior is moving the pointer back to the place
         // where it stayed when the loop started.
         if current_offset != 0 {
-            SyntaxTree::Loop { block }
+            Err(SyntaxTree::Loop { block, span })
         } else {
-            SyntaxTree::AddUntilZero { target }
+            Ok(SyntaxTree::AddUntilZero { target, span })
+        }
+    }
+}
+
+/// Remove the code which never runs or has no effect, including:
+///
+/// - loops, `SyntaxTree::Clear` and `SyntaxTree::AddUntilZero` reached when
+///   the current cell is known to be zero, e.g. the comment loop in the front
+///   of a program and a loop right after another loop.
+///
+/// The statements brought together by the removed code are merged as well.
+///
+/// The code in the front of a program is only removed by the rule created
+/// with `DeadCodeRule::pristine`.
+pub struct DeadCodeRule {
+    /// Whether the memory is known to be zero when the program starts.
+    pristine: bool,
+}
+
+impl DeadCodeRule {
+    pub fn new() -> Self {
+        Self { pristine: false }
+    }
+
+    /// Create a rule for a program starting on a fresh memory, which also
+    /// removes the code in the front of the program, e.g. the comment loop.
+    pub fn pristine() -> Self {
+        Self { pristine: true }
+    }
+
+    /// Eliminate dead code in a block and set `changed` if anything is
+    /// removed or merged. `pristine` tells whether the whole memory is known
+    /// to be zero when the block starts, which is only true in the front of a
+    /// program.
+    fn eliminate(
+        block: Vec<SyntaxTree>,
+        mut pristine: bool,
+        changed: &mut bool,
+    ) -> Vec<SyntaxTree> {
+        let mut res: Vec<SyntaxTree> = Vec::with_capacity(block.len());
+        // Whether the current cell is known to be zero.
+        let mut zero = pristine;
+
+        for statement in block {
+            match statement {
+                SyntaxTree::Loop { .. }
+                | SyntaxTree::Clear { .. }
+                | SyntaxTree::AddUntilZero { .. }
+                    if zero =>
+                {
+                    *changed = true
+                }
+                SyntaxTree::Add { val: 0, .. } | SyntaxTree::Seek { offset: 0, .. } => {
+                    *changed = true
+                }
+                statement @ SyntaxTree::Clear { .. } => {
+                    // The adds before it stay, since they may overflow.
+                    res.push(statement);
+                    zero = true;
+                }
+                statement @ (SyntaxTree::Loop { .. } | SyntaxTree::AddUntilZero { .. }) => {
+                    // Both of them only finish when the current cell becomes zero.
+                    res.push(statement);
+                    zero = true;
+                    pristine = false;
+                }
+                statement @ (SyntaxTree::Add { .. } | SyntaxTree::Input { .. }) => {
+                    Self::push(&mut res, statement, changed);
+                    zero = false;
+                    pristine = false;
+                }
+                statement @ SyntaxTree::Seek { .. } => {
+                    Self::push(&mut res, statement, changed);
+                    zero = pristine;
+                }
+                statement => res.push(statement),
+            }
+        }
+
+        res
+    }
+
+    /// Push a statement, merging it into the last one like the lexer does if
+    /// they're brought together by the removed code. Only the moves in the
+    /// same direction are merged, unless the sum overflows, since `+` and `-`
+    /// don't cancel out when the cell saturates or reports an overflow.
+    fn push(res: &mut Vec<SyntaxTree>, statement: SyntaxTree, changed: &mut bool) {
+        match (res.last_mut(), statement) {
+            (
+                Some(SyntaxTree::Add { val, span }),
+                SyntaxTree::Add {
+                    val: other,
+                    span: other_span,
+                },
+            ) if val.signum() == other.signum() && val.checked_add(other).is_some() => {
+                *val += other;
+                *span = span.merge(other_span);
+                *changed = true;
+            }
+            (
+                Some(SyntaxTree::Seek { offset, span }),
+                SyntaxTree::Seek {
+                    offset: other,
+                    span: other_span,
+                },
+            ) if offset.signum() == other.signum() && offset.checked_add(other).is_some() => {
+                *offset += other;
+                *span = span.merge(other_span);
+                *changed = true;
+            }
+            (_, statement) => res.push(statement),
+        }
+    }
+}
+
+impl Rule for DeadCodeRule {
+    fn apply(&self, block: SyntaxTree) -> SyntaxTree {
+        self.try_apply(block).unwrap_or_else(|block| block)
+    }
+
+    fn try_apply(&self, block: SyntaxTree) -> Result<SyntaxTree, SyntaxTree> {
+        let mut changed = false;
+        let block = match block {
+            SyntaxTree::Root { block } => SyntaxTree::Root {
+                block: Self::eliminate(block, self.pristine, &mut changed),
+            },
+            SyntaxTree::Loop { block, span } => SyntaxTree::Loop {
+                block: Self::eliminate(block, false, &mut changed),
+                span,
+            },
+            otherwise => return Err(otherwise),
+        };
+
+        if changed {
+            Ok(block)
+        } else {
+            Err(block)
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use crate::compiler::lexer::Span;
     use crate::compiler::parser::syntax::AddUntilZeroArg;
+    use crate::compiler::Compiler;
+    use crate::execution::context::Context;
+    use crate::execution::memory::config::{Cell, Config as MemoryConfig, Overflow};
+    use crate::execution::processor::Processor;
+    use crate::execution::stream::config::{Config as StreamConfig, Input, Output};
 
     use super::*;
 
@@ -126,9 +482,15 @@ mod tests {
 
         let tree = SyntaxTree::Root {
             block: vec![
-                SyntaxTree::Input,
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
                 SyntaxTree::Loop {
-                    block: vec![SyntaxTree::Add { val: -1 }],
+                    block: vec![SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
                 },
             ],
         };
@@ -136,7 +498,14 @@ mod tests {
         let tree = optimizer.optimize(tree);
 
         let expected = SyntaxTree::Root {
-            block: vec![SyntaxTree::Input, SyntaxTree::Clear],
+            block: vec![
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+            ],
         };
 
         assert_eq!(tree, expected);
@@ -151,22 +520,54 @@ mod tests {
             block: vec![
                 SyntaxTree::Loop {
                     block: vec![
-                        SyntaxTree::Add { val: -1 },
-                        SyntaxTree::Seek { offset: 2 },
-                        SyntaxTree::Add { val: -2 },
-                        SyntaxTree::Seek { offset: -3 },
-                        SyntaxTree::Add { val: 1 },
-                        SyntaxTree::Seek { offset: 1 },
+                        SyntaxTree::Add {
+                            val: -1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 2,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Add {
+                            val: -2,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: -3,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::default(),
+                        },
                     ],
+                    span: Span::default(),
                 },
                 SyntaxTree::Loop {
                     block: vec![
-                        SyntaxTree::Add { val: -1 },
-                        SyntaxTree::Seek { offset: 1 },
+                        SyntaxTree::Add {
+                            val: -1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::default(),
+                        },
                         SyntaxTree::Output,
-                        SyntaxTree::Add { val: 1 },
-                        SyntaxTree::Seek { offset: -1 },
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: -1,
+                            span: Span::default(),
+                        },
                     ],
+                    span: Span::default(),
                 },
             ],
         };
@@ -177,15 +578,29 @@ mod tests {
             block: vec![
                 SyntaxTree::AddUntilZero {
                     target: vec![AddUntilZeroArg::new(2, -2), AddUntilZeroArg::new(-1, 1)],
+                    span: Span::default(),
                 },
                 SyntaxTree::Loop {
                     block: vec![
-                        SyntaxTree::Add { val: -1 },
-                        SyntaxTree::Seek { offset: 1 },
+                        SyntaxTree::Add {
+                            val: -1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::default(),
+                        },
                         SyntaxTree::Output,
-                        SyntaxTree::Add { val: 1 },
-                        SyntaxTree::Seek { offset: -1 },
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: -1,
+                            span: Span::default(),
+                        },
                     ],
+                    span: Span::default(),
                 },
             ],
         };
@@ -193,6 +608,132 @@ mod tests {
         assert_eq!(tree, expected);
     }
 
+    /// Merge the first two adjacent `SyntaxTree::Add` in a block each time.
+    struct MergeOneAddRule;
+
+    impl Rule for MergeOneAddRule {
+        fn apply(&self, block: SyntaxTree) -> SyntaxTree {
+            let mut block = match block {
+                SyntaxTree::Root { block } => block,
+                otherwise => return otherwise,
+            };
+
+            let pos = block
+                .windows(2)
+                .position(|w| matches!(w, [SyntaxTree::Add { .. }, SyntaxTree::Add { .. }]));
+
+            if let Some(pos) = pos {
+                if let SyntaxTree::Add { val, .. } = block.remove(pos + 1) {
+                    if let SyntaxTree::Add { val: before, .. } = &mut block[pos] {
+                        *before += val;
+                    }
+                }
+            }
+
+            SyntaxTree::Root { block }
+        }
+    }
+
+    fn three_adds() -> SyntaxTree {
+        SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 3,
+                    span: Span::default(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn rule_mode() {
+        let mut optimizer = Optimizer::new();
+        optimizer.set_max_iterations(1);
+        optimizer.add_rule_with_mode(Box::new(MergeOneAddRule), RuleMode::Once);
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Add {
+                    val: 3,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 3,
+                    span: Span::default(),
+                },
+            ],
+        };
+        assert_eq!(optimizer.optimize(three_adds()), expected);
+
+        let mut optimizer = Optimizer::new();
+        optimizer.set_max_iterations(1);
+        optimizer.add_rule_with_mode(Box::new(MergeOneAddRule), RuleMode::Fixpoint);
+        let expected = SyntaxTree::Root {
+            block: vec![SyntaxTree::Add {
+                val: 6,
+                span: Span::default(),
+            }],
+        };
+        assert_eq!(optimizer.optimize(three_adds()), expected);
+    }
+
+    #[test]
+    fn rule_order() {
+        let tree = || SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        // `[-]` matches both rules, so whichever comes first wins.
+        let mut optimizer = Optimizer::with_default_rules();
+        assert_eq!(
+            optimizer.optimize(tree()),
+            SyntaxTree::Root {
+                block: vec![
+                    SyntaxTree::Input {
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Clear {
+                        span: Span::default()
+                    }
+                ]
+            }
+        );
+
+        optimizer.insert_rule(0, Box::new(AddUntilZeroRule::new()), RuleMode::Once);
+        assert_eq!(
+            optimizer.optimize(tree()),
+            SyntaxTree::Root {
+                block: vec![
+                    SyntaxTree::Input {
+                        span: Span::default(),
+                    },
+                    SyntaxTree::AddUntilZero {
+                        target: vec![],
+                        span: Span::default()
+                    }
+                ]
+            }
+        );
+    }
+
     #[test]
     fn add_while_zero_rule_with_changing_the_counter_incorrectly() {
         let mut optimizer = Optimizer::new();
@@ -201,14 +742,30 @@ mod tests {
         let tree = SyntaxTree::Root {
             block: vec![SyntaxTree::Loop {
                 block: vec![
-                    SyntaxTree::Add { val: -1 },
-                    SyntaxTree::Seek { offset: 1 },
-                    SyntaxTree::Add { val: 1 },
+                    SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Seek {
+                        offset: 1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Add {
+                        val: 1,
+                        span: Span::default(),
+                    },
                     // Move the pointer to the counter and change it apart from
                     // the decrement in the front of the loop.
-                    SyntaxTree::Seek { offset: -1 },
-                    SyntaxTree::Add { val: -1 },
+                    SyntaxTree::Seek {
+                        offset: -1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    },
                 ],
+                span: Span::default(),
             }],
         };
 
@@ -218,15 +775,466 @@ mod tests {
         let expected = SyntaxTree::Root {
             block: vec![SyntaxTree::Loop {
                 block: vec![
-                    SyntaxTree::Add { val: -1 },
-                    SyntaxTree::Seek { offset: 1 },
-                    SyntaxTree::Add { val: 1 },
-                    SyntaxTree::Seek { offset: -1 },
-                    SyntaxTree::Add { val: -1 },
+                    SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Seek {
+                        offset: 1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Add {
+                        val: 1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Seek {
+                        offset: -1,
+                        span: Span::default(),
+                    },
+                    SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    },
                 ],
+                span: Span::default(),
             }],
         };
 
         assert_eq!(tree, expected);
     }
+
+    #[test]
+    fn dead_code_rule() {
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(Box::new(DeadCodeRule::pristine()));
+
+        let tree = SyntaxTree::Root {
+            block: vec![
+                // A comment loop in the front of the program.
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Output],
+                    span: Span::default(),
+                },
+                SyntaxTree::Seek {
+                    offset: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 3,
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Clear {
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Loop {
+                            block: vec![SyntaxTree::Input {
+                                span: Span::default(),
+                            }],
+                            span: Span::default(),
+                        },
+                    ],
+                    span: Span::default(),
+                },
+                SyntaxTree::AddUntilZero {
+                    target: vec![AddUntilZeroArg::new(1, 1)],
+                    span: Span::default(),
+                },
+                SyntaxTree::Output,
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Input {
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+                SyntaxTree::Seek {
+                    offset: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Input {
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        let tree = optimizer.optimize(tree);
+
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Seek {
+                    offset: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 3,
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![
+                        SyntaxTree::Add {
+                            val: 1,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Clear {
+                            span: Span::default(),
+                        },
+                    ],
+                    span: Span::default(),
+                },
+                SyntaxTree::Output,
+                SyntaxTree::Seek {
+                    offset: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Input {
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        assert_eq!(tree, expected);
+
+        // `>[-]>>+[-]-+` with the seeks left around the removed code merged,
+        // while the adds before and after it are kept.
+        let tree = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Seek {
+                    offset: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Seek {
+                    offset: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: -1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Seek {
+                    offset: 0,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Seek {
+                    offset: 3,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: -1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        assert_eq!(optimizer.optimize(tree), expected);
+    }
+
+    #[test]
+    fn dead_code_rule_keeps_front() {
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(Box::new(DeadCodeRule::new()));
+
+        let front = SyntaxTree::Loop {
+            block: vec![SyntaxTree::Output],
+            span: Span::default(),
+        };
+        let tree = SyntaxTree::Root {
+            block: vec![
+                front.clone(),
+                SyntaxTree::Seek {
+                    offset: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Input {
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        // Only the loop right after `SyntaxTree::Clear` is removed.
+        let expected = SyntaxTree::Root {
+            block: vec![
+                front,
+                SyntaxTree::Seek {
+                    offset: 2,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+            ],
+        };
+        assert_eq!(optimizer.optimize(tree), expected);
+    }
+
+    #[test]
+    fn dead_code_rule_merge_overflow() {
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(Box::new(DeadCodeRule::new()));
+
+        let tree = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Add {
+                    val: i32::MAX,
+                    span: Span::default(),
+                },
+                SyntaxTree::Seek {
+                    offset: 0,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        // The adds brought together are kept apart for their sum overflows.
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Add {
+                    val: i32::MAX,
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+            ],
+        };
+        assert_eq!(optimizer.optimize(tree), expected);
+    }
+
+    /// Run the code on a single `u8` cell and return the result along with
+    /// the output.
+    fn run(code: &str, optimizer: Optimizer, overflow: Overflow) -> (Result<(), ()>, Vec<i128>) {
+        let instructions = Compiler::with_optimizer(optimizer).compile(code).unwrap();
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        let stream_config = StreamConfig {
+            input: Input::Null,
+            output: Output::Vec(output.clone()),
+        };
+        let memory_config = MemoryConfig {
+            len: 1,
+            cell: Cell::U8,
+            overflow,
+            ..Default::default()
+        };
+        let mut context = Context::new(memory_config, stream_config);
+        let res = Processor::new(instructions).unwrap().run(&mut context);
+        let output = output.borrow().iter().copied().collect();
+        (res.map_err(|_| ()), output)
+    }
+
+    #[test]
+    fn dead_code_rule_overflow() {
+        // `+` and `-` brought together don't cancel out when the cell
+        // saturates: 255 - 1 is not 255.
+        let code = format!("{}><-.", "+".repeat(260));
+        for overflow in [Overflow::Saturate, Overflow::Error] {
+            assert_eq!(
+                run(&code, Optimizer::with_default_rules(), overflow.clone()),
+                run(&code, Optimizer::new(), overflow),
+            );
+        }
+        assert_eq!(
+            run(&code, Optimizer::with_default_rules(), Overflow::Saturate),
+            (Ok(()), vec![254]),
+        );
+
+        // Nor do they when going below zero is reported.
+        let code = "-><+.";
+        assert_eq!(
+            run(code, Optimizer::with_default_rules(), Overflow::Error),
+            run(code, Optimizer::new(), Overflow::Error),
+        );
+        assert_eq!(
+            run(code, Optimizer::with_default_rules(), Overflow::Error).0,
+            Err(())
+        );
+
+        // The add before a clear still overflows.
+        let code = format!("{}[-].", "+".repeat(256));
+        assert_eq!(
+            run(&code, Optimizer::with_default_rules(), Overflow::Error),
+            (Err(()), vec![]),
+        );
+        assert_eq!(
+            run(&code, Optimizer::with_default_rules(), Overflow::Saturate),
+            (Ok(()), vec![0]),
+        );
+    }
+
+    #[test]
+    fn optimize_bottom_up() {
+        // `,[[-]]` and `,+[-]`
+        let tree = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Loop {
+                        block: vec![SyntaxTree::Add {
+                            val: -1,
+                            span: Span::default(),
+                        }],
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Loop {
+                    block: vec![SyntaxTree::Add {
+                        val: -1,
+                        span: Span::default(),
+                    }],
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        let tree = Optimizer::with_default_rules().optimize(tree);
+
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+                SyntaxTree::Input {
+                    span: Span::default(),
+                },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Clear {
+                    span: Span::default(),
+                },
+            ],
+        };
+
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn try_apply_without_change() {
+        let tree = || SyntaxTree::Loop {
+            block: vec![
+                SyntaxTree::Add {
+                    val: -1,
+                    span: Span::default(),
+                },
+                SyntaxTree::Output,
+            ],
+            span: Span::default(),
+        };
+
+        // None of the built-in rules matches, so the node is given back as is.
+        assert_eq!(ClearRule::new().try_apply(tree()), Err(tree()));
+        assert_eq!(AddUntilZeroRule::new().try_apply(tree()), Err(tree()));
+        assert_eq!(DeadCodeRule::pristine().try_apply(tree()), Err(tree()));
+    }
+
+    /// A rule which never reaches a fixpoint.
+    struct IncreaseRule;
+
+    impl Rule for IncreaseRule {
+        fn apply(&self, block: SyntaxTree) -> SyntaxTree {
+            match block {
+                SyntaxTree::Add { val, span } => SyntaxTree::Add { val: val + 1, span },
+                otherwise => otherwise,
+            }
+        }
+    }
+
+    #[test]
+    fn optimize_terminates() {
+        let tree = || SyntaxTree::Root {
+            block: vec![SyntaxTree::Add {
+                val: 0,
+                span: Span::default(),
+            }],
+        };
+
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(Box::new(IncreaseRule));
+        optimizer.set_max_iterations(3);
+        let expected = SyntaxTree::Root {
+            block: vec![SyntaxTree::Add {
+                val: 3,
+                span: Span::default(),
+            }],
+        };
+        assert_eq!(optimizer.optimize(tree()), expected);
+
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule_with_mode(Box::new(IncreaseRule), RuleMode::Fixpoint);
+        optimizer.set_max_iterations(2);
+        let expected = SyntaxTree::Root {
+            block: vec![SyntaxTree::Add {
+                val: 2 * MAX_FIXPOINT_ITERATIONS as i32,
+                span: Span::default(),
+            }],
+        };
+        assert_eq!(optimizer.optimize(tree()), expected);
+    }
 }
@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::compiler::lexer::Span;
+use crate::compiler::parser::syntax::SyntaxTree;
+
+/// A rewrite applied by a rule to a node of the `SyntaxTree`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+    pub rule: String,
+    /// The span of the source code which was rewritten. It's `None` only when
+    /// none of the nodes involved comes with a span, e.g. when a custom rule
+    /// only changed some `SyntaxTree::Input` and `SyntaxTree::Output`.
+    pub span: Option<Span>,
+}
+
+impl Rewrite {
+    pub(super) fn new(rule: &str, before: &SyntaxTree, after: &SyntaxTree) -> Self {
+        Self {
+            rule: rule.to_owned(),
+            span: rewritten_span(before, after),
+        }
+    }
+}
+
+/// What an `Optimizer` did to a program.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub rewrites: Vec<Rewrite>,
+    pub instructions_before: usize,
+    pub instructions_after: usize,
+    pub loops_before: usize,
+    pub loops_after: usize,
+}
+
+impl Report {
+    pub(super) fn before(tree: &SyntaxTree) -> Self {
+        Self {
+            instructions_before: count_instructions(tree),
+            loops_before: count_loops(tree),
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn after(&mut self, tree: &SyntaxTree, rewrites: Vec<Rewrite>) {
+        self.rewrites = rewrites;
+        self.instructions_after = count_instructions(tree);
+        self.loops_after = count_loops(tree);
+    }
+
+    pub fn loops_eliminated(&self) -> usize {
+        self.loops_before.saturating_sub(self.loops_after)
+    }
+
+    /// Return how many rewrites each rule applied, sorted by the rule names.
+    pub fn counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+
+        for rewrite in &self.rewrites {
+            *counts.entry(rewrite.rule.as_str()).or_insert(0) += 1;
+        }
+
+        counts
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (rule, count) in self.counts() {
+            writeln!(f, "{rule}: {count} rewrite(s)")?;
+        }
+
+        writeln!(
+            f,
+            "instructions: {} -> {}",
+            self.instructions_before, self.instructions_after
+        )?;
+        write!(
+            f,
+            "loops: {} -> {} ({} eliminated)",
+            self.loops_before,
+            self.loops_after,
+            self.loops_eliminated()
+        )
+    }
+}
+
+/// Count the instructions the tree will be compiled to, including the
+/// trailing `Instruction::Halt`.
+fn count_instructions(tree: &SyntaxTree) -> usize {
+    fn count(block: &[SyntaxTree]) -> usize {
+        block
+            .iter()
+            .map(|tree| match tree {
+                // Every loop needs a `JumpIfZero` and a `Jump`.
+                SyntaxTree::Loop { block, .. } => count(block) + 2,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    match tree {
+        SyntaxTree::Root { block } => count(block) + 1,
+        SyntaxTree::Loop { block, .. } => count(block) + 2,
+        _ => 1,
+    }
+}
+
+fn count_loops(tree: &SyntaxTree) -> usize {
+    match tree {
+        SyntaxTree::Root { block } => block.iter().map(count_loops).sum(),
+        SyntaxTree::Loop { block, .. } => block.iter().map(count_loops).sum::<usize>() + 1,
+        _ => 0,
+    }
+}
+
+/// Locate the code a rule rewrote. When a block is changed, only the
+/// statements between the unchanged head and tail of it are taken into
+/// account, and the whole node is used if none of them has a span.
+fn rewritten_span(before: &SyntaxTree, after: &SyntaxTree) -> Option<Span> {
+    match (before, after) {
+        (SyntaxTree::Root { block: old }, SyntaxTree::Root { block: new })
+        | (SyntaxTree::Loop { block: old, .. }, SyntaxTree::Loop { block: new, .. }) => {
+            changed_span(old, new).or_else(|| before.span())
+        }
+        _ => before.span(),
+    }
+}
+
+fn changed_span(old: &[SyntaxTree], new: &[SyntaxTree]) -> Option<Span> {
+    let head = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let tail = old[head..]
+        .iter()
+        .rev()
+        .zip(new[head..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    old[head..old.len() - tail]
+        .iter()
+        .filter_map(SyntaxTree::span)
+        .reduce(Span::merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::lexer::build_token_list;
+    use crate::compiler::parser::optimizer::Optimizer;
+    use crate::compiler::InstructionList;
+
+    fn build(code: &str) -> SyntaxTree {
+        SyntaxTree::build(build_token_list(code)).unwrap()
+    }
+
+    #[test]
+    fn report() {
+        let code = "[comment],[-]>+[->+<]";
+        let (tree, report) = Optimizer::with_pristine_rules().optimize_with_report(build(code));
+
+        let rewrites = vec![
+            Rewrite {
+                rule: String::from("ClearRule"),
+                span: Some(Span::new(10, 13)),
+            },
+            Rewrite {
+                rule: String::from("AddUntilZeroRule"),
+                span: Some(Span::new(15, 21)),
+            },
+            Rewrite {
+                rule: String::from("DeadCodeRule"),
+                span: Some(Span::new(0, 9)),
+            },
+        ];
+        assert_eq!(report.rewrites, rewrites);
+        assert_eq!(report.instructions_before, 15);
+        assert_eq!(report.instructions_after, 6);
+        assert_eq!(report.loops_eliminated(), 3);
+        assert_eq!(
+            report.instructions_after,
+            InstructionList::compile(tree).0.len()
+        );
+    }
+
+    #[test]
+    fn report_statements_outside_loops() {
+        let code = ",[-][-].";
+        let (_, report) = Optimizer::with_default_rules().optimize_with_report(build(code));
+
+        let rewrites = vec![
+            Rewrite {
+                rule: String::from("ClearRule"),
+                span: Some(Span::new(1, 4)),
+            },
+            Rewrite {
+                rule: String::from("ClearRule"),
+                span: Some(Span::new(4, 7)),
+            },
+            // The second `[-]` is dropped for the cell is already zero.
+            Rewrite {
+                rule: String::from("DeadCodeRule"),
+                span: Some(Span::new(4, 7)),
+            },
+        ];
+        assert_eq!(report.rewrites, rewrites);
+    }
+
+    #[test]
+    fn instruction_count() {
+        let code = "+[>[-]<,]>.";
+        assert_eq!(
+            count_instructions(&build(code)),
+            InstructionList::compile(build(code)).0.len()
+        );
+    }
+}
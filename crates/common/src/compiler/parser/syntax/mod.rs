@@ -1,9 +1,9 @@
-use crate::compiler::lexer::{SingleToken, Token, TokenList};
+use crate::compiler::lexer::{SingleToken, Span, Token, TokenList};
 use snafu::prelude::*;
 
 pub type Result<T> = std::result::Result<T, SyntaxError>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AddUntilZeroArg {
     pub offset: isize,
     pub times: i32,
@@ -13,42 +13,101 @@ impl AddUntilZeroArg {
     pub fn new(offset: isize, times: i32) -> Self {
         Self { offset, times }
     }
+
+    /// Return whether some of the targets share an offset, whose cell is then
+    /// added to more than once in every iteration.
+    pub fn repeats_offset(target: &[AddUntilZeroArg]) -> bool {
+        target
+            .iter()
+            .enumerate()
+            .any(|(i, arg)| target[..i].iter().any(|other| other.offset == arg.offset))
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+/// The syntax tree of a program. Apart from `Output` and `Root`, every node
+/// keeps the span of the source code it comes from, which is the run of
+/// tokens for `Add` and `Seek`, the single `,` for `Input` and the loop
+/// rewritten into the node for `Clear` and `AddUntilZero`.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SyntaxTree {
-    Add { val: i32 },
-    Seek { offset: i32 },
-    Clear,
-    AddUntilZero { target: Vec<AddUntilZeroArg> },
-    Input,
+    Add {
+        val: i32,
+        span: Span,
+    },
+    Seek {
+        offset: i32,
+        span: Span,
+    },
+    Clear {
+        span: Span,
+    },
+    AddUntilZero {
+        target: Vec<AddUntilZeroArg>,
+        span: Span,
+    },
+    Input {
+        span: Span,
+    },
     Output,
-    Root { block: Vec<SyntaxTree> },
-    Loop { block: Vec<SyntaxTree> },
+    Root {
+        block: Vec<SyntaxTree>,
+    },
+    /// A loop along with the span from its `[` to its `]` in the source code.
+    Loop {
+        block: Vec<SyntaxTree>,
+        span: Span,
+    },
 }
 
 impl SyntaxTree {
+    /// Return the span of the source code the node comes from. The span of a
+    /// `Root` covers all the statements in it which have a span.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            SyntaxTree::Add { span, .. }
+            | SyntaxTree::Seek { span, .. }
+            | SyntaxTree::Clear { span }
+            | SyntaxTree::AddUntilZero { span, .. }
+            | SyntaxTree::Input { span }
+            | SyntaxTree::Loop { span, .. } => Some(*span),
+            SyntaxTree::Output => None,
+            SyntaxTree::Root { block } => block
+                .iter()
+                .filter_map(SyntaxTree::span)
+                .reduce(Span::merge),
+        }
+    }
+
     pub fn build(token_list: TokenList) -> Result<SyntaxTree> {
         let mut current = token_list.0.into_iter();
         let mut left_bracket_count = 0;
-        let block = SyntaxTree::build_impl(&mut current, &mut left_bracket_count)?;
+        let (block, _) = SyntaxTree::build_impl(&mut current, &mut left_bracket_count)?;
         Ok(SyntaxTree::Root { block })
     }
 
-    fn build_impl<I>(current: &mut I, left_bracket_count: &mut i32) -> Result<Vec<SyntaxTree>>
+    /// Build the statements until the `]` closing the current block and return
+    /// them along with the span of the `]`.
+    fn build_impl<I>(
+        current: &mut I,
+        left_bracket_count: &mut i32,
+    ) -> Result<(Vec<SyntaxTree>, Span)>
     where
         I: Iterator<Item = Token>,
     {
         let mut res: Vec<SyntaxTree> = vec![];
+        let mut end = Span::default();
 
         loop {
-            if let Some(Token { token, count }) = current.next() {
+            if let Some(Token { token, count, span }) = current.next() {
                 match token {
-                    SingleToken::Add => res.push(SyntaxTree::Add { val: count }),
-                    SingleToken::GreaterThan => res.push(SyntaxTree::Seek { offset: count }),
+                    SingleToken::Add => res.push(SyntaxTree::Add { val: count, span }),
+                    SingleToken::GreaterThan => res.push(SyntaxTree::Seek {
+                        offset: count,
+                        span,
+                    }),
                     SingleToken::Comma => {
                         for _ in 0..count {
-                            res.push(SyntaxTree::Input)
+                            res.push(SyntaxTree::Input { span })
                         }
                     }
                     SingleToken::Dot => {
@@ -58,12 +117,16 @@ impl SyntaxTree {
                     }
                     SingleToken::LeftBracket => {
                         *left_bracket_count += 1;
-                        let block = SyntaxTree::build_impl(current, left_bracket_count)?;
-                        res.push(SyntaxTree::Loop { block })
+                        let (block, end) = SyntaxTree::build_impl(current, left_bracket_count)?;
+                        res.push(SyntaxTree::Loop {
+                            block,
+                            span: span.merge(end),
+                        })
                     }
                     SingleToken::RightBracket => {
                         *left_bracket_count -= 1;
                         ensure!(*left_bracket_count >= 0, UnpairedRightBracketSnafu);
+                        end = span;
                         break;
                     }
                     // Both `SingleToken::Sub` and `SingleToken::LessThan` have been
@@ -81,7 +144,7 @@ impl SyntaxTree {
             }
         }
 
-        Ok(res)
+        Ok((res, end))
     }
 }
 
@@ -96,6 +159,7 @@ pub enum SyntaxError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::lexer::build_token_list;
 
     #[test]
     fn to_syntax_tree() {
@@ -111,14 +175,26 @@ mod tests {
 
         let expected = Ok(SyntaxTree::Root {
             block: vec![
-                SyntaxTree::Add { val: 1 },
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::default(),
+                },
                 SyntaxTree::Output,
                 SyntaxTree::Loop {
                     block: vec![
-                        SyntaxTree::Seek { offset: -2 },
-                        SyntaxTree::Input,
-                        SyntaxTree::Seek { offset: 1 },
+                        SyntaxTree::Seek {
+                            offset: -2,
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Input {
+                            span: Span::default(),
+                        },
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::default(),
+                        },
                     ],
+                    span: Span::default(),
                 },
             ],
         });
@@ -151,4 +227,38 @@ mod tests {
         let expected = Err(SyntaxError::UnpairedRightBracket);
         assert_eq!(SyntaxTree::build(tokens), expected);
     }
+
+    #[test]
+    fn span() {
+        let tree = SyntaxTree::build(build_token_list("+[>[-]<]")).unwrap();
+        let expected = SyntaxTree::Root {
+            block: vec![
+                SyntaxTree::Add {
+                    val: 1,
+                    span: Span::new(0, 1),
+                },
+                SyntaxTree::Loop {
+                    block: vec![
+                        SyntaxTree::Seek {
+                            offset: 1,
+                            span: Span::new(2, 3),
+                        },
+                        SyntaxTree::Loop {
+                            block: vec![SyntaxTree::Add {
+                                val: -1,
+                                span: Span::new(4, 5),
+                            }],
+                            span: Span::new(3, 6),
+                        },
+                        SyntaxTree::Seek {
+                            offset: -1,
+                            span: Span::new(6, 7),
+                        },
+                    ],
+                    span: Span::new(1, 8),
+                },
+            ],
+        };
+        assert_eq!(tree, expected);
+    }
 }
@@ -0,0 +1,520 @@
+//! A differential test harness for the optimizer rules.
+//!
+//! Every program is run with no optimization as the reference, and then with
+//! each rule set under every combination of memory configurations. Unless the
+//! reference run doesn't halt in time, the optimized run must end the same
+//! way: failing as well, or halting with the same output, memory (tape) and
+//! pointer. A failing program is shrunk to a minimal one before it's reported.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::rc::Rc;
+
+use crate::compiler::{
+    AddUntilZeroRule, ClearRule, Compiler, DeadCodeRule, InstructionList, Optimizer,
+};
+use crate::execution::context::Context;
+use crate::execution::memory::config::{
+    Addr, Cell, Config as MemoryConfig, Eof, Init, Overflow, Storage,
+};
+use crate::execution::processor::{Processor, ProcessorState};
+use crate::execution::stream::config::{Config as StreamConfig, Input, Output};
+
+/// Memory length used by the harness, which is small so that the whole tape
+/// can be compared and out-of-bounds accesses are actually exercised.
+const LEN: usize = 64;
+
+/// The maximum number of instructions the reference run may execute. The
+/// programs exceeding it are skipped.
+const STEP_LIMIT: usize = 2000;
+
+/// The input of every run, which overflows some of the cells on purpose.
+const INPUT: [i32; 4] = [3, 0, 300, -1];
+
+struct RuleSet {
+    name: &'static str,
+    build: fn() -> Optimizer,
+    /// Whether the rule set assumes the memory is zero when the program
+    /// starts, which isn't true with a preloaded tape.
+    pristine: bool,
+}
+
+fn rule_sets() -> Vec<RuleSet> {
+    fn single(rule: Box<dyn crate::compiler::Rule>) -> Optimizer {
+        let mut optimizer = Optimizer::new();
+        optimizer.add_rule(rule);
+        optimizer
+    }
+
+    vec![
+        RuleSet {
+            name: "ClearRule",
+            build: || single(Box::new(ClearRule::new())),
+            pristine: false,
+        },
+        RuleSet {
+            name: "AddUntilZeroRule",
+            build: || single(Box::new(AddUntilZeroRule::new())),
+            pristine: false,
+        },
+        RuleSet {
+            name: "DeadCodeRule",
+            build: || single(Box::new(DeadCodeRule::new())),
+            pristine: false,
+        },
+        RuleSet {
+            name: "DeadCodeRule::pristine",
+            build: || single(Box::new(DeadCodeRule::pristine())),
+            pristine: true,
+        },
+        RuleSet {
+            name: "default",
+            build: Optimizer::with_default_rules,
+            pristine: false,
+        },
+        RuleSet {
+            name: "pristine",
+            build: Optimizer::with_pristine_rules,
+            pristine: true,
+        },
+    ]
+}
+
+/// Return the memory configs to run the programs with, which are every
+/// combination of the options below.
+fn memory_configs() -> Vec<MemoryConfig> {
+    let addrs = [
+        Addr::Unsigned,
+        Addr::Signed,
+        Addr::Circular,
+        // Allowed to grow a little, so that both growing and running out of
+        // the room are exercised.
+        Addr::Dynamic { max: Some(LEN + 8) },
+    ];
+    let cells = [
+        Cell::I8,
+        Cell::U8,
+        Cell::I32,
+        // Small enough to overflow with a few `+`s.
+        Cell::Bits {
+            bits: 4,
+            signed: false,
+        },
+        Cell::Bits {
+            bits: 3,
+            signed: true,
+        },
+    ];
+    let overflows = [
+        Overflow::Error,
+        Overflow::Wrap,
+        Overflow::Saturate,
+        Overflow::ErrorOnNegative,
+    ];
+    let eofs = [
+        Eof::Zero,
+        Eof::Keep,
+        Eof::Ignore,
+        Eof::Value(255),
+        Eof::Error,
+    ];
+    let storages = [Storage::Dense, Storage::Sparse];
+    let inits = [
+        None,
+        // Some of the bytes overflow the small cells on purpose.
+        Some(Init {
+            addr: 0,
+            data: vec![2, 0, 255, 7, 1],
+        }),
+    ];
+
+    // The unbounded cells never overflow.
+    let cells: Vec<_> = cells
+        .iter()
+        .flat_map(|cell| overflows.iter().map(move |overflow| (cell, overflow)))
+        .chain([(&Cell::Unbounded, &Overflow::Error)])
+        .collect();
+    let mut configs = vec![];
+
+    for addr in &addrs {
+        for (cell, overflow) in &cells {
+            for eof in &eofs {
+                for storage in &storages {
+                    for init in &inits {
+                        configs.push(MemoryConfig {
+                            len: LEN,
+                            addr: addr.clone(),
+                            cell: (*cell).clone(),
+                            overflow: (*overflow).clone(),
+                            eof: eof.clone(),
+                            storage: storage.clone(),
+                            init: init.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    configs
+}
+
+#[derive(Clone, PartialEq, Eq)]
+enum Outcome {
+    /// The program halted normally.
+    Halted {
+        output: Vec<i128>,
+        tape: Vec<i128>,
+        pointer: isize,
+    },
+    /// The program failed.
+    Aborted,
+    /// The program didn't halt within the step limit.
+    Timeout,
+}
+
+impl Debug for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Halted {
+                output,
+                tape,
+                pointer,
+            } => {
+                // Trim the trailing zeros to keep the message short.
+                let len = tape
+                    .iter()
+                    .rposition(|&cell| cell != 0)
+                    .map_or(0, |i| i + 1);
+                f.debug_struct("Halted")
+                    .field("output", output)
+                    .field("tape", &&tape[..len])
+                    .field("pointer", pointer)
+                    .finish()
+            }
+            Outcome::Aborted => write!(f, "Aborted"),
+            Outcome::Timeout => write!(f, "Timeout"),
+        }
+    }
+}
+
+/// Compile the program with the optimizer, or return `None` if it's invalid.
+fn compile(code: &str, optimizer: Optimizer) -> Option<InstructionList> {
+    Compiler::with_optimizer(optimizer).compile(code).ok()
+}
+
+fn run(instructions: Option<&InstructionList>, config: &MemoryConfig) -> Outcome {
+    let instructions = match instructions {
+        Some(instructions) => instructions.clone(),
+        None => return Outcome::Aborted,
+    };
+
+    let input = Rc::new(RefCell::new(VecDeque::from(INPUT.to_vec())));
+    let output = Rc::new(RefCell::new(VecDeque::new()));
+    let stream_config = StreamConfig {
+        input: Input::Vec(input),
+        output: Output::Vec(output.clone()),
+    };
+    let mut context = Context::new(config.clone(), stream_config);
+    let mut processor = Processor::new(instructions).unwrap();
+
+    for _ in 0..STEP_LIMIT {
+        if processor.state() == ProcessorState::Halted {
+            break;
+        }
+
+        if processor.step(&mut context).is_err() {
+            return Outcome::Aborted;
+        }
+    }
+
+    if processor.state() != ProcessorState::Halted {
+        return Outcome::Timeout;
+    }
+
+    let memory = &context.memory;
+    let range = memory.range();
+    let output = output.borrow().iter().copied().collect();
+    Outcome::Halted {
+        output,
+        tape: (range.left..=range.right)
+            .map(|addr| memory.get_at(addr).unwrap())
+            .collect(),
+        pointer: memory.position(),
+    }
+}
+
+struct Failure {
+    code: String,
+    rule_set: &'static str,
+    config: MemoryConfig,
+    expected: Outcome,
+    actual: Outcome,
+}
+
+impl Debug for Failure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "optimized program differs from the reference")?;
+        writeln!(f, "  program:  `{}`", self.code)?;
+        writeln!(f, "  rule set: {}", self.rule_set)?;
+        writeln!(f, "  config:   {:?}", self.config)?;
+        writeln!(f, "  expected: {:?}", self.expected)?;
+        write!(f, "  actual:   {:?}", self.actual)
+    }
+}
+
+/// Compare the outcome of the optimized run with the reference one, unless
+/// the reference run doesn't halt in time.
+fn compare(
+    code: &str,
+    rule_set: &RuleSet,
+    config: &MemoryConfig,
+    expected: &Outcome,
+    actual: Outcome,
+) -> Option<Failure> {
+    if *expected == Outcome::Timeout || actual == *expected {
+        return None;
+    }
+
+    Some(Failure {
+        code: code.to_owned(),
+        rule_set: rule_set.name,
+        config: config.clone(),
+        expected: expected.clone(),
+        actual,
+    })
+}
+
+/// Check whether the program fails with the rule set under the config.
+fn check_one(code: &str, rule_set: &RuleSet, config: &MemoryConfig) -> Option<Failure> {
+    let expected = run(compile(code, Optimizer::new()).as_ref(), config);
+    let actual = run(compile(code, (rule_set.build)()).as_ref(), config);
+    compare(code, rule_set, config, &expected, actual)
+}
+
+fn check(code: &str) -> Option<Failure> {
+    let reference = compile(code, Optimizer::new());
+    let rule_sets = rule_sets();
+
+    // Most rule sets leave a program as is or optimize it the same way as
+    // some others, so every distinct program is only run once per config.
+    let mut programs = vec![reference];
+    let indices: Vec<usize> = rule_sets
+        .iter()
+        .map(|rule_set| {
+            let optimized = compile(code, (rule_set.build)());
+            programs
+                .iter()
+                .position(|program| *program == optimized)
+                .unwrap_or_else(|| {
+                    programs.push(optimized);
+                    programs.len() - 1
+                })
+        })
+        .collect();
+
+    for config in memory_configs() {
+        let mut outcomes: Vec<Option<Outcome>> = vec![None; programs.len()];
+        let expected = run(programs[0].as_ref(), &config);
+
+        if expected == Outcome::Timeout {
+            continue;
+        }
+
+        outcomes[0] = Some(expected.clone());
+
+        for (rule_set, &index) in rule_sets.iter().zip(&indices) {
+            if rule_set.pristine && config.init.is_some() {
+                continue;
+            }
+
+            let actual = outcomes[index]
+                .get_or_insert_with(|| run(programs[index].as_ref(), &config))
+                .clone();
+
+            if compare(code, rule_set, &config, &expected, actual).is_some() {
+                let code = shrink(code, |code| check_one(code, rule_set, &config).is_some());
+                return check_one(&code, rule_set, &config);
+            }
+        }
+    }
+
+    None
+}
+
+/// Repeatedly remove a single command or a pair of brackets from the program
+/// while it keeps failing.
+fn shrink(code: &str, fails: impl Fn(&str) -> bool) -> String {
+    let mut code: Vec<char> = code.chars().filter(|c| "+-<>.,[]".contains(*c)).collect();
+
+    'outer: loop {
+        for i in 0..code.len() {
+            let mut candidate = code.clone();
+
+            match code[i] {
+                '[' => {
+                    candidate.remove(matching_bracket(&code, i));
+                    candidate.remove(i);
+                }
+                ']' => continue,
+                _ => {
+                    candidate.remove(i);
+                }
+            }
+
+            let candidate: String = candidate.into_iter().collect();
+
+            if fails(&candidate) {
+                code = candidate.chars().collect();
+                continue 'outer;
+            }
+        }
+
+        return code.into_iter().collect();
+    }
+}
+
+fn matching_bracket(code: &[char], left: usize) -> usize {
+    let mut depth = 0;
+
+    for (i, c) in code.iter().enumerate().skip(left) {
+        match c {
+            '[' => depth += 1,
+            ']' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    unreachable!()
+}
+
+/// A xorshift generator, which is enough to produce reproducible programs.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+}
+
+fn generate(random: &mut Random, len: usize, depth: usize) -> String {
+    let mut code = String::new();
+
+    while code.len() < len {
+        match random.below(12) {
+            0..=2 => code.push('+'),
+            3..=4 => code.push('-'),
+            5..=6 => code.push('>'),
+            7 => code.push('<'),
+            8 => code.push('.'),
+            9 => code.push(','),
+            _ if depth > 0 => {
+                let body_len = 1 + random.below(6) as usize;
+                code.push('[');
+                code.push_str(&generate(random, body_len, depth - 1));
+                code.push(']');
+            }
+            _ => code.push('-'),
+        }
+    }
+
+    code
+}
+
+mod tests {
+    use super::*;
+
+    fn assert_equivalent(code: &str) {
+        if let Some(failure) = check(code) {
+            panic!("{failure:?}");
+        }
+    }
+
+    #[test]
+    fn random_programs() {
+        let mut random = Random(0x2545f4914f6cdd1d);
+
+        for _ in 0..200 {
+            let len = 4 + random.below(24) as usize;
+            let code = generate(&mut random, len, 3);
+            assert_equivalent(&code);
+        }
+    }
+
+    #[test]
+    fn corpus_programs() {
+        const CORPUS: &[&str] = &[
+            include_str!("../../../../examples/helloworld.bf"),
+            "[comment]+++++[>++<-]>.",
+            ",[.,]",
+            ",[->+>+<<]>[-<+>]>.<<.",
+            "+[>+<+]>.",
+            "++>+++[-<[->>+<<]>]>>.",
+            "-[[-]>+<]>.",
+            "+++[>+>[-]<<-]>.>.",
+            ">+>+<[[->]<<]>.",
+            "+[-[->+<]]>.",
+            // The second cell saturates or goes negative in the middle of an
+            // iteration, but not in the end.
+            ",>,,-<[->+>+<-<]>.",
+            ",>+<[->->+<+<]>.",
+            ",>,[->+<>-<]>.",
+        ];
+
+        for code in CORPUS {
+            assert_equivalent(code);
+        }
+    }
+
+    #[test]
+    fn shrink_failing_program() {
+        /// A broken rule which drops all the outputs.
+        struct DropOutputRule;
+
+        impl crate::compiler::Rule for DropOutputRule {
+            fn apply(&self, block: crate::compiler::SyntaxTree) -> crate::compiler::SyntaxTree {
+                use crate::compiler::SyntaxTree;
+
+                match block {
+                    SyntaxTree::Root { block } => SyntaxTree::Root {
+                        block: block
+                            .into_iter()
+                            .filter(|tree| *tree != SyntaxTree::Output)
+                            .collect(),
+                    },
+                    otherwise => otherwise,
+                }
+            }
+        }
+
+        let rule_set = RuleSet {
+            name: "DropOutputRule",
+            build: || {
+                let mut optimizer = Optimizer::new();
+                optimizer.add_rule(Box::new(DropOutputRule));
+                optimizer
+            },
+            pristine: false,
+        };
+        let config = MemoryConfig::default();
+        let code = "++>+[-]<[->+<]>.,.";
+
+        assert!(check_one(code, &rule_set, &config).is_some());
+        let code = shrink(code, |code| check_one(code, &rule_set, &config).is_some());
+        assert_eq!(code, ".");
+    }
+}
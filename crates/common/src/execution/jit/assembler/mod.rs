@@ -0,0 +1,279 @@
+//! A tiny x86-64 encoder covering only the instructions the JIT emits.
+//!
+//! The generated code keeps the address of the current cell in `%rbx`, the
+//! addresses of the leftmost and the rightmost cells in `%r12` and `%r13`, and
+//! the pointer to the runtime in `%r15`.
+//!
+//! The cells are 64-bit, but they only hold `i8` or `i32` values extended to
+//! 64 bits, so the values are calculated in the 32-bit registers and only
+//! extended again when they're stored.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Condition {
+    Always,
+    Equal,
+    NotEqual,
+    Overflow,
+    Below,
+    Above,
+    Less,
+}
+
+/// The position of a 32-bit relative jump target to be filled later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Patch(usize);
+
+pub(crate) struct Assembler {
+    code: Vec<u8>,
+}
+
+impl Assembler {
+    pub(crate) fn new() -> Self {
+        Self { code: vec![] }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.code
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.code.len()
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, imm: i32) {
+        self.bytes(&imm.to_le_bytes());
+    }
+
+    /// Save the callee-saved registers and move the arguments `(runtime,
+    /// leftmost, rightmost, current)` into place.
+    pub(crate) fn prologue(&mut self) {
+        self.bytes(&[0x53]); // push %rbx
+        self.bytes(&[0x41, 0x54]); // push %r12
+        self.bytes(&[0x41, 0x55]); // push %r13
+        self.bytes(&[0x41, 0x56]); // push %r14, which keeps the stack aligned
+        self.bytes(&[0x41, 0x57]); // push %r15
+        self.bytes(&[0x49, 0x89, 0xFF]); // mov %rdi, %r15
+        self.bytes(&[0x49, 0x89, 0xF4]); // mov %rsi, %r12
+        self.bytes(&[0x49, 0x89, 0xD5]); // mov %rdx, %r13
+        self.bytes(&[0x48, 0x89, 0xCB]); // mov %rcx, %rbx
+    }
+
+    /// Save the current cell to the runtime, restore the registers and return
+    /// `%eax`.
+    pub(crate) fn epilogue(&mut self) {
+        self.bytes(&[0x49, 0x89, 0x1F]); // mov %rbx, (%r15)
+        self.bytes(&[0x41, 0x5F]); // pop %r15
+        self.bytes(&[0x41, 0x5E]); // pop %r14
+        self.bytes(&[0x41, 0x5D]); // pop %r13
+        self.bytes(&[0x41, 0x5C]); // pop %r12
+        self.bytes(&[0x5B]); // pop %rbx
+        self.bytes(&[0xC3]); // ret
+    }
+
+    /// `mov $imm, %eax`
+    pub(crate) fn set_result(&mut self, imm: i32) {
+        self.bytes(&[0xB8]);
+        self.imm32(imm);
+    }
+
+    /// `movq $imm, 8(%r15)`, which stores the counter to the runtime.
+    pub(crate) fn set_counter(&mut self, imm: i32) {
+        self.bytes(&[0x49, 0xC7, 0x47, 0x08]);
+        self.imm32(imm);
+    }
+
+    /// Emit a jump whose target is filled by `patch` later.
+    pub(crate) fn jump(&mut self, condition: Condition) -> Patch {
+        match condition {
+            Condition::Always => self.bytes(&[0xE9]),
+            Condition::Equal => self.bytes(&[0x0F, 0x84]),
+            Condition::NotEqual => self.bytes(&[0x0F, 0x85]),
+            Condition::Overflow => self.bytes(&[0x0F, 0x80]),
+            Condition::Below => self.bytes(&[0x0F, 0x82]),
+            Condition::Above => self.bytes(&[0x0F, 0x87]),
+            Condition::Less => self.bytes(&[0x0F, 0x8C]),
+        }
+
+        let patch = Patch(self.position());
+        self.imm32(0);
+        patch
+    }
+
+    pub(crate) fn jump_to(&mut self, condition: Condition, target: usize) {
+        let patch = self.jump(condition);
+        self.patch(patch, target);
+    }
+
+    pub(crate) fn patch(&mut self, Patch(at): Patch, target: usize) {
+        let rel = (target as isize - (at + 4) as isize) as i32;
+        self.code[at..at + 4].copy_from_slice(&rel.to_le_bytes());
+    }
+
+    /// `cmpq $0, (%rbx)`
+    pub(crate) fn test_cell(&mut self) {
+        self.bytes(&[0x48, 0x83, 0x3B, 0x00]);
+    }
+
+    /// `movq $0, (%rbx)`
+    pub(crate) fn clear_cell(&mut self) {
+        self.bytes(&[0x48, 0xC7, 0x03]);
+        self.imm32(0);
+    }
+
+    /// `mov (%rbx), %eax`, which loads the lower half of the cell.
+    pub(crate) fn load_cell(&mut self) {
+        self.bytes(&[0x8B, 0x03]);
+    }
+
+    /// `movslq %eax, %rax` and `mov %rax, (%rbx)`
+    pub(crate) fn store_cell(&mut self) {
+        self.bytes(&[0x48, 0x63, 0xC0]);
+        self.bytes(&[0x48, 0x89, 0x03]);
+    }
+
+    /// `add $imm, %eax`
+    pub(crate) fn add_eax(&mut self, imm: i32) {
+        self.bytes(&[0x05]);
+        self.imm32(imm);
+    }
+
+    /// `movsbl %al, %eax`
+    pub(crate) fn sign_extend_eax(&mut self) {
+        self.bytes(&[0x0F, 0xBE, 0xC0]);
+    }
+
+    /// `movsbl %al, %ecx` and `cmp %eax, %ecx`, which sets `ZF` if `%eax`
+    /// fits in a byte.
+    pub(crate) fn check_eax_byte(&mut self) {
+        self.bytes(&[0x0F, 0xBE, 0xC8]);
+        self.bytes(&[0x39, 0xC1]);
+    }
+
+    /// `test %eax, %eax`
+    pub(crate) fn test_eax(&mut self) {
+        self.bytes(&[0x85, 0xC0]);
+    }
+
+    /// `lea disp(%rbx), %rax`
+    pub(crate) fn lea_rax(&mut self, disp: i32) {
+        self.bytes(&[0x48, 0x8D, 0x83]);
+        self.imm32(disp);
+    }
+
+    /// `lea disp(%rbx), %rdx`
+    pub(crate) fn lea_rdx(&mut self, disp: i32) {
+        self.bytes(&[0x48, 0x8D, 0x93]);
+        self.imm32(disp);
+    }
+
+    /// `cmp %r12, %rax`, which compares `%rax` with the leftmost cell.
+    pub(crate) fn cmp_rax_left(&mut self) {
+        self.bytes(&[0x4C, 0x39, 0xE0]);
+    }
+
+    /// `cmp %r13, %rax`, which compares `%rax` with the rightmost cell.
+    pub(crate) fn cmp_rax_right(&mut self) {
+        self.bytes(&[0x4C, 0x39, 0xE8]);
+    }
+
+    /// `cmp %r12, %rdx`
+    pub(crate) fn cmp_rdx_left(&mut self) {
+        self.bytes(&[0x4C, 0x39, 0xE2]);
+    }
+
+    /// `cmp %r13, %rdx`
+    pub(crate) fn cmp_rdx_right(&mut self) {
+        self.bytes(&[0x4C, 0x39, 0xEA]);
+    }
+
+    /// `mov %rax, %rbx`
+    pub(crate) fn move_rax_to_current(&mut self) {
+        self.bytes(&[0x48, 0x89, 0xC3]);
+    }
+
+    /// `imul $imm, %eax, %ecx`
+    pub(crate) fn mul_eax_to_ecx(&mut self, imm: i32) {
+        self.bytes(&[0x69, 0xC8]);
+        self.imm32(imm);
+    }
+
+    /// `add disp(%rbx), %ecx`
+    pub(crate) fn add_cell_to_ecx(&mut self, disp: i32) {
+        self.bytes(&[0x03, 0x8B]);
+        self.imm32(disp);
+    }
+
+    /// `movsbl %cl, %ecx`
+    pub(crate) fn sign_extend_ecx(&mut self) {
+        self.bytes(&[0x0F, 0xBE, 0xC9]);
+    }
+
+    /// `movsbl %cl, %edx` and `cmp %ecx, %edx`, which sets `ZF` if `%ecx`
+    /// fits in a byte.
+    pub(crate) fn check_ecx_byte(&mut self) {
+        self.bytes(&[0x0F, 0xBE, 0xD1]);
+        self.bytes(&[0x39, 0xCA]);
+    }
+
+    /// `movslq %ecx, %rcx` and `mov %rcx, disp(%rbx)`
+    pub(crate) fn store_ecx(&mut self, disp: i32) {
+        self.bytes(&[0x48, 0x63, 0xC9]);
+        self.bytes(&[0x48, 0x89, 0x8B]);
+        self.imm32(disp);
+    }
+
+    /// Call `extern "C" fn(runtime, cell value)`.
+    pub(crate) fn call_with_value(&mut self, function: usize) {
+        self.bytes(&[0x4C, 0x89, 0xFF]); // mov %r15, %rdi
+        self.bytes(&[0x8B, 0x33]); // mov (%rbx), %esi
+        self.call(function);
+    }
+
+    /// `mov (%r15), %rbx`, which loads the current cell saved in the runtime
+    /// by a callback.
+    pub(crate) fn reload_current(&mut self) {
+        self.bytes(&[0x49, 0x8B, 0x1F]);
+    }
+
+    /// Call `extern "C" fn(runtime, cell address) -> i32`.
+    pub(crate) fn call_with_address(&mut self, function: usize) {
+        self.bytes(&[0x4C, 0x89, 0xFF]); // mov %r15, %rdi
+        self.bytes(&[0x48, 0x89, 0xDE]); // mov %rbx, %rsi
+        self.call(function);
+    }
+
+    fn call(&mut self, function: usize) {
+        self.bytes(&[0x48, 0xB8]); // movabs $function, %rax
+        self.bytes(&(function as u64).to_le_bytes());
+        self.bytes(&[0xFF, 0xD0]); // call *%rax
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_jump() {
+        let mut asm = Assembler::new();
+        let start = asm.position();
+        asm.test_cell();
+        let patch = asm.jump(Condition::Equal);
+        asm.clear_cell();
+        asm.jump_to(Condition::Always, start);
+        let end = asm.position();
+        asm.patch(patch, end);
+
+        let expected = [
+            0x48, 0x83, 0x3B, 0x00, // cmpq $0, (%rbx)
+            0x0F, 0x84, 0x0C, 0x00, 0x00, 0x00, // je end
+            0x48, 0xC7, 0x03, 0x00, 0x00, 0x00, 0x00, // movq $0, (%rbx)
+            0xE9, 0xEA, 0xFF, 0xFF, 0xFF, // jmp start
+        ];
+        assert_eq!(asm.finish(), expected);
+    }
+}
@@ -0,0 +1,601 @@
+//! A JIT compiler translating an `InstructionList` into x86-64 machine code.
+//!
+//! The machine code works directly on the cells of `Memory` and calls back
+//! into `InStream` and `OutStream` for I/O. Whenever an instruction would fail,
+//! e.g. an out-of-bounds seek, the machine code stops before it and the
+//! `Processor` continues from there, so the errors are exactly the same as
+//! interpreting the whole program. Unsupported platforms and memories, such as
+//! the ones built from custom strategies, are interpreted as well.
+
+mod assembler;
+
+use assembler::{Assembler, Condition, Patch};
+
+use crate::codegen::{structure, Node};
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+use crate::execution::context::Context;
+use crate::execution::memory::config::{
+    Addr, Cell, Config as MemoryConfig, Eof, Overflow, Storage,
+};
+use crate::execution::processor::{Processor, ProcessorError, Result};
+
+/// The values returned by the machine code.
+const HALTED: i32 = 0;
+const STOPPED: i32 = 1;
+const FAILED: i32 = 2;
+
+pub struct Jit {
+    instructions: InstructionList,
+}
+
+impl Jit {
+    /// Create a JIT compiler after checking that the instructions are valid.
+    pub fn new(instructions: InstructionList) -> Result<Self> {
+        instructions.verify()?;
+        Ok(Self { instructions })
+    }
+
+    /// Check whether the program can be compiled for the memory, otherwise it
+    /// will be interpreted.
+    pub fn is_supported(&self, context: &Context) -> bool {
+        cfg!(all(target_arch = "x86_64", target_os = "linux"))
+            && context
+                .memory
+                .config()
+                .and_then(|config| compile(&self.instructions, config))
+                .is_some()
+    }
+
+    pub fn run(&self, context: &mut Context) -> Result<()> {
+        let code = context
+            .memory
+            .config()
+            .and_then(|config| compile(&self.instructions, config))
+            .and_then(|code| Executable::new(&code));
+
+        match code {
+            Some(code) => self.run_native(&code, context),
+            None => Processor::new(self.instructions.clone())?.run(context),
+        }
+    }
+
+    /// Run the machine code on the cells of the memory.
+    ///
+    /// # Safety contract
+    ///
+    /// While the machine code is running, the cells are accessed through the
+    /// raw pointers derived here, and `Memory` is only reached by the callbacks
+    /// through `Runtime::context`. A callback writing the cells through
+    /// `Memory` invalidates the pointers held by the machine code, so it saves
+    /// a pointer derived again from `Memory` to `Runtime::current`, which the
+    /// machine code reloads right after the call. None of the pointers is used
+    /// after the machine code returns, and in particular not while the
+    /// `Processor` resumes on the same memory.
+    fn run_native(&self, code: &Executable, context: &mut Context) -> Result<()> {
+        let range = context.memory.range();
+        let index = (context.memory.position() - range.left) as usize;
+
+        let (status, index, counter, error) = {
+            // All the pointers are derived from this one, so that reaching the
+            // context from the callbacks doesn't invalidate the others.
+            let context: *mut Context = context;
+            // SAFETY: `context` comes from a mutable reference which isn't
+            // used until the pointers are gone.
+            let cells = unsafe { (*context).memory.cells_mut() };
+            let base = cells.as_mut_ptr();
+            let len = cells.len();
+
+            // SAFETY: The indices are within the cells, which aren't
+            // reallocated while the machine code is running, for only the
+            // memories of a fixed length are compiled.
+            let (rightmost, current) = unsafe { (base.add(len - 1), base.add(index)) };
+            let mut runtime = Runtime {
+                current,
+                counter: 0,
+                base,
+                left: range.left,
+                context,
+                error: None,
+            };
+
+            // SAFETY: The machine code only accesses the cells within the
+            // bounds, and the runtime outlives the call.
+            let status = unsafe { code.call(&mut runtime, base, rightmost, current) };
+            // SAFETY: The machine code keeps the current cell within the cells.
+            let index = unsafe { runtime.current.offset_from(base) };
+            (status, index, runtime.counter, runtime.error.take())
+        };
+
+        // The runtime along with all the pointers into the cells is gone, so
+        // the memory is only accessed through `context` from now on.
+        context.memory.set_position(range.left + index);
+
+        match status {
+            HALTED => Ok(()),
+            STOPPED => Processor::resume(self.instructions.clone(), counter).run(context),
+            FAILED => Err(error.unwrap()),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The state shared with the machine code, whose first two fields are
+/// accessed by it directly.
+#[repr(C)]
+struct Runtime {
+    /// The current cell, which is saved when the machine code returns.
+    current: *mut i64,
+    /// The instruction to continue from when the machine code stops.
+    counter: usize,
+    base: *mut i64,
+    left: isize,
+    context: *mut Context,
+    error: Option<ProcessorError>,
+}
+
+extern "C" fn input(runtime: *mut Runtime, cell: *mut i64) -> i32 {
+    // SAFETY: The runtime and the context are valid during the call, and the
+    // machine code doesn't touch the cells until it returns.
+    let runtime = unsafe { &mut *runtime };
+    let context = unsafe { &mut *runtime.context };
+    let index = unsafe { cell.offset_from(runtime.base) };
+    context.memory.set_position(runtime.left + index);
+
+    let status = match context.memory.set(context.in_stream.read() as i128) {
+        Ok(()) => 0,
+        Err(e) => {
+            runtime.error = Some(e.into());
+            1
+        }
+    };
+
+    // The cell has been written through `Memory`, so the pointer held by the
+    // machine code is replaced with a new one derived from it.
+    runtime.current = context.memory.cells_mut()[index as usize..].as_mut_ptr();
+    status
+}
+
+extern "C" fn output(runtime: *mut Runtime, val: i32) {
+    // SAFETY: The same as `input`.
+    let context = unsafe { &mut *(*runtime).context };
+    context.out_stream.write(val as i128);
+}
+
+/// Convert an offset in cells to one in bytes.
+fn displacement(offset: isize) -> Option<i32> {
+    offset
+        .checked_mul(std::mem::size_of::<i64>() as isize)
+        .and_then(|disp| i32::try_from(disp).ok())
+}
+
+/// Compile the instructions for the memory config, or return `None` if it's
+/// unsupported.
+fn compile(instructions: &InstructionList, config: &MemoryConfig) -> Option<Vec<u8>> {
+    // The machine code only works on a fixed range of `i8` or `i32` cells
+    // stored together, which either wrap or report an error on overflow. The
+    // processor reports where EOF is read.
+    if config.len == 0
+        || matches!(config.addr, Addr::Dynamic { .. } | Addr::Circular)
+        || matches!(config.storage, Storage::Sparse)
+        || !matches!(config.cell, Cell::I8 | Cell::I32)
+        || !matches!(config.overflow, Overflow::Error | Overflow::Wrap)
+        || matches!(config.eof, Eof::Error)
+    {
+        return None;
+    }
+
+    let nodes = structure(instructions).ok()?;
+    let mut asm = Assembler::new();
+    let mut loops = vec![];
+    // The jumps to stop before the instructions at the counters.
+    let mut stops: Vec<(Patch, usize)> = vec![];
+    let mut failures = vec![];
+
+    asm.prologue();
+
+    for node in nodes {
+        match node {
+            Node::Instruction { addr, ins } => match ins {
+                Instruction::Add { val } => {
+                    asm.load_cell();
+                    asm.add_eax(*val);
+
+                    match (&config.cell, &config.overflow) {
+                        (Cell::I8, Overflow::Wrap) => asm.sign_extend_eax(),
+                        (Cell::I32, Overflow::Wrap) => {}
+                        (Cell::I8, Overflow::Error) => {
+                            stops.push((asm.jump(Condition::Overflow), addr));
+                            asm.check_eax_byte();
+                            stops.push((asm.jump(Condition::NotEqual), addr));
+                        }
+                        (Cell::I32, Overflow::Error) => {
+                            stops.push((asm.jump(Condition::Overflow), addr));
+                        }
+                        _ => unreachable!(),
+                    }
+
+                    asm.store_cell();
+                }
+                Instruction::Seek { offset } => {
+                    let disp = displacement(*offset)?;
+                    asm.lea_rax(disp);
+
+                    if disp < 0 {
+                        asm.cmp_rax_left();
+                        stops.push((asm.jump(Condition::Below), addr));
+                    } else {
+                        asm.cmp_rax_right();
+                        stops.push((asm.jump(Condition::Above), addr));
+                    }
+
+                    asm.move_rax_to_current();
+                }
+                Instruction::Clear => {
+                    // A negative cell overflows before it's cleared.
+                    if let Overflow::Error = config.overflow {
+                        asm.test_cell();
+                        stops.push((asm.jump(Condition::Less), addr));
+                    }
+
+                    asm.clear_cell();
+                }
+                Instruction::AddUntilZero { target } => {
+                    compile_add_until_zero(&mut asm, config, addr, target, &mut stops)?
+                }
+                Instruction::Input => {
+                    asm.call_with_address(input as *const () as usize);
+                    asm.reload_current();
+                    asm.test_eax();
+                    failures.push(asm.jump(Condition::NotEqual));
+                }
+                Instruction::Output => asm.call_with_value(output as *const () as usize),
+                _ => unreachable!(),
+            },
+            Node::LoopStart { .. } => {
+                asm.test_cell();
+                let exit = asm.jump(Condition::Equal);
+                loops.push((asm.position(), exit));
+            }
+            Node::LoopEnd { .. } => {
+                let (body, exit) = loops.pop().unwrap();
+                asm.test_cell();
+                asm.jump_to(Condition::NotEqual, body);
+                let end = asm.position();
+                asm.patch(exit, end);
+            }
+        }
+    }
+
+    asm.set_result(HALTED);
+    let epilogue = asm.position();
+    asm.epilogue();
+
+    stops.sort_by_key(|(_, counter)| *counter);
+
+    for group in stops.chunk_by(|(_, a), (_, b)| a == b) {
+        let stub = asm.position();
+        asm.set_counter(i32::try_from(group[0].1).ok()?);
+        asm.set_result(STOPPED);
+        asm.jump_to(Condition::Always, epilogue);
+
+        for (patch, _) in group {
+            asm.patch(*patch, stub);
+        }
+    }
+
+    let stub = asm.position();
+    asm.set_result(FAILED);
+    asm.jump_to(Condition::Always, epilogue);
+
+    for patch in failures {
+        asm.patch(patch, stub);
+    }
+
+    Some(asm.finish())
+}
+
+/// Compile `AddUntilZero`, which checks all the targets before changing any of
+/// them, so that it can stop before the instruction.
+fn compile_add_until_zero(
+    asm: &mut Assembler,
+    config: &MemoryConfig,
+    addr: usize,
+    target: &[AddUntilZeroArg],
+    stops: &mut Vec<(Patch, usize)>,
+) -> Option<()> {
+    let mut offsets: Vec<isize> = target.iter().map(|arg| arg.offset).collect();
+    offsets.sort();
+    offsets.dedup();
+
+    // Overlapping targets can't be checked in advance, so let the processor
+    // run them.
+    if offsets.len() != target.len() || offsets.contains(&0) {
+        stops.push((asm.jump(Condition::Always), addr));
+        return Some(());
+    }
+
+    asm.load_cell();
+    asm.test_eax();
+    let skip = asm.jump(Condition::Equal);
+
+    // A negative counter overflows before it reaches 0.
+    if let Overflow::Error = config.overflow {
+        stops.push((asm.jump(Condition::Less), addr));
+    }
+
+    if let Some(&min) = offsets.first().filter(|&&min| min < 0) {
+        asm.lea_rdx(displacement(min)?);
+        asm.cmp_rdx_left();
+        stops.push((asm.jump(Condition::Below), addr));
+    }
+
+    if let Some(&max) = offsets.last().filter(|&&max| max > 0) {
+        asm.lea_rdx(displacement(max)?);
+        asm.cmp_rdx_right();
+        stops.push((asm.jump(Condition::Above), addr));
+    }
+
+    if let Overflow::Error = config.overflow {
+        for AddUntilZeroArg { offset, times } in target {
+            asm.mul_eax_to_ecx(*times);
+            stops.push((asm.jump(Condition::Overflow), addr));
+            asm.add_cell_to_ecx(displacement(*offset)?);
+            stops.push((asm.jump(Condition::Overflow), addr));
+
+            if let Cell::I8 = config.cell {
+                asm.check_ecx_byte();
+                stops.push((asm.jump(Condition::NotEqual), addr));
+            }
+        }
+    }
+
+    asm.clear_cell();
+
+    for AddUntilZeroArg { offset, times } in target {
+        let disp = displacement(*offset)?;
+        asm.mul_eax_to_ecx(*times);
+        asm.add_cell_to_ecx(disp);
+
+        if let Cell::I8 = config.cell {
+            asm.sign_extend_ecx();
+        }
+
+        asm.store_ecx(disp);
+    }
+
+    let end = asm.position();
+    asm.patch(skip, end);
+    Some(())
+}
+
+type Function = unsafe extern "C" fn(*mut Runtime, *mut i64, *mut i64, *mut i64) -> i32;
+
+/// Machine code in an executable memory mapping.
+struct Executable {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Executable {
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn new(code: &[u8]) -> Option<Self> {
+        let len = code.len();
+
+        // SAFETY: A new private mapping is created, written and then made
+        // executable instead of writable.
+        unsafe {
+            let ptr = libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+
+            if ptr == libc::MAP_FAILED {
+                return None;
+            }
+
+            let executable = Self {
+                ptr: ptr as *mut u8,
+                len,
+            };
+            std::ptr::copy_nonoverlapping(code.as_ptr(), executable.ptr, len);
+
+            if libc::mprotect(ptr, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                return None;
+            }
+
+            Some(executable)
+        }
+    }
+
+    #[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+    fn new(_code: &[u8]) -> Option<Self> {
+        None
+    }
+
+    unsafe fn call(
+        &self,
+        runtime: *mut Runtime,
+        leftmost: *mut i64,
+        rightmost: *mut i64,
+        current: *mut i64,
+    ) -> i32 {
+        let function: Function = std::mem::transmute(self.ptr);
+        function(runtime, leftmost, rightmost, current)
+    }
+}
+
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+impl Drop for Executable {
+    fn drop(&mut self) {
+        // SAFETY: The mapping is created by `new` and no longer used.
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::execution::memory::config::{Addr, Eof};
+    use crate::execution::stream::config::{Config as StreamConfig, Input, Output};
+
+    type State = (Result<()>, Vec<i128>, Vec<i128>, isize);
+
+    /// Run the program with the JIT or the processor and return the result,
+    /// the output, the tape and the pointer.
+    fn run(code: &str, config: &MemoryConfig, input: &[i32], jit: bool) -> State {
+        let instructions = Compiler::new().compile(code).unwrap();
+        let input = Rc::new(RefCell::new(input.iter().copied().collect()));
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        let stream_config = StreamConfig {
+            input: Input::Vec(input),
+            output: Output::Vec(output.clone()),
+        };
+        let mut context = Context::new(config.clone(), stream_config);
+
+        let res = if jit {
+            let jit = Jit::new(instructions).unwrap();
+            assert!(jit.is_supported(&context));
+            jit.run(&mut context)
+        } else {
+            Processor::new(instructions).unwrap().run(&mut context)
+        };
+
+        let memory = &context.memory;
+        let range = memory.range();
+        let tape = (range.left..=range.right)
+            .map(|addr| memory.get_at(addr).unwrap())
+            .collect();
+        let output = output.borrow().iter().copied().collect();
+        (res, output, tape, memory.position())
+    }
+
+    fn configs() -> Vec<MemoryConfig> {
+        let mut configs = vec![];
+
+        for addr in [Addr::Unsigned, Addr::Signed] {
+            for cell in [Cell::I8, Cell::I32] {
+                for overflow in [Overflow::Error, Overflow::Wrap] {
+                    for eof in [Eof::Zero, Eof::Keep, Eof::Ignore, Eof::Value(100)] {
+                        configs.push(MemoryConfig {
+                            len: 64,
+                            addr: addr.clone(),
+                            cell: cell.clone(),
+                            overflow: overflow.clone(),
+                            eof: eof.clone(),
+                            storage: Storage::Dense,
+                            init: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        configs
+    }
+
+    #[test]
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    fn run_like_processor() {
+        const PROGRAMS: &[&str] = &[
+            include_str!("../../../../../examples/helloworld.bf"),
+            include_str!("../../../../../examples/squares.bf"),
+            ",[.,]",
+            ",[->+>+<<]>[-<+>]>.<<.",
+            "++>+++[-<[->>+<<]>]>>.",
+            "+++++[>+++++[>+++++<-]<-]>>.",
+            "-[->+++<]>.",
+            ",[->+++<]>.",
+            "+[<+]",
+            "+[>+]",
+            "+[->-<]>.",
+            "-[[-]>+<]>.",
+            "-[->+<]>.",
+        ];
+        let input = [3, 200, -1, 0, 77];
+
+        for code in PROGRAMS {
+            for config in configs() {
+                let expected = run(code, &config, &input, false);
+                let actual = run(code, &config, &input, true);
+                assert_eq!(actual, expected, "`{code}` with {config:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn fall_back_to_processor() {
+        // The offset is too large to be compiled.
+        let instructions = InstructionList(vec![
+            Instruction::Output,
+            Instruction::Seek {
+                offset: isize::MAX / 2,
+            },
+            Instruction::Halt,
+        ]);
+        let config = MemoryConfig::default();
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        let stream_config = StreamConfig {
+            input: Input::Null,
+            output: Output::Vec(output.clone()),
+        };
+        let mut context = Context::new(config, stream_config);
+
+        let jit = Jit::new(instructions).unwrap();
+        assert!(!jit.is_supported(&context));
+        assert!(matches!(
+            jit.run(&mut context),
+            Err(ProcessorError::Memory { .. })
+        ));
+        assert_eq!(*output.borrow(), [0]);
+
+        // The machine code only works on a fixed range of `i8` or `i32` cells
+        // stored together, which either wrap or report an error on overflow.
+        let jit = Jit::new(Compiler::new().compile("+[>+]").unwrap()).unwrap();
+        let configs = [
+            MemoryConfig {
+                addr: Addr::Dynamic { max: None },
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                addr: Addr::Circular,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                storage: Storage::Sparse,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                cell: Cell::U8,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                overflow: Overflow::ErrorOnNegative,
+                ..MemoryConfig::default()
+            },
+            MemoryConfig {
+                eof: Eof::Error,
+                ..MemoryConfig::default()
+            },
+        ];
+
+        for config in configs {
+            let stream_config = StreamConfig {
+                input: Input::Null,
+                output: Output::Vec(output.clone()),
+            };
+            let context = Context::new(config, stream_config);
+            assert!(!jit.is_supported(&context));
+        }
+    }
+}
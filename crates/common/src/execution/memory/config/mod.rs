@@ -1,12 +1,20 @@
-use super::DEFAULT_LEN;
+use snafu::prelude::*;
 
-#[derive(Clone)]
+use super::{
+    AccessOutOfBoundsSnafu, AddrRange, EmptySnafu, MemoryError, TooLargeSnafu, DEFAULT_LEN,
+    MAX_DENSE_LEN,
+};
+
+#[derive(Debug, Clone)]
 pub struct Config {
     pub len: usize,
     pub addr: Addr,
     pub cell: Cell,
     pub overflow: Overflow,
     pub eof: Eof,
+    pub storage: Storage,
+    /// The data preloaded to the memory before the program runs.
+    pub init: Option<Init>,
 }
 
 impl Default for Config {
@@ -17,31 +25,139 @@ impl Default for Config {
             cell: Cell::I8,
             overflow: Overflow::Error,
             eof: Eof::Ignore,
+            storage: Storage::Dense,
+            init: None,
         }
     }
 }
 
-#[derive(Clone)]
+impl Config {
+    /// Get the abstract address range of the memory built from this config,
+    /// which is only the initial one if the memory grows.
+    pub fn range(&self) -> AddrRange {
+        match self.addr {
+            Addr::Unsigned | Addr::Circular => AddrRange {
+                left: 0,
+                right: self.len as isize - 1,
+            },
+            Addr::Signed => {
+                let half_len = self.len.div_ceil(2) as isize;
+                AddrRange {
+                    left: -half_len,
+                    right: half_len - 1,
+                }
+            }
+            Addr::Dynamic { max } => {
+                let len = self.len.clamp(1, max.unwrap_or(usize::MAX).max(1));
+                AddrRange {
+                    left: 0,
+                    right: len as isize - 1,
+                }
+            }
+        }
+    }
+
+    /// Check that the memory built from this config can be allocated, which
+    /// means it has at least one cell, the preloaded data fits in it, and a
+    /// dense one has at most `MAX_DENSE_LEN` cells including the ones it grows
+    /// to for the data.
+    pub fn check(&self) -> Result<(), MemoryError> {
+        ensure!(self.len != 0, EmptySnafu);
+        let range = self.range();
+        let mut len = range.len();
+
+        if let Some(Init { addr, data }) = self.init.as_ref().filter(|init| !init.data.is_empty()) {
+            let (left, right) = (*addr, addr.saturating_add(data.len() as isize - 1));
+            let fits = match self.addr {
+                Addr::Unsigned | Addr::Signed => range.contains(left) && range.contains(right),
+                Addr::Circular => true,
+                Addr::Dynamic { max } => {
+                    len = right
+                        .max(range.right)
+                        .abs_diff(left.min(range.left))
+                        .saturating_add(1);
+                    max.is_none_or(|max| len <= max)
+                }
+            };
+            let addr = if range.contains(left) { right } else { left };
+            ensure!(fits, AccessOutOfBoundsSnafu { addr, range });
+        }
+
+        ensure!(
+            matches!(self.storage, Storage::Sparse) || len <= MAX_DENSE_LEN,
+            TooLargeSnafu { len }
+        );
+        Ok(())
+    }
+}
+
+/// The data written to the cells from `addr` to the right, one byte per cell.
+#[derive(Debug, Clone)]
+pub struct Init {
+    pub addr: isize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub enum Addr {
     Unsigned,
     Signed,
+    /// `[0, len - 1]` with the pointer wrapping around at both ends.
+    Circular,
+    /// Start from `[0, len - 1]` and grow in both directions when the pointer
+    /// moves out of the range, up to `max` cells in total if it's given.
+    Dynamic {
+        max: Option<usize>,
+    },
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Cell {
     I8,
+    U8,
+    I16,
+    U16,
     I32,
+    U32,
+    I64,
+    U64,
+    /// A signed or unsigned integer of 1 to 64 bits.
+    Bits {
+        bits: u32,
+        signed: bool,
+    },
+    /// An arbitrary-precision integer, which never overflows.
+    Unbounded,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Overflow {
     Error,
     Wrap,
+    /// Clamp the value to the range of a cell.
+    Saturate,
+    /// Report an error if the value goes below 0, and wrap it if it exceeds
+    /// the maximum of a cell, which treats the cells as unsigned.
+    ErrorOnNegative,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Eof {
     Zero,
     Keep,
     Ignore,
+    /// Turn EOF to a value, e.g. 255 for the programs expecting unsigned
+    /// bytes.
+    Value(i64),
+    /// Report an error when reading EOF.
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub enum Storage {
+    /// Allocate all the cells at once.
+    Dense,
+    /// Allocate the cells page by page when they're written to, which allows a
+    /// huge memory with only a few cells used.
+    Sparse,
 }
@@ -0,0 +1,149 @@
+//! The strategies of a memory dispatched by `match` instead of virtual calls,
+//! which lets the built-in strategies be inlined into the hot path. The custom
+//! strategies given to `Memory::new` are still called through trait objects.
+
+use super::strategy::*;
+use super::Result;
+
+/// Expand to a `match` calling the same code on the strategy in each variant.
+macro_rules! dispatch {
+    ($self:expr, $enum:ident { $($variant:ident),* }, $s:ident => $call:expr) => {
+        match $self {
+            $($enum::$variant($s) => $call,)*
+        }
+    };
+}
+
+pub(crate) enum AnyAddrStrategy {
+    Unsigned(UnsignedAddrStrategy),
+    Signed(SignedAddrStrategy),
+    Circular(CircularAddrStrategy),
+    Dynamic(DynamicAddrStrategy),
+    Custom(Box<dyn AddrStrategy>),
+}
+
+macro_rules! addr {
+    ($self:expr, $s:ident => $call:expr) => {
+        dispatch!($self, AnyAddrStrategy { Unsigned, Signed, Circular, Dynamic, Custom }, $s => $call)
+    };
+}
+
+impl AddrStrategy for AnyAddrStrategy {
+    #[inline]
+    fn initial(&self) -> isize {
+        addr!(self, s => s.initial())
+    }
+
+    #[inline]
+    fn seek(&self, addr: isize, offset: isize) -> Result<isize> {
+        addr!(self, s => s.seek(addr, offset))
+    }
+
+    #[inline]
+    fn calc(&self, addr: isize) -> usize {
+        addr!(self, s => s.calc(addr))
+    }
+
+    #[inline]
+    fn range(&self) -> AddrRange {
+        addr!(self, s => s.range())
+    }
+
+    fn grow(&mut self, addr: isize) -> Option<(usize, usize)> {
+        addr!(self, s => s.grow(addr))
+    }
+
+    #[inline]
+    fn wrap(&self, addr: isize) -> Option<isize> {
+        addr!(self, s => s.wrap(addr))
+    }
+}
+
+pub(crate) enum AnyCellStrategy {
+    I8(I8CellStrategy),
+    I32(I32CellStrategy),
+    Bits(BitsCellStrategy),
+    Unbounded(UnboundedCellStrategy),
+    Custom(Box<dyn CellStrategy>),
+}
+
+macro_rules! cell {
+    ($self:expr, $s:ident => $call:expr) => {
+        dispatch!($self, AnyCellStrategy { I8, I32, Bits, Unbounded, Custom }, $s => $call)
+    };
+}
+
+impl CellStrategy for AnyCellStrategy {
+    #[inline]
+    fn min(&self) -> i128 {
+        cell!(self, s => s.min())
+    }
+
+    #[inline]
+    fn max(&self) -> i128 {
+        cell!(self, s => s.max())
+    }
+
+    #[inline]
+    fn is_overflowed(&self, num: i128) -> bool {
+        cell!(self, s => s.is_overflowed(num))
+    }
+
+    #[inline]
+    fn wrap(&self, num: i128) -> i128 {
+        cell!(self, s => s.wrap(num))
+    }
+
+    #[inline]
+    fn is_unbounded(&self) -> bool {
+        cell!(self, s => s.is_unbounded())
+    }
+}
+
+pub(crate) enum AnyOverflowStrategy {
+    Error(ErrorOverflowStrategy),
+    Wrap(WrapOverflowStrategy),
+    Saturate(SaturateOverflowStrategy),
+    ErrorOnNegative(ErrorOnNegativeOverflowStrategy),
+    Custom(Box<dyn OverflowStrategy>),
+}
+
+macro_rules! overflow {
+    ($self:expr, $s:ident => $call:expr) => {
+        dispatch!($self, AnyOverflowStrategy { Error, Wrap, Saturate, ErrorOnNegative, Custom }, $s => $call)
+    };
+}
+
+impl OverflowStrategy for AnyOverflowStrategy {
+    #[inline]
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128> {
+        overflow!(self, s => s.add(cell_strategy, before, add))
+    }
+
+    #[inline]
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128> {
+        overflow!(self, s => s.set(cell_strategy, val))
+    }
+}
+
+pub(crate) enum AnyEofStrategy {
+    Zero(ZeroEofStrategy),
+    Keep(KeepEofStrategy),
+    Ignore(IgnoreEofStrategy),
+    Value(ValueEofStrategy),
+    Error(ErrorEofStrategy),
+    Custom(Box<dyn EofStrategy>),
+}
+
+macro_rules! eof {
+    ($self:expr, $s:ident => $call:expr) => {
+        dispatch!($self, AnyEofStrategy { Zero, Keep, Ignore, Value, Error, Custom }, $s => $call)
+    };
+}
+
+impl EofStrategy for AnyEofStrategy {
+    #[inline]
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        eof!(self, s => s.check(input))
+    }
+}
@@ -0,0 +1,207 @@
+//! Write the cells of a memory and the pointer in the formats other tools can
+//! read.
+
+use std::io::{self, Write};
+
+use num_bigint::BigInt;
+
+use super::{AddrRange, Memory};
+
+/// The number of cells in a line of a hex dump.
+const HEX_LINE_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The lowest byte of each cell, without the pointer.
+    Raw,
+    /// The lowest byte of each cell in the layout of `hexdump -C`, after a
+    /// line with the pointer.
+    Hex,
+    /// A line of `address,value,pointer` for each cell, where `pointer` is 1
+    /// for the cell the pointer points to and 0 for the others.
+    Csv,
+    /// An object with the pointer, the address of the first cell and the
+    /// values of the cells.
+    Json,
+}
+
+impl Memory {
+    /// Get the smallest range containing the pointer and all the cells which
+    /// aren't zero, which are the cells touched by the program unless the
+    /// memory is preloaded.
+    pub fn touched(&self) -> AddrRange {
+        let init = AddrRange {
+            left: self.cur,
+            right: self.cur,
+        };
+
+        self.cells()
+            .filter(|(_, val)| *val != 0)
+            .fold(init, |range, (addr, _)| AddrRange {
+                left: range.left.min(addr),
+                right: range.right.max(addr),
+            })
+    }
+
+    /// Write the cells in the touched range and the pointer.
+    pub fn dump(&self, format: Format, out: &mut dyn Write) -> io::Result<()> {
+        let range = self.touched();
+
+        match format {
+            Format::Raw => {
+                for addr in range.left..=range.right {
+                    out.write_all(&[self.byte_at(addr)])?;
+                }
+            }
+            Format::Hex => self.dump_hex(range, out)?,
+            Format::Csv => {
+                writeln!(out, "address,value,pointer")?;
+
+                for addr in range.left..=range.right {
+                    let pointer = (addr == self.cur) as u8;
+                    writeln!(out, "{addr},{},{pointer}", self.value_at(addr))?;
+                }
+            }
+            Format::Json => {
+                write!(
+                    out,
+                    "{{\"pointer\":{},\"start\":{},\"cells\":[",
+                    self.cur, range.left
+                )?;
+
+                for addr in range.left..=range.right {
+                    let comma = if addr == range.left { "" } else { "," };
+                    write!(out, "{comma}{}", self.value_at(addr))?;
+                }
+
+                writeln!(out, "]}}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn dump_hex(&self, range: AddrRange, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "pointer: {}", self.cur)?;
+
+        for start in (range.left..=range.right).step_by(HEX_LINE_LEN) {
+            let end = range.right.min(start + HEX_LINE_LEN as isize - 1);
+            let bytes: Vec<u8> = (start..=end).map(|addr| self.byte_at(addr)).collect();
+
+            if start < 0 {
+                write!(out, "-{:07x}  ", start.unsigned_abs())?;
+            } else {
+                write!(out, "{start:08x}  ")?;
+            }
+
+            for i in 0..HEX_LINE_LEN {
+                match bytes.get(i) {
+                    Some(byte) => write!(out, "{byte:02x} ")?,
+                    None => write!(out, "   ")?,
+                }
+
+                if i == HEX_LINE_LEN / 2 - 1 {
+                    write!(out, " ")?;
+                }
+            }
+
+            let text: String = bytes
+                .iter()
+                .map(|byte| match byte {
+                    b' ' | b'!'..=b'~' => *byte as char,
+                    _ => '.',
+                })
+                .collect();
+            writeln!(out, " |{text}|")?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the full value of a cell in the range.
+    fn value_at(&self, addr: isize) -> BigInt {
+        self.get_big_at(addr).unwrap()
+    }
+
+    /// Get the lowest byte of a cell in the range in two's complement.
+    fn byte_at(&self, addr: isize) -> u8 {
+        if self.unbounded {
+            let byte = self.value_at(addr) & BigInt::from(u8::MAX);
+            u8::try_from(byte).unwrap()
+        } else {
+            self.get_at(addr).unwrap() as u8
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execution::memory::config::{Addr, Cell};
+    use crate::execution::memory::Builder;
+
+    fn dump(memory: &Memory, format: Format) -> String {
+        let mut out = vec![];
+        memory.dump(format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn dump_formats() {
+        let mut memory = Builder::new().addr(Addr::Signed).build();
+        memory.preload(-2, b"Hi").unwrap();
+        memory.set_at(17, -2).unwrap();
+        memory.seek(3).unwrap();
+        assert_eq!(
+            memory.touched(),
+            AddrRange {
+                left: -2,
+                right: 17
+            }
+        );
+
+        let mut raw = vec![];
+        memory.dump(Format::Raw, &mut raw).unwrap();
+        let mut expected = vec![0; 20];
+        expected[..2].copy_from_slice(b"Hi");
+        expected[19] = 0xfe;
+        assert_eq!(raw, expected);
+
+        let hex = "pointer: 3
+-0000002  48 69 00 00 00 00 00 00  00 00 00 00 00 00 00 00  |Hi..............|
+0000000e  00 00 00 fe                                       |....|
+";
+        assert_eq!(dump(&memory, Format::Hex), hex);
+
+        let csv = dump(&memory, Format::Csv);
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 21);
+        assert_eq!(lines[..3], ["address,value,pointer", "-2,72,0", "-1,105,0"]);
+        assert_eq!(lines[6], "3,0,1");
+        assert_eq!(lines[20], "17,-2,0");
+
+        let json = dump(&memory, Format::Json);
+        let cells = format!("72,105{},-2", ",0".repeat(17));
+        assert_eq!(
+            json,
+            format!("{{\"pointer\":3,\"start\":-2,\"cells\":[{cells}]}}\n")
+        );
+
+        // The big values are written in full, and only their lowest bytes
+        // are in the raw bytes.
+        let mut memory = Builder::new().cell(Cell::Unbounded).build();
+        memory.add(1 << 100).unwrap();
+        memory.add(-1).unwrap();
+        assert_eq!(
+            dump(&memory, Format::Json),
+            format!(
+                "{{\"pointer\":0,\"start\":0,\"cells\":[{}]}}\n",
+                (1i128 << 100) - 1
+            )
+        );
+
+        let mut raw = vec![];
+        memory.dump(Format::Raw, &mut raw).unwrap();
+        assert_eq!(raw, [0xff]);
+    }
+}
@@ -1,13 +1,25 @@
 pub mod config;
+mod dispatch;
+pub mod dump;
+mod storage;
 mod strategy;
 
-use config::{Addr, Cell, Config, Eof, Overflow};
+use std::collections::HashMap;
+
+use config::{Addr, Cell, Config, Eof, Init, Overflow, Storage};
+use dispatch::{AnyAddrStrategy, AnyCellStrategy, AnyEofStrategy, AnyOverflowStrategy};
+use num_bigint::{BigInt, Sign};
 use snafu::prelude::*;
+use storage::Cells;
+pub use storage::PAGE_LEN;
 pub use strategy::AddrRange;
 use strategy::{AddrStrategy, CellStrategy, EofStrategy, OverflowStrategy};
 
 pub type Result<T> = std::result::Result<T, MemoryError>;
 
+/// The stored value of an unbounded cell whose value is in `Memory::big`.
+const BIG: i64 = i64::MIN;
+
 #[derive(Snafu, Debug, PartialEq, Eq)]
 pub enum MemoryError {
     #[snafu(display("try to seek pointer from {} to {}, which is out of [{}, {}]",
@@ -21,113 +33,425 @@ pub enum MemoryError {
     range.left, range.right))]
     AccessOutOfBounds { addr: isize, range: AddrRange },
     #[snafu(display("{before} + {add} will overflow"))]
-    AddOverflow { before: i32, add: i32 },
+    AddOverflow { before: i128, add: i128 },
     #[snafu(display("{val} will overflow"))]
-    SetOverflow { val: i32 },
+    SetOverflow { val: i128 },
+    #[snafu(display("unexpected EOF"))]
+    UnexpectedEof,
+    #[snafu(display("a dense memory of {len} cells is too large, which has to be sparse"))]
+    TooLarge { len: usize },
+    #[snafu(display("the memory must have at least one cell"))]
+    Empty,
 }
 
 pub struct Memory {
-    memory: Vec<i32>,
+    memory: Cells,
     cur: isize,
-    addr_strategy: Box<dyn AddrStrategy>,
-    cell_strategy: Box<dyn CellStrategy>,
-    eof_strategy: Box<dyn EofStrategy>,
-    overflow_strategy: Box<dyn OverflowStrategy>,
+    /// The range of the address strategy, which only changes when it grows.
+    range: AddrRange,
+    addr_strategy: AnyAddrStrategy,
+    cell_strategy: AnyCellStrategy,
+    eof_strategy: AnyEofStrategy,
+    overflow_strategy: AnyOverflowStrategy,
+    /// Whether the cells are stored as unsigned 64-bit integers, because their
+    /// values don't fit in `i64`.
+    unsigned: bool,
+    /// Whether the cells are arbitrary-precision integers.
+    unbounded: bool,
+    /// The values of the unbounded cells which don't fit in `i64` by their
+    /// addresses, whose stored values are `BIG`.
+    big: HashMap<isize, BigInt>,
+    /// The config the memory is built from, which is unknown when it's built
+    /// from custom strategies.
+    config: Option<Config>,
 }
 
 impl Memory {
+    /// Create a memory from custom strategies, which are called through trait
+    /// objects. The memories built by `Builder` dispatch the built-in
+    /// strategies statically instead.
     pub fn new(
         addr_strategy: Box<dyn AddrStrategy>,
         cell_strategy: Box<dyn CellStrategy>,
         eof_strategy: Box<dyn EofStrategy>,
         overflow_strategy: Box<dyn OverflowStrategy>,
     ) -> Self {
-        let memory = vec![0; addr_strategy.range().len()];
+        Self::with_storage(
+            AnyAddrStrategy::Custom(addr_strategy),
+            AnyCellStrategy::Custom(cell_strategy),
+            AnyEofStrategy::Custom(eof_strategy),
+            AnyOverflowStrategy::Custom(overflow_strategy),
+            &Storage::Dense,
+        )
+    }
+
+    fn with_storage(
+        addr_strategy: AnyAddrStrategy,
+        cell_strategy: AnyCellStrategy,
+        eof_strategy: AnyEofStrategy,
+        overflow_strategy: AnyOverflowStrategy,
+        storage: &Storage,
+    ) -> Self {
+        let range = addr_strategy.range();
+        let memory = match storage {
+            Storage::Dense => Cells::dense(range.len()),
+            // Align the pages to the addresses.
+            Storage::Sparse => Cells::sparse(range.len(), -range.left),
+        };
         let cur = addr_strategy.initial();
+        let unbounded = cell_strategy.is_unbounded();
+        let unsigned = !unbounded && cell_strategy.max() > i64::MAX as i128;
         Self {
             memory,
             cur,
+            range,
             addr_strategy,
             cell_strategy,
             eof_strategy,
             overflow_strategy,
+            unsigned,
+            unbounded,
+            big: HashMap::new(),
+            config: None,
         }
     }
 
+    pub fn config(&self) -> Option<&Config> {
+        self.config.as_ref()
+    }
+
+    #[inline]
     pub fn seek(&mut self, offset: isize) -> Result<()> {
-        self.cur = self.addr_strategy.seek(self.cur, offset)?;
+        let target = self.addr_strategy.seek(self.cur, offset)?;
+
+        if !self.range.contains(target) {
+            ensure!(
+                self.grow(target),
+                SeekOutOfBoundsSnafu {
+                    now_position: self.cur,
+                    offset,
+                    range: self.range
+                }
+            );
+        }
+
+        self.cur = target;
         Ok(())
     }
 
+    /// Grow the memory to contain `addr` if the address strategy allows.
+    #[inline(never)]
+    fn grow(&mut self, addr: isize) -> bool {
+        let Some((left, right)) = self.addr_strategy.grow(addr) else {
+            return false;
+        };
+
+        self.memory.grow(left, right);
+        self.range = self.addr_strategy.range();
+        true
+    }
+
     pub fn position(&self) -> isize {
         self.cur
     }
 
-    pub fn add(&mut self, add: i32) -> Result<()> {
+    #[inline]
+    pub fn add(&mut self, add: i128) -> Result<()> {
         self.add_at(self.cur, add)
     }
 
-    pub fn add_at(&mut self, addr: isize, add: i32) -> Result<()> {
-        ensure!(
-            self.range().contains(addr),
-            AccessOutOfBoundsSnafu {
-                addr,
-                range: self.range()
-            }
-        );
-        let addr = self.addr_strategy.calc(addr);
-        let target = self.memory.get_mut(addr).unwrap();
-        let strategy = self.cell_strategy.as_ref();
-        let res = self.overflow_strategy.add(strategy, *target, add)?;
-        *target = res;
+    #[inline]
+    pub fn add_at(&mut self, addr: isize, add: i128) -> Result<()> {
+        let index = self.index_mut(addr)?;
+
+        if self.unbounded {
+            self.add_unbounded(index, add);
+            return Ok(());
+        }
+
+        let before = self.load(index);
+        let res = self
+            .overflow_strategy
+            .add(&self.cell_strategy, before, add)?;
+        *self.memory.get_mut(index) = res as i64;
         Ok(())
     }
 
-    pub fn set(&mut self, val: i32) -> Result<()> {
+    /// Set the current cell to a value, or to the input read into it, which is
+    /// checked by the EOF strategy.
+    #[inline]
+    pub fn set(&mut self, val: i128) -> Result<()> {
         self.set_at(self.cur, val)
     }
 
-    pub fn set_at(&mut self, addr: isize, val: i32) -> Result<()> {
-        ensure!(
-            self.range().contains(addr),
-            AccessOutOfBoundsSnafu {
-                addr,
-                range: self.range()
+    #[inline]
+    pub fn set_at(&mut self, addr: isize, val: i128) -> Result<()> {
+        let index = self.index_mut(addr)?;
+
+        if let Some(res) = self.eof_strategy.check(val)? {
+            if self.unbounded {
+                self.set_unbounded(index, res);
+            } else {
+                let res = self.overflow_strategy.set(&self.cell_strategy, res)?;
+                *self.memory.get_mut(index) = res as i64;
             }
-        );
-        let addr = self.addr_strategy.calc(addr);
-        let target = self.memory.get_mut(addr).unwrap();
+        }
+
+        Ok(())
+    }
+
+    /// Write the data to the cells from `addr` to the right, one byte per cell,
+    /// which is wrapped to the cells like `Overflow::Wrap` does.
+    pub fn preload(&mut self, addr: isize, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        // Grow the memory or fail before any cell is written.
+        self.index_mut(addr)?;
+        self.index_mut(addr + data.len() as isize - 1)?;
+
+        for (i, byte) in data.iter().enumerate() {
+            let index = self.index_mut(addr + i as isize)?;
+            let val = *byte as i128;
 
-        if let Some(res) = self.eof_strategy.check(val) {
-            let strategy = self.cell_strategy.as_ref();
-            let res = self.overflow_strategy.set(strategy, res)?;
-            *target = res;
+            if self.unbounded {
+                self.set_unbounded(index, val);
+            } else if self.cell_strategy.is_overflowed(val) {
+                *self.memory.get_mut(index) = self.cell_strategy.wrap(val) as i64;
+            } else {
+                *self.memory.get_mut(index) = val as i64;
+            }
         }
 
         Ok(())
     }
 
-    pub fn get(&self) -> i32 {
+    /// Add an arbitrary-precision integer to the current cell, which must be
+    /// unbounded.
+    pub(crate) fn add_big(&mut self, add: &BigInt) -> Result<()> {
+        debug_assert!(self.unbounded);
+        let index = self.index_mut(self.cur)?;
+        let res = self.load_big(index) + add;
+        self.store_big(index, res);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get(&self) -> i128 {
         self.get_at(self.cur).unwrap()
     }
 
-    pub fn get_at(&self, addr: isize) -> Result<i32> {
-        ensure!(
-            self.range().contains(addr),
-            AccessOutOfBoundsSnafu {
-                addr,
-                range: self.range()
+    /// Get the value of a cell, which is clamped to `i128` if the cell is
+    /// unbounded. Use `get_big_at` to get the full value.
+    #[inline]
+    pub fn get_at(&self, addr: isize) -> Result<i128> {
+        Ok(self.load(self.index(addr)?))
+    }
+
+    pub fn get_big(&self) -> BigInt {
+        self.get_big_at(self.cur).unwrap()
+    }
+
+    pub fn get_big_at(&self, addr: isize) -> Result<BigInt> {
+        Ok(self.load_big(self.index(addr)?))
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.unbounded
+    }
+
+    /// Return whether counting a cell down to zero while adding to some other
+    /// cells round after round gives the same result as adding the totals at
+    /// once, where `monotonic` tells whether every value only moves one way
+    /// until the counter reaches zero, i.e. the counter is positive and every
+    /// cell is added to only once in a round. It's always true for the
+    /// wrapping values, and true for the other built-in strategies as long as
+    /// the values are monotonic.
+    pub fn merges_adds(&self, monotonic: bool) -> bool {
+        match self.overflow_strategy {
+            AnyOverflowStrategy::Wrap(_) => true,
+            AnyOverflowStrategy::Custom(_) => false,
+            _ => monotonic,
+        }
+    }
+
+    /// Check whether the current cell fails when it's counted down to 0 by 1
+    /// at a time, which is the case for a negative value reporting the
+    /// overflow once it goes below the minimum. It saves running the
+    /// iterations one by one only to fail in the end.
+    pub fn check_count_down(&self) -> Result<()> {
+        match self.overflow_strategy {
+            AnyOverflowStrategy::Error(_) if self.get() < 0 => Err(MemoryError::AddOverflow {
+                before: self.cell_strategy.min(),
+                add: -1,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Convert a stored cell back to its value.
+    #[inline]
+    fn load(&self, index: usize) -> i128 {
+        let raw = self.memory.get(index);
+
+        if self.unsigned {
+            raw as u64 as i128
+        } else if self.unbounded && raw == BIG {
+            self.load_clamped(index)
+        } else {
+            raw as i128
+        }
+    }
+
+    /// Get the value of an unbounded cell whose stored value is `BIG`, which is
+    /// kept out of `load` so it stays small enough to be inlined.
+    #[inline(never)]
+    fn load_clamped(&self, index: usize) -> i128 {
+        clamp(&self.big[&self.address(index)])
+    }
+
+    fn load_big(&self, index: usize) -> BigInt {
+        match self.memory.get(index) {
+            BIG if self.unbounded => self.big[&self.address(index)].clone(),
+            _ => BigInt::from(self.load(index)),
+        }
+    }
+
+    /// Store the value of an unbounded cell.
+    fn store_big(&mut self, index: usize, val: BigInt) {
+        let addr = self.address(index);
+
+        match i64::try_from(&val) {
+            Ok(raw) if raw != BIG => {
+                *self.memory.get_mut(index) = raw;
+                self.big.remove(&addr);
             }
-        );
-        let addr = self.addr_strategy.calc(addr);
-        Ok(self.memory[addr])
+            _ => {
+                *self.memory.get_mut(index) = BIG;
+                self.big.insert(addr, val);
+            }
+        }
+    }
+
+    /// Add to an unbounded cell, which only uses `BigInt` if the value doesn't
+    /// fit in `i64`.
+    #[inline(never)]
+    fn add_unbounded(&mut self, index: usize, add: i128) {
+        let raw = self.memory.get(index);
+        let res = i64::try_from(add)
+            .ok()
+            .filter(|_| raw != BIG)
+            .and_then(|add| raw.checked_add(add));
+
+        match res {
+            Some(res) if res != BIG => *self.memory.get_mut(index) = res,
+            _ => {
+                let res = self.load_big(index) + add;
+                self.store_big(index, res);
+            }
+        }
+    }
+
+    #[inline(never)]
+    fn set_unbounded(&mut self, index: usize, val: i128) {
+        match i64::try_from(val) {
+            Ok(raw) if raw != BIG && self.memory.get(index) != BIG => {
+                *self.memory.get_mut(index) = raw;
+            }
+            _ => self.store_big(index, BigInt::from(val)),
+        }
+    }
+
+    /// Get the address of the cell at `index` in `self.memory`, which doesn't
+    /// change when the memory grows.
+    fn address(&self, index: usize) -> isize {
+        self.range.left + index as isize
+    }
+
+    /// Get the index of the cell at `addr` in `self.memory`.
+    #[inline]
+    fn index(&self, addr: isize) -> Result<usize> {
+        let addr = if self.range.contains(addr) {
+            addr
+        } else {
+            self.addr_strategy
+                .wrap(addr)
+                .context(AccessOutOfBoundsSnafu {
+                    addr,
+                    range: self.range,
+                })?
+        };
+
+        Ok(self.addr_strategy.calc(addr))
+    }
+
+    /// Get the index of the cell at `addr` in `self.memory`, growing the memory
+    /// if it's needed and allowed.
+    #[inline]
+    fn index_mut(&mut self, addr: isize) -> Result<usize> {
+        if !self.range.contains(addr) && self.addr_strategy.wrap(addr).is_none() {
+            ensure!(
+                self.grow(addr),
+                AccessOutOfBoundsSnafu {
+                    addr,
+                    range: self.range
+                }
+            );
+        }
+
+        self.index(addr)
     }
 
     pub fn range(&self) -> AddrRange {
-        self.addr_strategy.range()
+        self.range
+    }
+
+    /// Get the address ranges of the stored cells from the left to the right,
+    /// outside which all the cells are zero. A sparse memory only stores the
+    /// pages written to, and a dense one stores the whole range.
+    pub fn pages(&self) -> Vec<AddrRange> {
+        self.memory
+            .pages()
+            .into_iter()
+            .map(|(left, right)| AddrRange {
+                left: self.range.left + left as isize,
+                right: self.range.left + right as isize,
+            })
+            .collect()
+    }
+
+    /// Get the addresses and the values of the stored cells from the left to
+    /// the right.
+    pub fn cells(&self) -> impl Iterator<Item = (isize, i128)> + '_ {
+        self.pages()
+            .into_iter()
+            .flat_map(|range| range.left..=range.right)
+            .map(|addr| (addr, self.get_at(addr).unwrap()))
+    }
+
+    /// Get all the stored cells from the left to the right, which is only
+    /// valid if the memory is built from a config with the dense storage.
+    #[cfg(feature = "jit")]
+    pub(crate) fn cells_mut(&mut self) -> &mut [i64] {
+        self.memory.as_mut_slice().unwrap()
+    }
+
+    #[cfg(feature = "jit")]
+    pub(crate) fn set_position(&mut self, addr: isize) {
+        debug_assert!(self.range().contains(addr));
+        self.cur = addr;
     }
 }
 
+/// Clamp an arbitrary-precision integer to `i128`.
+pub(crate) fn clamp(val: &BigInt) -> i128 {
+    i128::try_from(val).unwrap_or(match val.sign() {
+        Sign::Minus => i128::MIN,
+        _ => i128::MAX,
+    })
+}
+
 impl Default for Memory {
     fn default() -> Self {
         Builder::new().build()
@@ -140,10 +464,16 @@ pub struct Builder {
     cell: Cell,
     overflow: Overflow,
     eof: Eof,
+    storage: Storage,
+    init: Option<Init>,
 }
 
 const DEFAULT_LEN: usize = 32768;
 
+/// The maximum number of cells a dense memory may have, since they're all
+/// allocated at once. A larger memory has to be sparse.
+pub const MAX_DENSE_LEN: usize = 1 << 28;
+
 #[allow(dead_code)]
 impl Builder {
     pub fn new() -> Self {
@@ -153,6 +483,8 @@ impl Builder {
             cell: Cell::I8,
             overflow: Overflow::Error,
             eof: Eof::Ignore,
+            storage: Storage::Dense,
+            init: None,
         }
     }
 
@@ -163,6 +495,8 @@ impl Builder {
             cell,
             overflow,
             eof,
+            storage,
+            init,
         } = config;
 
         Self {
@@ -171,6 +505,8 @@ impl Builder {
             cell,
             overflow,
             eof,
+            storage,
+            init,
         }
     }
 
@@ -199,29 +535,294 @@ impl Builder {
         self
     }
 
+    pub fn storage(mut self, storage: Storage) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = Some(init);
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the preloaded data doesn't fit in the memory, which can be
+    /// checked by `Config::check` first.
     pub fn build(self) -> Memory {
-        let addr_strategy: Box<dyn AddrStrategy> = match self.addr {
-            Addr::Unsigned => Box::new(strategy::UnsignedAddrStrategy::new(self.len)),
-            Addr::Signed => Box::new(strategy::SignedAddrStrategy::new((self.len + 1) / 2)),
+        let addr_strategy = match self.addr {
+            Addr::Unsigned => {
+                AnyAddrStrategy::Unsigned(strategy::UnsignedAddrStrategy::new(self.len))
+            }
+            Addr::Signed => {
+                AnyAddrStrategy::Signed(strategy::SignedAddrStrategy::new(self.len.div_ceil(2)))
+            }
+            Addr::Circular => {
+                AnyAddrStrategy::Circular(strategy::CircularAddrStrategy::new(self.len))
+            }
+            Addr::Dynamic { max } => {
+                AnyAddrStrategy::Dynamic(strategy::DynamicAddrStrategy::new(self.len, max))
+            }
         };
-        let cell_strategy: Box<dyn CellStrategy> = match self.cell {
-            Cell::I8 => Box::new(strategy::I8CellStrategy {}),
-            Cell::I32 => Box::new(strategy::I32CellStrategy {}),
+        let bits =
+            |bits, signed| AnyCellStrategy::Bits(strategy::BitsCellStrategy::new(bits, signed));
+        let cell_strategy = match self.cell {
+            Cell::I8 => AnyCellStrategy::I8(strategy::I8CellStrategy {}),
+            Cell::U8 => bits(8, false),
+            Cell::I16 => bits(16, true),
+            Cell::U16 => bits(16, false),
+            Cell::I32 => AnyCellStrategy::I32(strategy::I32CellStrategy {}),
+            Cell::U32 => bits(32, false),
+            Cell::I64 => bits(64, true),
+            Cell::U64 => bits(64, false),
+            Cell::Bits { bits: n, signed } => bits(n, signed),
+            Cell::Unbounded => AnyCellStrategy::Unbounded(strategy::UnboundedCellStrategy {}),
         };
-        let overflow_strategy: Box<dyn OverflowStrategy> = match self.overflow {
-            Overflow::Error => Box::new(strategy::ErrorOverflowStrategy {}),
-            Overflow::Wrap => Box::new(strategy::WrapOverflowStrategy {}),
+        let overflow_strategy = match self.overflow {
+            Overflow::Error => AnyOverflowStrategy::Error(strategy::ErrorOverflowStrategy {}),
+            Overflow::Wrap => AnyOverflowStrategy::Wrap(strategy::WrapOverflowStrategy {}),
+            Overflow::Saturate => {
+                AnyOverflowStrategy::Saturate(strategy::SaturateOverflowStrategy {})
+            }
+            Overflow::ErrorOnNegative => {
+                AnyOverflowStrategy::ErrorOnNegative(strategy::ErrorOnNegativeOverflowStrategy {})
+            }
         };
-        let eof_strategy: Box<dyn EofStrategy> = match self.eof {
-            Eof::Zero => Box::new(strategy::ZeroEofStrategy {}),
-            Eof::Keep => Box::new(strategy::KeepEofStrategy {}),
-            Eof::Ignore => Box::new(strategy::IgnoreEofStrategy {}),
+        let eof_strategy = match self.eof {
+            Eof::Zero => AnyEofStrategy::Zero(strategy::ZeroEofStrategy {}),
+            Eof::Keep => AnyEofStrategy::Keep(strategy::KeepEofStrategy {}),
+            Eof::Ignore => AnyEofStrategy::Ignore(strategy::IgnoreEofStrategy {}),
+            Eof::Value(val) => {
+                AnyEofStrategy::Value(strategy::ValueEofStrategy { val: val as i128 })
+            }
+            Eof::Error => AnyEofStrategy::Error(strategy::ErrorEofStrategy {}),
         };
-        Memory::new(
+        let mut memory = Memory::with_storage(
             addr_strategy,
             cell_strategy,
             eof_strategy,
             overflow_strategy,
-        )
+            &self.storage,
+        );
+        memory.config = Some(Config {
+            len: self.len,
+            addr: self.addr,
+            cell: self.cell,
+            overflow: self.overflow,
+            eof: self.eof,
+            storage: self.storage,
+            init: None,
+        });
+
+        if let Some(init) = self.init {
+            memory
+                .preload(init.addr, &init.data)
+                .expect("the preloaded data doesn't fit in the memory");
+            memory.config.as_mut().unwrap().init = Some(init);
+        }
+
+        memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_memory() {
+        let mut memory = Builder::new()
+            .len(2_000_000_000)
+            .addr(Addr::Signed)
+            .storage(Storage::Sparse)
+            .build();
+        memory.add(1).unwrap();
+        memory.seek(999_999_999).unwrap();
+        memory.add(2).unwrap();
+        memory.seek(-1_999_999_999).unwrap();
+        memory.add(3).unwrap();
+
+        let page = PAGE_LEN as isize;
+        assert_eq!(
+            memory.pages(),
+            [
+                AddrRange {
+                    left: -1_000_000_000,
+                    right: (-1_000_000_000isize).div_euclid(page) * page + page - 1
+                },
+                AddrRange {
+                    left: 0,
+                    right: page - 1
+                },
+                AddrRange {
+                    left: 999_999_999 / page * page,
+                    right: 999_999_999
+                },
+            ]
+        );
+
+        let cells: Vec<_> = memory.cells().filter(|(_, val)| *val != 0).collect();
+        assert_eq!(cells, [(-1_000_000_000, 3), (0, 1), (999_999_999, 2)]);
+    }
+
+    #[test]
+    fn custom_strategies() {
+        let mut memory = Memory::new(
+            Box::new(strategy::CircularAddrStrategy::new(4)),
+            Box::new(strategy::BitsCellStrategy::new(4, false)),
+            Box::new(strategy::ValueEofStrategy { val: 7 }),
+            Box::new(strategy::WrapOverflowStrategy {}),
+        );
+        assert!(memory.config().is_none());
+        memory.seek(-1).unwrap();
+        assert_eq!(memory.position(), 3);
+        memory.add(17).unwrap();
+        assert_eq!(memory.get(), 1);
+        memory.set(-1).unwrap();
+        assert_eq!(memory.get(), 7);
+    }
+
+    #[test]
+    fn preload() {
+        let init = |addr, data: &[u8]| Init {
+            addr,
+            data: data.to_vec(),
+        };
+
+        let memory = Builder::new().len(4).init(init(1, b"\x01\xff")).build();
+        assert_eq!(memory.get_at(1), Ok(1));
+        assert_eq!(memory.get_at(2), Ok(-1));
+        assert!(memory.config().unwrap().init.is_some());
+
+        let mut memory = Builder::new()
+            .len(4)
+            .addr(Addr::Dynamic { max: None })
+            .build();
+        memory.preload(-2, b"bf").unwrap();
+        assert_eq!(memory.get_at(-2), Ok(98));
+        assert_eq!(memory.get_at(-1), Ok(102));
+
+        let mut memory = Builder::new().len(4).cell(Cell::Unbounded).build();
+        memory.preload(3, b"\xff").unwrap();
+        assert_eq!(memory.get_at(3), Ok(255));
+
+        // Nothing is written if the data doesn't fit.
+        let range = AddrRange { left: 0, right: 3 };
+        assert_eq!(
+            memory.preload(2, b"bf!"),
+            Err(MemoryError::AccessOutOfBounds { addr: 4, range })
+        );
+        assert_eq!(memory.get_at(2), Ok(0));
+
+        let config = |addr, init| Config {
+            len: 4,
+            addr,
+            init: Some(init),
+            ..Default::default()
+        };
+        assert_eq!(
+            config(Addr::Unsigned, init(3, b"bf")).check(),
+            Err(MemoryError::AccessOutOfBounds { addr: 4, range })
+        );
+        assert_eq!(config(Addr::Circular, init(3, b"bf")).check(), Ok(()));
+        assert_eq!(
+            config(Addr::Dynamic { max: None }, init(-1, b"bf")).check(),
+            Ok(())
+        );
+        assert_eq!(
+            config(Addr::Dynamic { max: Some(4) }, init(-1, b"bf")).check(),
+            Err(MemoryError::AccessOutOfBounds { addr: -1, range })
+        );
+
+        // A dense memory is allocated at once, including the cells it grows
+        // to for the preloaded data.
+        let len = MAX_DENSE_LEN + 1;
+        let huge = Config {
+            len,
+            ..Default::default()
+        };
+        assert_eq!(huge.check(), Err(MemoryError::TooLarge { len }));
+        let huge = Config {
+            storage: Storage::Sparse,
+            ..huge
+        };
+        assert_eq!(huge.check(), Ok(()));
+        assert_eq!(
+            config(Addr::Dynamic { max: None }, init(isize::MIN, b"bf")).check(),
+            Err(MemoryError::TooLarge {
+                len: isize::MAX as usize + 5
+            })
+        );
+
+        // There is no cell for the pointer to stay at.
+        for addr in [Addr::Unsigned, Addr::Circular, Addr::Dynamic { max: None }] {
+            let empty = Config {
+                len: 0,
+                addr,
+                ..Default::default()
+            };
+            assert_eq!(empty.check(), Err(MemoryError::Empty));
+        }
+    }
+
+    #[test]
+    fn wide_cells() {
+        let mut memory = Builder::new()
+            .cell(Cell::U64)
+            .overflow(Overflow::Wrap)
+            .build();
+        memory.add(-1).unwrap();
+        assert_eq!(memory.get(), u64::MAX as i128);
+        memory.add(2).unwrap();
+        assert_eq!(memory.get(), 1);
+
+        let mut memory = Builder::new().cell(Cell::I64).build();
+        memory.set(i64::MIN as i128).unwrap();
+        assert_eq!(memory.get(), i64::MIN as i128);
+        assert_eq!(
+            memory.add(-1),
+            Err(MemoryError::AddOverflow {
+                before: i64::MIN as i128,
+                add: -1
+            })
+        );
+
+        let mut memory = Builder::new().cell(Cell::U8).build();
+        assert_eq!(
+            memory.add(-1),
+            Err(MemoryError::AddOverflow { before: 0, add: -1 })
+        );
+        memory.set(255).unwrap();
+        assert_eq!(memory.get(), 255);
+    }
+
+    #[test]
+    fn unbounded_cells() {
+        let mut memory = Builder::new()
+            .len(4)
+            .addr(Addr::Dynamic { max: None })
+            .cell(Cell::Unbounded)
+            .build();
+        memory.add(i64::MAX as i128).unwrap();
+        memory.add(1).unwrap();
+        assert_eq!(memory.get(), i64::MAX as i128 + 1);
+
+        // `i64::MIN` is stored like the values which don't fit in `i64`.
+        memory.set(i64::MIN as i128).unwrap();
+        assert_eq!(memory.get(), i64::MIN as i128);
+        memory.add(1).unwrap();
+        assert_eq!(memory.get(), i64::MIN as i128 + 1);
+
+        let big = BigInt::from(i128::MAX) * 1000;
+        memory.add_big(&big).unwrap();
+        let big = big + i64::MIN + 1;
+        assert_eq!(memory.get(), i128::MAX);
+        assert_eq!(memory.get_big(), big);
+
+        // The values are kept when the memory grows to the left.
+        memory.seek(-10).unwrap();
+        memory.add(-1).unwrap();
+        assert_eq!(memory.get_big_at(0), Ok(big));
+        assert_eq!(memory.get_at(-10), Ok(-1));
     }
 }
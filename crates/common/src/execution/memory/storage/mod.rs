@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+/// The number of cells in a page of a sparse memory.
+pub const PAGE_LEN: usize = 4096;
+
+/// The cells of a memory, indexed from 0 like a vector.
+pub enum Cells {
+    /// All the cells allocated at once.
+    Dense(Vec<i64>),
+    /// Only the pages which are written to, so a huge memory costs nothing
+    /// until it's used.
+    Sparse(Pages),
+}
+
+impl Cells {
+    pub fn dense(len: usize) -> Self {
+        Self::Dense(vec![0; len])
+    }
+
+    /// Create the cells whose pages start from the multiples of `PAGE_LEN`
+    /// minus `base`.
+    pub fn sparse(len: usize, base: isize) -> Self {
+        Self::Sparse(Pages {
+            pages: HashMap::new(),
+            base,
+            len,
+        })
+    }
+
+    #[inline]
+    pub fn get(&self, index: usize) -> i64 {
+        match self {
+            Self::Dense(cells) => cells[index],
+            Self::Sparse(pages) => pages.get(index),
+        }
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> &mut i64 {
+        match self {
+            Self::Dense(cells) => &mut cells[index],
+            Self::Sparse(pages) => pages.get_mut(index),
+        }
+    }
+
+    /// Insert `left` cells before the first one and `right` cells after the
+    /// last one.
+    pub fn grow(&mut self, left: usize, right: usize) {
+        match self {
+            Self::Dense(cells) => {
+                cells.splice(0..0, std::iter::repeat_n(0, left));
+                cells.resize(cells.len() + right, 0);
+            }
+            Self::Sparse(pages) => {
+                pages.base += left as isize;
+                pages.len += left + right;
+            }
+        }
+    }
+
+    /// Get the index ranges of the stored cells from the left to the right,
+    /// outside which all the cells are zero.
+    pub fn pages(&self) -> Vec<(usize, usize)> {
+        match self {
+            Self::Dense(cells) if cells.is_empty() => vec![],
+            Self::Dense(cells) => vec![(0, cells.len() - 1)],
+            Self::Sparse(pages) => pages.ranges(),
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    pub fn as_mut_slice(&mut self) -> Option<&mut [i64]> {
+        match self {
+            Self::Dense(cells) => Some(cells),
+            Self::Sparse(_) => None,
+        }
+    }
+}
+
+pub struct Pages {
+    pages: HashMap<isize, Box<[i64]>>,
+    /// The offset subtracted from the indices before they're split into pages,
+    /// which increases when cells are inserted to the left so the existing
+    /// cells stay in their pages.
+    base: isize,
+    len: usize,
+}
+
+impl Pages {
+    /// Get the page of a cell and its offset in the page.
+    fn locate(&self, index: usize) -> (isize, usize) {
+        let pos = index as isize - self.base;
+        (
+            pos.div_euclid(PAGE_LEN as isize),
+            pos.rem_euclid(PAGE_LEN as isize) as usize,
+        )
+    }
+
+    fn get(&self, index: usize) -> i64 {
+        let (page, offset) = self.locate(index);
+        self.pages.get(&page).map_or(0, |page| page[offset])
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut i64 {
+        let (page, offset) = self.locate(index);
+        let page = self
+            .pages
+            .entry(page)
+            .or_insert_with(|| vec![0; PAGE_LEN].into_boxed_slice());
+        &mut page[offset]
+    }
+
+    fn ranges(&self) -> Vec<(usize, usize)> {
+        let mut pages: Vec<_> = self.pages.keys().copied().collect();
+        pages.sort_unstable();
+
+        let mut res: Vec<(usize, usize)> = vec![];
+
+        for page in pages {
+            let left = (page * PAGE_LEN as isize + self.base).max(0) as usize;
+            let right = ((page + 1) * PAGE_LEN as isize + self.base - 1) as usize;
+            let right = right.min(self.len - 1);
+
+            match res.last_mut() {
+                Some((_, last)) if *last + 1 == left => *last = right,
+                _ => res.push((left, right)),
+            }
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_cells() {
+        let mut cells = Cells::sparse(1_000_000_000, 0);
+        *cells.get_mut(5) = 1;
+        *cells.get_mut(999_999_999) = 2;
+        *cells.get_mut(PAGE_LEN) = 3;
+        assert_eq!(cells.get(5), 1);
+        assert_eq!(cells.get(6), 0);
+        assert_eq!(cells.get(999_999_999), 2);
+        assert_eq!(
+            cells.pages(),
+            [
+                (0, 2 * PAGE_LEN - 1),
+                (999_999_999 / PAGE_LEN * PAGE_LEN, 999_999_999)
+            ]
+        );
+
+        cells.grow(10, 0);
+        assert_eq!(cells.get(15), 1);
+        assert_eq!(cells.pages()[0], (10, 2 * PAGE_LEN + 9));
+        assert_eq!(cells.pages()[1].1, 1_000_000_009);
+
+        *cells.get_mut(0) = 4;
+        assert_eq!(cells.get(0), 4);
+        assert_eq!(cells.pages()[0], (0, 2 * PAGE_LEN + 9));
+    }
+}
@@ -36,6 +36,19 @@ pub trait AddrStrategy {
 
     /// Get the abstract address range.
     fn range(&self) -> AddrRange;
+
+    /// Extend the range to contain `addr`, which is out of it. Return how many
+    /// cells are added to the left and the right, or `None` if the memory can't
+    /// grow that far.
+    fn grow(&mut self, _addr: isize) -> Option<(usize, usize)> {
+        None
+    }
+
+    /// Map `addr`, which is out of the range, to the address within the range
+    /// it refers to, or return `None` if there isn't one.
+    fn wrap(&self, _addr: isize) -> Option<isize> {
+        None
+    }
 }
 
 pub struct UnsignedAddrStrategy {
@@ -101,7 +114,7 @@ impl AddrStrategy for SignedAddrStrategy {
     }
 
     fn calc(&self, addr: isize) -> usize {
-        addr as usize + self.half_len
+        (addr + self.half_len as isize) as usize
     }
 
     fn range(&self) -> AddrRange {
@@ -112,58 +125,248 @@ impl AddrStrategy for SignedAddrStrategy {
     }
 }
 
+/// The range is `[0, len - 1]`, and the pointer moves from the last cell to
+/// the first one and vice versa.
+pub struct CircularAddrStrategy {
+    len: usize,
+}
+
+impl CircularAddrStrategy {
+    pub fn new(len: usize) -> Self {
+        Self { len }
+    }
+}
+
+impl AddrStrategy for CircularAddrStrategy {
+    fn seek(&self, addr: isize, offset: isize) -> Result<isize> {
+        let len = self.len as isize;
+
+        match addr.checked_add(offset) {
+            Some(target) if 0 <= target && target < len => Ok(target),
+            _ => Ok((addr.rem_euclid(len) + offset.rem_euclid(len)) % len),
+        }
+    }
+
+    fn calc(&self, addr: isize) -> usize {
+        addr as usize
+    }
+
+    fn range(&self) -> AddrRange {
+        AddrRange {
+            left: 0,
+            right: self.len as isize - 1,
+        }
+    }
+
+    fn wrap(&self, addr: isize) -> Option<isize> {
+        Some(addr.rem_euclid(self.len as isize))
+    }
+}
+
+/// The range starts from `[0, len - 1]` and grows on demand in both
+/// directions, and at least doubles each time so growing is cheap. The total
+/// length never exceeds `max` if it's given.
+pub struct DynamicAddrStrategy {
+    range: AddrRange,
+    max: Option<usize>,
+}
+
+impl DynamicAddrStrategy {
+    pub fn new(len: usize, max: Option<usize>) -> Self {
+        let len = len.clamp(1, max.unwrap_or(usize::MAX).max(1));
+        Self {
+            range: AddrRange {
+                left: 0,
+                right: len as isize - 1,
+            },
+            max,
+        }
+    }
+}
+
+impl AddrStrategy for DynamicAddrStrategy {
+    fn seek(&self, addr: isize, offset: isize) -> Result<isize> {
+        // The memory grows if the target is out of the range.
+        Ok(addr + offset)
+    }
+
+    fn calc(&self, addr: isize) -> usize {
+        (addr - self.range.left) as usize
+    }
+
+    fn range(&self) -> AddrRange {
+        self.range
+    }
+
+    fn grow(&mut self, addr: isize) -> Option<(usize, usize)> {
+        let AddrRange { left, right } = self.range;
+        let len = self.range.len() as isize;
+        let mut range = if addr < left {
+            AddrRange {
+                left: addr.min(left - len),
+                right,
+            }
+        } else {
+            AddrRange {
+                left,
+                right: addr.max(right + len),
+            }
+        };
+
+        if let Some(max) = self.max.filter(|max| range.len() > *max) {
+            if addr < left {
+                range.left = right - max as isize + 1;
+            } else {
+                range.right = left + max as isize - 1;
+            }
+
+            if !range.contains(addr) {
+                return None;
+            }
+        }
+
+        self.range = range;
+        Some(((left - range.left) as usize, (range.right - right) as usize))
+    }
+}
+
 pub trait CellStrategy {
-    fn is_overflowed(&self, num: i64) -> bool;
+    /// Get the smallest value of a cell.
+    fn min(&self) -> i128;
+
+    /// Get the largest value of a cell.
+    fn max(&self) -> i128;
 
-    fn wrap(&self, num: i64) -> i32;
+    fn is_overflowed(&self, num: i128) -> bool {
+        num < self.min() || num > self.max()
+    }
+
+    fn wrap(&self, num: i128) -> i128;
+
+    /// Whether the cells are arbitrary-precision integers, whose values are
+    /// calculated by the memory itself and never overflow.
+    fn is_unbounded(&self) -> bool {
+        false
+    }
 }
 
 pub struct I8CellStrategy {}
 
 impl CellStrategy for I8CellStrategy {
-    fn is_overflowed(&self, num: i64) -> bool {
-        num < i8::MIN as i64 || num > i8::MAX as i64
+    fn min(&self) -> i128 {
+        i8::MIN as i128
     }
 
-    fn wrap(&self, num: i64) -> i32 {
-        num as i8 as i32
+    fn max(&self) -> i128 {
+        i8::MAX as i128
+    }
+
+    fn wrap(&self, num: i128) -> i128 {
+        num as i8 as i128
     }
 }
 
 pub struct I32CellStrategy {}
 
 impl CellStrategy for I32CellStrategy {
-    fn is_overflowed(&self, num: i64) -> bool {
-        num < i32::MIN as i64 || num > i32::MAX as i64
+    fn min(&self) -> i128 {
+        i32::MIN as i128
+    }
+
+    fn max(&self) -> i128 {
+        i32::MAX as i128
+    }
+
+    fn wrap(&self, num: i128) -> i128 {
+        num as i32 as i128
+    }
+}
+
+/// A signed or unsigned integer of 1 to 64 bits.
+pub struct BitsCellStrategy {
+    bits: u32,
+    signed: bool,
+}
+
+impl BitsCellStrategy {
+    /// # Panics
+    ///
+    /// Panics if `bits` isn't in `[1, 64]`.
+    pub fn new(bits: u32, signed: bool) -> Self {
+        assert!((1..=64).contains(&bits), "a cell can't have {bits} bits");
+        Self { bits, signed }
+    }
+}
+
+impl CellStrategy for BitsCellStrategy {
+    fn min(&self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(&self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
     }
 
-    fn wrap(&self, num: i64) -> i32 {
-        num as i32
+    fn wrap(&self, num: i128) -> i128 {
+        (num - self.min()).rem_euclid(1 << self.bits) + self.min()
+    }
+}
+
+/// An arbitrary-precision integer.
+pub struct UnboundedCellStrategy {}
+
+impl CellStrategy for UnboundedCellStrategy {
+    fn min(&self) -> i128 {
+        i128::MIN
+    }
+
+    fn max(&self) -> i128 {
+        i128::MAX
+    }
+
+    fn is_overflowed(&self, _num: i128) -> bool {
+        false
+    }
+
+    fn wrap(&self, num: i128) -> i128 {
+        num
+    }
+
+    fn is_unbounded(&self) -> bool {
+        true
     }
 }
 
 pub trait OverflowStrategy {
     /// Calculate and check the value for the `add` operation.
-    fn add(&self, cell_strategy: &dyn CellStrategy, before: i32, add: i32) -> Result<i32>;
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128>;
 
-    fn set(&self, cell_strategy: &dyn CellStrategy, val: i32) -> Result<i32>;
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128>;
 }
 
 pub struct ErrorOverflowStrategy {}
 
 impl OverflowStrategy for ErrorOverflowStrategy {
-    fn add(&self, cell_strategy: &dyn CellStrategy, before: i32, add: i32) -> Result<i32> {
-        let res = before as i64 + add as i64;
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128> {
+        let res = before + add;
 
         if cell_strategy.is_overflowed(res) {
             Err(MemoryError::AddOverflow { before, add })
         } else {
-            Ok(res as i32)
+            Ok(res)
         }
     }
 
-    fn set(&self, cell_strategy: &dyn CellStrategy, val: i32) -> Result<i32> {
-        if cell_strategy.is_overflowed(val as i64) {
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128> {
+        if cell_strategy.is_overflowed(val) {
             Err(MemoryError::SetOverflow { val })
         } else {
             Ok(val)
@@ -174,27 +377,63 @@ impl OverflowStrategy for ErrorOverflowStrategy {
 pub struct WrapOverflowStrategy {}
 
 impl OverflowStrategy for WrapOverflowStrategy {
-    fn add(&self, cell_strategy: &dyn CellStrategy, before: i32, add: i32) -> Result<i32> {
-        let res = before as i64 + add as i64;
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128> {
+        let res = before + add;
 
         if cell_strategy.is_overflowed(res) {
             Ok(cell_strategy.wrap(res))
         } else {
-            Ok(res as i32)
+            Ok(res)
         }
     }
 
-    fn set(&self, cell_strategy: &dyn CellStrategy, val: i32) -> Result<i32> {
-        if cell_strategy.is_overflowed(val as i64) {
-            Ok(cell_strategy.wrap(val as i64))
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128> {
+        if cell_strategy.is_overflowed(val) {
+            Ok(cell_strategy.wrap(val))
         } else {
             Ok(val)
         }
     }
 }
 
+pub struct SaturateOverflowStrategy {}
+
+impl OverflowStrategy for SaturateOverflowStrategy {
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128> {
+        self.set(cell_strategy, before + add)
+    }
+
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128> {
+        Ok(val.clamp(cell_strategy.min(), cell_strategy.max()))
+    }
+}
+
+/// Only allow the values in `[0, max]`, which wrap to 0 after the maximum.
+pub struct ErrorOnNegativeOverflowStrategy {}
+
+impl OverflowStrategy for ErrorOnNegativeOverflowStrategy {
+    fn add(&self, cell_strategy: &dyn CellStrategy, before: i128, add: i128) -> Result<i128> {
+        let res = before + add;
+
+        if res < 0 {
+            Err(MemoryError::AddOverflow { before, add })
+        } else {
+            Ok(res % (cell_strategy.max() + 1))
+        }
+    }
+
+    fn set(&self, cell_strategy: &dyn CellStrategy, val: i128) -> Result<i128> {
+        if val < 0 {
+            Err(MemoryError::SetOverflow { val })
+        } else {
+            Ok(val % (cell_strategy.max() + 1))
+        }
+    }
+}
+
 pub trait EofStrategy {
-    fn check(&self, input: i32) -> Option<i32>;
+    /// Get the value to store for the input, or `None` if it's ignored.
+    fn check(&self, input: i128) -> Result<Option<i128>>;
 }
 
 #[derive(Debug)]
@@ -202,11 +441,11 @@ pub struct ZeroEofStrategy {}
 
 /// Turn EOF to 0.
 impl EofStrategy for ZeroEofStrategy {
-    fn check(&self, input: i32) -> Option<i32> {
-        if input == EOF {
-            Some(0)
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        if input == EOF as i128 {
+            Ok(Some(0))
         } else {
-            Some(input)
+            Ok(Some(input))
         }
     }
 }
@@ -215,8 +454,8 @@ impl EofStrategy for ZeroEofStrategy {
 pub struct KeepEofStrategy {}
 
 impl EofStrategy for KeepEofStrategy {
-    fn check(&self, input: i32) -> Option<i32> {
-        Some(input)
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        Ok(Some(input))
     }
 }
 
@@ -224,11 +463,39 @@ impl EofStrategy for KeepEofStrategy {
 pub struct IgnoreEofStrategy {}
 
 impl EofStrategy for IgnoreEofStrategy {
-    fn check(&self, input: i32) -> Option<i32> {
-        if input == EOF {
-            None
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        if input == EOF as i128 {
+            Ok(None)
         } else {
-            Some(input)
+            Ok(Some(input))
+        }
+    }
+}
+
+/// Turn EOF to a value.
+pub struct ValueEofStrategy {
+    pub val: i128,
+}
+
+impl EofStrategy for ValueEofStrategy {
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        if input == EOF as i128 {
+            Ok(Some(self.val))
+        } else {
+            Ok(Some(input))
+        }
+    }
+}
+
+/// Report an error when reading EOF.
+pub struct ErrorEofStrategy {}
+
+impl EofStrategy for ErrorEofStrategy {
+    fn check(&self, input: i128) -> Result<Option<i128>> {
+        if input == EOF as i128 {
+            Err(MemoryError::UnexpectedEof)
+        } else {
+            Ok(Some(input))
         }
     }
 }
@@ -267,6 +534,43 @@ mod tests {
         assert_eq!(r.calc(4), 9);
     }
 
+    #[test]
+    fn circular_addr_strategy() {
+        let r = CircularAddrStrategy::new(5);
+        assert_eq!(r.seek(0, 4), Ok(4));
+        assert_eq!(r.seek(4, 1), Ok(0));
+        assert_eq!(r.seek(0, -1), Ok(4));
+        assert_eq!(r.seek(3, -13), Ok(0));
+        assert_eq!(r.seek(4, isize::MAX), Ok(1));
+        assert_eq!(r.wrap(-6), Some(4));
+        assert_eq!(r.wrap(12), Some(2));
+    }
+
+    #[test]
+    fn dynamic_addr_strategy() {
+        let mut r = DynamicAddrStrategy::new(4, None);
+        assert_eq!(r.seek(0, 10), Ok(10));
+        assert_eq!(r.grow(5), Some((0, 4)));
+        assert_eq!(r.range(), AddrRange { left: 0, right: 7 });
+        assert_eq!(r.grow(20), Some((0, 13)));
+        assert_eq!(r.grow(-1), Some((21, 0)));
+        assert_eq!(
+            r.range(),
+            AddrRange {
+                left: -21,
+                right: 20
+            }
+        );
+        assert_eq!(r.calc(0), 21);
+
+        let mut r = DynamicAddrStrategy::new(4, Some(10));
+        assert_eq!(r.grow(4), Some((0, 4)));
+        assert_eq!(r.grow(9), Some((0, 2)));
+        assert_eq!(r.range(), AddrRange { left: 0, right: 9 });
+        assert_eq!(r.grow(10), None);
+        assert_eq!(r.grow(-1), None);
+    }
+
     #[test]
     fn i8_cell_strategy() {
         let c = I8CellStrategy {};
@@ -287,12 +591,33 @@ mod tests {
     fn i32_cell_strategy() {
         let c = I32CellStrategy {};
         // i32::MAX = 2147483647, i32::MIN = -2147483648
-        assert!(c.is_overflowed(2147483648i64));
-        assert!(!c.is_overflowed(-2147483648i64));
-        assert!(c.is_overflowed(-2147483649i64));
+        assert!(c.is_overflowed(2147483648i128));
+        assert!(!c.is_overflowed(-2147483648i128));
+        assert!(c.is_overflowed(-2147483649i128));
+
+        assert_eq!(c.wrap(-2147483649i128), 2147483647);
+        assert_eq!(c.wrap(-2147483648i128 - 2147483647i128 - 1i128), 0);
+    }
 
-        assert_eq!(c.wrap(-2147483649i64), 2147483647);
-        assert_eq!(c.wrap(-2147483648i64 - 2147483647i64 - 1i64), 0);
+    #[test]
+    fn bits_cell_strategy() {
+        let c = BitsCellStrategy::new(8, false);
+        assert!(!c.is_overflowed(255));
+        assert!(c.is_overflowed(-1));
+        assert_eq!(c.wrap(-1), 255);
+        assert_eq!(c.wrap(256), 0);
+
+        let c = BitsCellStrategy::new(64, false);
+        assert_eq!(c.max(), u64::MAX as i128);
+        assert_eq!(c.wrap(-1), u64::MAX as i128);
+
+        let c = BitsCellStrategy::new(64, true);
+        assert_eq!(c.wrap(i64::MAX as i128 + 1), i64::MIN as i128);
+
+        let c = BitsCellStrategy::new(3, true);
+        assert_eq!((c.min(), c.max()), (-4, 3));
+        assert_eq!(c.wrap(4), -4);
+        assert_eq!(c.wrap(-13), 3);
     }
 
     #[test]
@@ -316,4 +641,45 @@ mod tests {
         assert_eq!(o.add(&c, 0, 1), Ok(1));
         assert_eq!(o.add(&c, 127, 1), Ok(-128));
     }
+
+    #[test]
+    fn saturate_overflow_strategy() {
+        let o = SaturateOverflowStrategy {};
+        let c = I8CellStrategy {};
+        assert_eq!(o.add(&c, 100, 100), Ok(127));
+        assert_eq!(o.add(&c, -100, -100), Ok(-128));
+        assert_eq!(o.set(&c, 1000), Ok(127));
+
+        let c = BitsCellStrategy::new(8, false);
+        assert_eq!(o.add(&c, 0, -1), Ok(0));
+    }
+
+    #[test]
+    fn error_on_negative_overflow_strategy() {
+        let o = ErrorOnNegativeOverflowStrategy {};
+        let c = I8CellStrategy {};
+        assert_eq!(o.add(&c, 127, 1), Ok(0));
+        assert_eq!(
+            o.add(&c, 0, -1),
+            Err(MemoryError::AddOverflow { before: 0, add: -1 })
+        );
+        assert_eq!(o.set(&c, -1), Err(MemoryError::SetOverflow { val: -1 }));
+
+        let c = BitsCellStrategy::new(8, false);
+        assert_eq!(o.add(&c, 200, 100), Ok(44));
+    }
+
+    #[test]
+    fn value_eof_strategy() {
+        let e = ValueEofStrategy { val: 255 };
+        assert_eq!(e.check(97), Ok(Some(97)));
+        assert_eq!(e.check(-1), Ok(Some(255)));
+    }
+
+    #[test]
+    fn error_eof_strategy() {
+        let e = ErrorEofStrategy {};
+        assert_eq!(e.check(97), Ok(Some(97)));
+        assert_eq!(e.check(-1), Err(MemoryError::UnexpectedEof));
+    }
 }
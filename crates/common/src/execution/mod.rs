@@ -1,4 +1,6 @@
 pub mod context;
+#[cfg(feature = "jit")]
+pub mod jit;
 pub mod memory;
 pub mod processor;
 pub mod stream;
@@ -1,6 +1,7 @@
+use num_bigint::Sign;
 use snafu::prelude::*;
 
-use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList};
+use crate::compiler::{AddUntilZeroArg, Instruction, InstructionList, VerifyError};
 use crate::execution::context::Context;
 use crate::execution::memory::{Memory, MemoryError};
 
@@ -28,7 +29,7 @@ impl Counter {
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ProcessorState {
     Ready,
     Running,
@@ -43,11 +44,23 @@ pub struct Processor {
 }
 
 impl Processor {
-    pub fn new(instructions: InstructionList) -> Self {
-        Self {
+    /// Create a processor after checking that the instructions are valid.
+    pub fn new(instructions: InstructionList) -> Result<Self> {
+        instructions.verify()?;
+        Ok(Self {
             counter: Counter::new(),
             instructions,
             state: ProcessorState::Ready,
+        })
+    }
+
+    /// Create a processor which starts from the instruction at `counter`.
+    #[cfg(feature = "jit")]
+    pub(crate) fn resume(instructions: InstructionList, counter: usize) -> Self {
+        Self {
+            counter: Counter { val: counter },
+            instructions,
+            state: ProcessorState::Running,
         }
     }
 
@@ -66,7 +79,12 @@ impl Processor {
         }
     }
 
-    fn step(&mut self, context: &mut Context) -> Result<()> {
+    pub fn state(&self) -> ProcessorState {
+        self.state
+    }
+
+    /// Execute a single instruction.
+    pub fn step(&mut self, context: &mut Context) -> Result<()> {
         let Context {
             memory,
             in_stream,
@@ -81,7 +99,7 @@ impl Processor {
 
         match &self.instructions.0[self.counter.get()] {
             Instruction::Add { val } => {
-                if let Err(e) = memory.add(*val) {
+                if let Err(e) = memory.add(*val as i128) {
                     self.abort();
                     Err(e.into())
                 } else {
@@ -98,27 +116,49 @@ impl Processor {
                     Ok(())
                 }
             }
-            Instruction::Clear => {
-                memory.set(0).unwrap();
-                self.tick();
-                Ok(())
-            }
-            Instruction::AddUntilZero { target } => {
-                if let Err(e) = self.add_while_zero(target, memory) {
+            // The same as a loop without targets, which only clears a negative
+            // cell if it wraps around.
+            Instruction::Clear => match self.add_while_zero(&[], memory) {
+                Ok(true) => {
+                    self.tick();
+                    Ok(())
+                }
+                Ok(false) => Ok(()),
+                Err(e) => Err(e),
+            },
+            Instruction::AddUntilZero { target } => match self.add_while_zero(target, memory) {
+                Ok(true) => {
+                    self.tick();
+                    Ok(())
+                }
+                // Run the next iteration in the next step.
+                Ok(false) => Ok(()),
+                Err(e) => {
                     self.abort();
                     Err(e)
+                }
+            },
+            Instruction::Input => {
+                if let Err(e) = memory.set(in_stream.read() as i128) {
+                    let addr = self.counter.get();
+                    self.abort();
+
+                    match e {
+                        MemoryError::UnexpectedEof => Err(ProcessorError::UnexpectedEof { addr }),
+                        e => Err(e.into()),
+                    }
                 } else {
                     self.tick();
                     Ok(())
                 }
             }
-            Instruction::Input => {
-                memory.set(in_stream.read()).unwrap();
-                self.tick();
-                Ok(())
-            }
             Instruction::Output => {
-                out_stream.write(memory.get());
+                if memory.is_unbounded() {
+                    out_stream.write_big(&memory.get_big());
+                } else {
+                    out_stream.write(memory.get());
+                }
+
                 self.tick();
                 Ok(())
             }
@@ -137,16 +177,79 @@ impl Processor {
 
                 Ok(())
             }
+            // The counter only stops at `Halt` before the first step, which means
+            // there is no other instruction, e.g. all the code is optimized away.
             Instruction::Halt => {
-                unreachable!()
+                self.state = ProcessorState::Halted;
+                Ok(())
             }
         }
     }
 
-    fn add_while_zero(&self, target: &Vec<AddUntilZeroArg>, memory: &mut Memory) -> Result<()> {
+    /// Run an `AddUntilZero` and return whether the loop it's optimized from
+    /// has ended.
+    ///
+    /// The products are added at once unless the memory can't merge the adds
+    /// of the iterations, e.g. when a cell saturates in the middle of one or
+    /// the counter is negative and never reaches 0 without wrapping around.
+    /// The loop is then run one iteration per step, so that a counter which
+    /// never reaches 0 keeps the program running instead of ending the loop
+    /// or hanging the step, and one which overflows fails the same way.
+    fn add_while_zero(&self, target: &[AddUntilZeroArg], memory: &mut Memory) -> Result<bool> {
+        if memory.is_unbounded() {
+            // Counting down from a negative value never reaches 0.
+            if memory.get_big().sign() == Sign::Minus {
+                return self.add_once(target, memory);
+            }
+
+            self.add_while_zero_big(target, memory)?;
+            return Ok(true);
+        }
+
         let val = memory.get();
 
         if val == 0 {
+            return Ok(true);
+        }
+
+        if !memory.merges_adds(val > 0 && !AddUntilZeroArg::repeats_offset(target)) {
+            // The targets may overflow before the counter does, which fails
+            // all the same.
+            memory.check_count_down()?;
+            return self.add_once(target, memory);
+        }
+
+        memory.set(0).unwrap();
+
+        for AddUntilZeroArg { offset, times } in target {
+            memory.seek(*offset)?;
+            memory.add(val * *times as i128)?;
+            memory.seek(-*offset)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Run a single iteration of an `AddUntilZero` and return whether the
+    /// counter has reached 0.
+    fn add_once(&self, target: &[AddUntilZeroArg], memory: &mut Memory) -> Result<bool> {
+        memory.add(-1)?;
+
+        for AddUntilZeroArg { offset, times } in target {
+            memory.seek(*offset)?;
+            memory.add(*times as i128)?;
+            memory.seek(-*offset)?;
+        }
+
+        Ok(memory.get() == 0)
+    }
+
+    /// `add_while_zero` for unbounded cells, whose products may not fit in
+    /// `i128`.
+    fn add_while_zero_big(&self, target: &[AddUntilZeroArg], memory: &mut Memory) -> Result<()> {
+        let val = memory.get_big();
+
+        if val.sign() == Sign::NoSign {
             return Ok(());
         }
 
@@ -154,7 +257,7 @@ impl Processor {
 
         for AddUntilZeroArg { offset, times } in target {
             memory.seek(*offset)?;
-            memory.add(val * *times)?;
+            memory.add_big(&(&val * *times))?;
             memory.seek(-*offset)?;
         }
 
@@ -163,10 +266,6 @@ impl Processor {
 
     pub fn run(&mut self, context: &mut Context) -> Result<()> {
         match self.state {
-            // There is only one halt instruction
-            ProcessorState::Ready if self.instructions.0.len() == 1 => {
-                return Err(ProcessorError::Empty)
-            }
             ProcessorState::Halted => return Err(ProcessorError::AlreadyHalted),
             ProcessorState::Failed => return Err(ProcessorError::Failed),
             _ => {}
@@ -188,8 +287,10 @@ pub enum ProcessorError {
     AlreadyHalted,
     #[snafu(display("couldn't continue to run due to the previous error"))]
     Failed,
-    #[snafu(display("empty program loaded"))]
-    Empty,
+    #[snafu(display("the instructions are invalid"))]
+    Invalid { source: VerifyError },
+    #[snafu(display("the input at instruction {addr} reads EOF"))]
+    UnexpectedEof { addr: usize },
 }
 
 impl From<MemoryError> for ProcessorError {
@@ -197,3 +298,388 @@ impl From<MemoryError> for ProcessorError {
         Self::Memory { source: e }
     }
 }
+
+impl From<VerifyError> for ProcessorError {
+    fn from(e: VerifyError) -> Self {
+        Self::Invalid { source: e }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::compiler::Assembler;
+    use crate::execution::memory::config::{Addr, Cell, Config as MemoryConfig, Eof, Overflow};
+    use crate::execution::memory::AddrRange;
+    use crate::execution::stream::config::{Config as StreamConfig, Input, Output};
+
+    fn run(text: &str) -> (Result<()>, Vec<i128>) {
+        let memory_config = MemoryConfig {
+            len: 8,
+            cell: Cell::I32,
+            ..Default::default()
+        };
+        run_with_config(text, memory_config)
+    }
+
+    fn run_with_config(text: &str, memory_config: MemoryConfig) -> (Result<()>, Vec<i128>) {
+        let instructions = Assembler::new().assemble(text).unwrap();
+        let output = Rc::new(RefCell::new(VecDeque::new()));
+        let stream_config = StreamConfig {
+            input: Input::Null,
+            output: Output::Vec(output.clone()),
+        };
+        let mut context = Context::new(memory_config, stream_config);
+        let res = Processor::new(instructions).unwrap().run(&mut context);
+        let output = output.borrow().iter().copied().collect();
+        (res, output)
+    }
+
+    #[test]
+    fn add_until_zero() {
+        let (res, output) = run("
+            seek 2
+            add 1
+            seek -1
+            add 5
+            mul [-1:3, 1:2, 3:0]
+            output
+            seek -1
+            output
+            seek 2
+            output
+            mul [1:1]
+            seek 1
+            output
+            halt
+        ");
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![0, 15, 11, 11]);
+
+        let (res, _) = run("
+            add 1
+            mul [-1:1]
+            halt
+        ");
+        assert!(matches!(res, Err(ProcessorError::Memory { .. })));
+    }
+
+    #[test]
+    fn add_until_zero_overflow() {
+        let config = |cell, overflow| MemoryConfig {
+            len: 4,
+            cell,
+            overflow,
+            ..Default::default()
+        };
+        // The products overflow `i32` before they're added.
+        let text = "
+            add 100
+            seek 1
+            add 50
+            seek -1
+            mul [1:2147483647, 2:-2147483648]
+            seek 1
+            output
+            seek 1
+            output
+            halt
+        ";
+        let (res, output) = run_with_config(text, config(Cell::I8, Overflow::Saturate));
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![127, -128]);
+
+        let (res, output) = run_with_config(text, config(Cell::I32, Overflow::Saturate));
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![i32::MAX as i128, i32::MIN as i128]);
+
+        let text = "
+            add 200
+            seek 1
+            add 100
+            seek -1
+            mul [1:2]
+            seek 1
+            output
+            halt
+        ";
+        let (res, output) = run_with_config(text, config(Cell::U8, Overflow::ErrorOnNegative));
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![244]);
+
+        let (res, _) = run_with_config(
+            &text.replace("[1:2]", "[1:-1]"),
+            config(Cell::U8, Overflow::ErrorOnNegative),
+        );
+        assert_eq!(
+            res,
+            Err(ProcessorError::Memory {
+                source: MemoryError::AddOverflow {
+                    before: 100,
+                    add: -200
+                }
+            })
+        );
+
+        // The repeated offsets are added to in every iteration like the loop.
+        let text = "
+            add 10
+            seek 1
+            add OTHER
+            seek -1
+            mul [1:1, 2:1, 1:-1]
+            seek 1
+            output
+            halt
+        ";
+        let (res, output) = run_with_config(
+            &text.replace("OTHER", "250"),
+            config(Cell::U8, Overflow::Saturate),
+        );
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![250]);
+
+        let (res, output) = run_with_config(
+            &text
+                .replace("OTHER", "5")
+                .replace("[1:1, 2:1, 1:-1]", "[1:-1, 2:1, 1:1]"),
+            config(Cell::U8, Overflow::ErrorOnNegative),
+        );
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![5]);
+    }
+
+    #[test]
+    fn add_until_zero_negative_counter() {
+        let config = |overflow| MemoryConfig {
+            len: 4,
+            cell: Cell::I8,
+            overflow,
+            ..Default::default()
+        };
+        let texts = [
+            "add -1\n mul [1:1, 1:-1]\n halt",
+            "add -1\n mul [1:1]\n halt",
+            "add -1\n clear\n halt",
+        ];
+
+        for text in texts {
+            // The counter is stuck at the minimum, so the loop never ends.
+            let instructions = Assembler::new().assemble(text).unwrap();
+            let stream_config = StreamConfig {
+                input: Input::Null,
+                output: Output::Null,
+            };
+            let mut context = Context::new(config(Overflow::Saturate), stream_config);
+            let mut processor = Processor::new(instructions).unwrap();
+
+            for _ in 0..1000 {
+                processor.step(&mut context).unwrap();
+            }
+
+            assert_ne!(processor.state(), ProcessorState::Halted, "{text}");
+            assert_eq!(context.memory.get(), i8::MIN as i128);
+
+            // The counter overflows after it reaches the minimum.
+            let (res, _) = run_with_config(text, config(Overflow::Error));
+            assert_eq!(
+                res,
+                Err(ProcessorError::Memory {
+                    source: MemoryError::AddOverflow {
+                        before: i8::MIN as i128,
+                        add: -1
+                    }
+                }),
+                "{text}"
+            );
+
+            // The counter wraps around to 0.
+            let (res, _) = run_with_config(text, config(Overflow::Wrap));
+            assert_eq!(res, Ok(()), "{text}");
+        }
+    }
+
+    #[test]
+    fn eof() {
+        let config = |eof| MemoryConfig {
+            len: 4,
+            cell: Cell::U8,
+            eof,
+            ..Default::default()
+        };
+        let text = "
+            add 1
+            input
+            output
+            halt
+        ";
+
+        let (res, output) = run_with_config(text, config(Eof::Value(255)));
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![255]);
+
+        let (res, output) = run_with_config(text, config(Eof::Error));
+        assert_eq!(res, Err(ProcessorError::UnexpectedEof { addr: 1 }));
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn dynamic_memory() {
+        let config = |max| MemoryConfig {
+            len: 2,
+            addr: Addr::Dynamic { max },
+            cell: Cell::I32,
+            ..Default::default()
+        };
+        let text = "
+            seek 100
+            add 7
+            seek -150
+            add 3
+            mul [150:2]
+            seek 150
+            output
+            halt
+        ";
+
+        let (res, output) = run_with_config(text, config(None));
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![13]);
+
+        let (res, _) = run_with_config(text, config(Some(120)));
+        assert_eq!(
+            res,
+            Err(ProcessorError::Memory {
+                source: MemoryError::SeekOutOfBounds {
+                    now_position: 100,
+                    offset: -150,
+                    range: AddrRange {
+                        left: 0,
+                        right: 100
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn circular_memory() {
+        let config = MemoryConfig {
+            len: 4,
+            addr: Addr::Circular,
+            cell: Cell::I32,
+            ..Default::default()
+        };
+        let (res, output) = run_with_config(
+            "
+            seek -1
+            add 3
+            mul [2:2, -5:1]
+            seek 1
+            output
+            seek 2
+            output
+            seek 7
+            output
+            halt
+        ",
+            config,
+        );
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![0, 3, 6]);
+    }
+
+    #[test]
+    fn unbounded_cells() {
+        let config = MemoryConfig {
+            len: 4,
+            cell: Cell::Unbounded,
+            ..Default::default()
+        };
+        // Calculate `2 ^ n`.
+        let text = |n| {
+            format!(
+                "
+                add 1
+                seek 1
+                add {n}
+                loop: jz end
+                    add -1
+                    seek -1
+                    mul [2:2]
+                    seek 2
+                    mul [-2:1]
+                    seek -1
+                    jump loop
+                end:
+                seek -1
+                output
+                halt
+            "
+            )
+        };
+
+        let (res, output) = run_with_config(&text(64), config.clone());
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![1 << 64]);
+
+        // The output is clamped if the stream can't hold the full value.
+        let (res, output) = run_with_config(&text(200), config);
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![i128::MAX]);
+    }
+
+    #[test]
+    fn unsigned_cells() {
+        let config = MemoryConfig {
+            len: 4,
+            cell: Cell::U64,
+            overflow: Overflow::Wrap,
+            ..Default::default()
+        };
+        let (res, output) = run_with_config(
+            "
+            add -1
+            output
+            mul [1:2]
+            seek 1
+            output
+            halt
+        ",
+            config,
+        );
+        assert_eq!(res, Ok(()));
+        assert_eq!(output, vec![u64::MAX as i128, u64::MAX as i128 - 1]);
+
+        let config = MemoryConfig {
+            len: 4,
+            cell: Cell::Bits {
+                bits: 4,
+                signed: false,
+            },
+            ..Default::default()
+        };
+        let (res, _) = run_with_config("add 15\nadd 1\nhalt", config);
+        assert_eq!(
+            res,
+            Err(ProcessorError::Memory {
+                source: MemoryError::AddOverflow { before: 15, add: 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn reject_invalid_instructions() {
+        let instructions = InstructionList(vec![Instruction::Jump { target: 5 }]);
+        assert!(matches!(
+            Processor::new(instructions),
+            Err(ProcessorError::Invalid {
+                source: VerifyError::InvalidJumpTarget { addr: 0, target: 5 }
+            })
+        ));
+    }
+}
@@ -2,7 +2,7 @@ use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::rc::Rc;
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub input: Input,
     pub output: Output,
@@ -17,17 +17,17 @@ impl Default for Config {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Input {
     Null,
     Standard,
     Vec(Rc<RefCell<VecDeque<i32>>>),
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Output {
     Null,
     CharStandard,
     IntStandard,
-    Vec(Rc<RefCell<VecDeque<i32>>>),
+    Vec(Rc<RefCell<VecDeque<i128>>>),
 }
@@ -6,6 +6,9 @@ use std::io::{stdin, BufReader, Read, Stdin};
 use std::rc::Rc;
 
 use config::{Config, Input, Output};
+use num_bigint::BigInt;
+
+use super::memory::clamp;
 
 pub const EOF: i32 = -1;
 
@@ -62,43 +65,54 @@ impl InStream for VecInStream {
 }
 
 pub trait OutStream {
-    fn write(&mut self, content: i32);
+    fn write(&mut self, content: i128);
+
+    /// Write an arbitrary-precision integer, which is clamped to `i128` unless
+    /// the stream can show the full value.
+    fn write_big(&mut self, content: &BigInt) {
+        self.write(clamp(content));
+    }
 }
 
 pub struct NullOutStream;
 
 impl OutStream for NullOutStream {
-    fn write(&mut self, _content: i32) {}
+    fn write(&mut self, _content: i128) {}
 }
 
 pub struct CharStandardOutStream;
 
 impl OutStream for CharStandardOutStream {
-    fn write(&mut self, content: i32) {
-        print!("{}", char::from_u32(content as u32).unwrap_or('�'));
+    fn write(&mut self, content: i128) {
+        let c = u32::try_from(content).ok().and_then(char::from_u32);
+        print!("{}", c.unwrap_or('�'));
     }
 }
 
 pub struct IntStandardOutStream;
 
 impl OutStream for IntStandardOutStream {
-    fn write(&mut self, content: i32) {
+    fn write(&mut self, content: i128) {
+        print!("{content} ");
+    }
+
+    fn write_big(&mut self, content: &BigInt) {
         print!("{content} ");
     }
 }
 
 pub struct VecOutStream {
-    output: Rc<RefCell<VecDeque<i32>>>,
+    output: Rc<RefCell<VecDeque<i128>>>,
 }
 
 impl VecOutStream {
-    pub fn new(output: Rc<RefCell<VecDeque<i32>>>) -> Self {
+    pub fn new(output: Rc<RefCell<VecDeque<i128>>>) -> Self {
         Self { output }
     }
 }
 
 impl OutStream for VecOutStream {
-    fn write(&mut self, content: i32) {
+    fn write(&mut self, content: i128) {
         self.output.borrow_mut().push_back(content);
     }
 }
@@ -15,5 +15,9 @@
     clippy::comparison_chain
 )]
 
+pub mod bytecode;
+pub mod codegen;
 pub mod compiler;
+#[cfg(test)]
+mod equivalence;
 pub mod execution;